@@ -0,0 +1,57 @@
+create table ledger_transactions (
+    id              serial PRIMARY KEY,
+    organization_id integer NOT NULL REFERENCES "organizations" ("id"),
+    kind            text NOT NULL CHECK (kind in ('sale', 'pledge', 'payment', 'refund')),
+    memo            text NOT NULL DEFAULT '',
+    item_id         integer REFERENCES "items" ("id"),
+    bid_id          integer REFERENCES "bids" ("id"),
+    date_created    timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified   timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on ledger_transactions (organization_id);
+
+
+create table ledger_entries (
+    id              serial PRIMARY KEY,
+    transaction_id  integer NOT NULL REFERENCES "ledger_transactions" ("id") ON DELETE CASCADE,
+    account         text NOT NULL CHECK (account in ('bidder_receivable', 'item_revenue', 'donations',
+                                                     'gateway_clearing', 'refunds')),
+    bidder_id       integer REFERENCES "bidders" ("id"),
+    debit           bigint NOT NULL DEFAULT 0 CHECK (debit >= 0),
+    credit          bigint NOT NULL DEFAULT 0 CHECK (credit >= 0),
+    date_created    timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified   timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK ((debit = 0) <> (credit = 0))
+);
+create index on ledger_entries (transaction_id);
+create index on ledger_entries (account, bidder_id);
+
+
+-- every ledger transaction must balance by the time its
+-- database transaction commits
+create function check_ledger_balanced() returns trigger as $$
+declare
+    unbalanced integer;
+begin
+    select sum(debit) - sum(credit) into unbalanced
+    from ledger_entries where transaction_id = NEW.transaction_id;
+    IF unbalanced <> 0 THEN
+        RAISE EXCEPTION 'ledger transaction % is unbalanced by %', NEW.transaction_id, unbalanced;
+    END IF;
+    return NULL;
+end;
+$$ LANGUAGE plpgsql;
+
+create constraint trigger ledger_balanced_trigger
+after insert or update on ledger_entries
+deferrable initially deferred
+for each row execute procedure check_ledger_balanced();
+
+
+create trigger date_modified_trigger
+before update on ledger_transactions
+for each row execute procedure update_date_modified();
+
+create trigger date_modified_trigger
+before update on ledger_entries
+for each row execute procedure update_date_modified();
@@ -0,0 +1,4 @@
+drop table payment_holds;
+alter table bidders drop column spending_limit;
+alter table organizations drop column preauth_amount;
+alter table organizations drop column bidder_spending_limit;
@@ -0,0 +1,21 @@
+alter table organizations add column bidder_spending_limit bigint CHECK (bidder_spending_limit >= 0);
+alter table organizations add column preauth_amount bigint CHECK (preauth_amount > 0);
+alter table bidders add column spending_limit bigint CHECK (spending_limit >= 0);
+
+
+create table payment_holds (
+    id              serial PRIMARY KEY,
+    bidder_id       integer NOT NULL REFERENCES "bidders" ("id") ON DELETE CASCADE,
+    payment_info_id integer NOT NULL REFERENCES "payment_information" ("id"),
+    amount          bigint NOT NULL CHECK (amount > 0),
+    gateway         text NOT NULL,
+    gateway_ref     text NOT NULL,
+    status          text NOT NULL DEFAULT 'authorized' CHECK (status in ('authorized', 'captured', 'released')),
+    date_created    timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified   timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on payment_holds (bidder_id);
+
+create trigger date_modified_trigger
+before update on payment_holds
+for each row execute procedure update_date_modified();
@@ -0,0 +1,3 @@
+alter table bidders drop column terms_accepted_at;
+alter table bidders drop column paddle;
+alter table organizations drop column next_paddle;
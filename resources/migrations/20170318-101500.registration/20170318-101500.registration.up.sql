@@ -0,0 +1,4 @@
+alter table organizations add column next_paddle integer NOT NULL DEFAULT 1 CHECK (next_paddle > 0);
+alter table bidders add column paddle integer CHECK (paddle > 0);
+alter table bidders add column terms_accepted_at timestamp WITH TIME ZONE;
+create unique index on bidders (organization_id, paddle);
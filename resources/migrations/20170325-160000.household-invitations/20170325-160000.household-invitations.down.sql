@@ -0,0 +1,2 @@
+drop index profiles_one_primary_per_bidder;
+drop table invitations;
@@ -0,0 +1,20 @@
+create table invitations (
+    id                 serial PRIMARY KEY,
+    bidder_id          integer NOT NULL REFERENCES "bidders" ("id") ON DELETE CASCADE,
+    inviter_profile_id integer NOT NULL REFERENCES "profiles" ("id") ON DELETE CASCADE,
+    email              text NOT NULL,
+    token_hash         bytea UNIQUE NOT NULL,
+    expires_at         timestamp WITH TIME ZONE NOT NULL,
+    accepted_at        timestamp WITH TIME ZONE,
+    date_created       timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified      timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on invitations (bidder_id);
+
+create trigger date_modified_trigger
+before update on invitations
+for each row execute procedure update_date_modified();
+
+
+-- a bidder can have at most one primary profile
+create unique index profiles_one_primary_per_bidder on profiles (bidder_id) where is_primary;
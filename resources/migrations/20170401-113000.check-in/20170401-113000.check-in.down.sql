@@ -0,0 +1,4 @@
+drop index profiles_lower_name_idx;
+drop index users_lower_email_idx;
+alter table profiles drop column checked_in_by;
+alter table profiles drop column checked_in_at;
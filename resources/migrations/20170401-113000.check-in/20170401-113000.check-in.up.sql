@@ -0,0 +1,4 @@
+alter table profiles add column checked_in_at timestamp WITH TIME ZONE;
+alter table profiles add column checked_in_by integer REFERENCES "users" ("id") ON DELETE SET NULL;
+create index users_lower_email_idx on users ((lower(email)));
+create index profiles_lower_name_idx on profiles ((lower(name)) text_pattern_ops);
@@ -0,0 +1,3 @@
+alter table bids drop column written_at;
+alter table bids drop column entered_by;
+alter table bids drop column source;
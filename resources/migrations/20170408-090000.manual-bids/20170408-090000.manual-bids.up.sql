@@ -0,0 +1,4 @@
+alter table bids add column source text NOT NULL DEFAULT 'online' CHECK (source in ('online', 'manual'));
+alter table bids add column entered_by integer REFERENCES "users" ("id") ON DELETE SET NULL;
+alter table bids add column written_at timestamp WITH TIME ZONE;
+alter table bids add CHECK (source = 'online' or written_at is not null);
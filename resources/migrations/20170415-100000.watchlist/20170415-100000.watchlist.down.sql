@@ -0,0 +1,2 @@
+drop table watchlist;
+alter table items drop column closes_at;
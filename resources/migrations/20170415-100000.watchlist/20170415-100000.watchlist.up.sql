@@ -0,0 +1,16 @@
+alter table items add column closes_at timestamp WITH TIME ZONE;
+
+create table watchlist (
+    id                  serial PRIMARY KEY,
+    profile_id          integer NOT NULL REFERENCES "profiles" ("id") ON DELETE CASCADE,
+    item_id             integer NOT NULL REFERENCES "items" ("id") ON DELETE CASCADE,
+    closing_notified_at timestamp WITH TIME ZONE,
+    date_created        timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified       timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (profile_id, item_id)
+);
+create index on watchlist (item_id);
+
+create trigger date_modified_trigger
+before update on watchlist
+for each row execute procedure update_date_modified();
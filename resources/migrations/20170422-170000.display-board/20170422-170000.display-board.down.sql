@@ -0,0 +1 @@
+alter table organizations drop column display_token_hash;
@@ -0,0 +1,2 @@
+-- sha256 of the token handed out in display board urls
+alter table organizations add column display_token_hash bytea;
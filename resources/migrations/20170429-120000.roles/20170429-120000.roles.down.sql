@@ -0,0 +1,8 @@
+alter table users add column level_ integer NOT NULL DEFAULT 0 CHECK (level_ >= 0);
+update users set level_ = 5
+    where id in (select user_id from user_roles where role in ('org_admin', 'volunteer'));
+update users set level_ = 10
+    where id in (select user_id from user_roles where role = 'super_admin');
+alter table users alter column level_ drop default;
+
+drop table user_roles;
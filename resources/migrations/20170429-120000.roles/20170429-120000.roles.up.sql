@@ -0,0 +1,29 @@
+-- roles granted to a user, either globally (organization_id is null)
+-- or within a single organization
+create table user_roles (
+    id              serial PRIMARY KEY,
+    user_id         integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    role            text NOT NULL CHECK (role in ('super_admin', 'org_admin', 'volunteer', 'bidder')),
+    organization_id integer REFERENCES "organizations" ("id") ON DELETE CASCADE,
+    date_created    timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified   timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (role <> 'super_admin' or organization_id is null)
+);
+create unique index user_roles_unique on user_roles (user_id, role, coalesce(organization_id, 0));
+create index on user_roles (user_id);
+
+create trigger date_modified_trigger
+before update on user_roles
+for each row execute procedure update_date_modified();
+
+
+-- carry over the old numeric levels: 10+ was admin, 5+ was volunteer
+insert into user_roles (user_id, role)
+    select id, 'super_admin' from users where level_ >= 10;
+insert into user_roles (user_id, role)
+    select id, 'volunteer' from users where level_ >= 5 and level_ < 10;
+insert into user_roles (user_id, role, organization_id)
+    select distinct p.user_id, 'bidder', b.organization_id
+    from profiles p join bidders b on b.id = p.bidder_id;
+
+alter table users drop column level_;
@@ -0,0 +1,19 @@
+alter table users drop constraint users_auth_id_fkey;
+alter table users add constraint users_auth_id_fkey
+    foreign key (auth_id) references auth (id) ON DELETE CASCADE;
+alter table bidders drop constraint bidders_organization_id_fkey;
+alter table bidders add constraint bidders_organization_id_fkey
+    foreign key (organization_id) references organizations (id) ON DELETE SET NULL;
+alter table items drop constraint items_organization_id_fkey;
+alter table items add constraint items_organization_id_fkey
+    foreign key (organization_id) references organizations (id) ON DELETE SET NULL;
+alter table bids drop constraint bids_bidder_id_fkey;
+alter table bids add constraint bids_bidder_id_fkey
+    foreign key (bidder_id) references bidders (id) ON DELETE SET NULL;
+alter table bids drop constraint bids_item_id_fkey;
+alter table bids add constraint bids_item_id_fkey
+    foreign key (item_id) references items (id) ON DELETE SET NULL;
+
+alter table users drop column deleted_at;
+alter table items drop column deleted_at;
+alter table organizations drop column deleted_at;
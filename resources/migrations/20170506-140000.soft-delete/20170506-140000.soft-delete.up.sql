@@ -0,0 +1,23 @@
+-- soft deletion: rows are hidden by setting deleted_at and can be restored
+alter table organizations add column deleted_at timestamp WITH TIME ZONE;
+alter table items add column deleted_at timestamp WITH TIME ZONE;
+alter table users add column deleted_at timestamp WITH TIME ZONE;
+
+
+-- hard deletes must never orphan bid history or silently take users with them.
+-- these were SET NULL on NOT NULL columns, which could only ever fail
+alter table bids drop constraint bids_item_id_fkey;
+alter table bids add constraint bids_item_id_fkey
+    foreign key (item_id) references items (id) ON DELETE RESTRICT;
+alter table bids drop constraint bids_bidder_id_fkey;
+alter table bids add constraint bids_bidder_id_fkey
+    foreign key (bidder_id) references bidders (id) ON DELETE RESTRICT;
+alter table items drop constraint items_organization_id_fkey;
+alter table items add constraint items_organization_id_fkey
+    foreign key (organization_id) references organizations (id) ON DELETE RESTRICT;
+alter table bidders drop constraint bidders_organization_id_fkey;
+alter table bidders add constraint bidders_organization_id_fkey
+    foreign key (organization_id) references organizations (id) ON DELETE RESTRICT;
+alter table users drop constraint users_auth_id_fkey;
+alter table users add constraint users_auth_id_fkey
+    foreign key (auth_id) references auth (id) ON DELETE RESTRICT;
@@ -0,0 +1,2 @@
+drop table email_verifications;
+alter table users drop column verified_at;
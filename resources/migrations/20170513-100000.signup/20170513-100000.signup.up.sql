@@ -0,0 +1,19 @@
+-- accounts created by admins or at the check-in desk are trusted
+alter table users add column verified_at timestamp WITH TIME ZONE;
+update users set verified_at = date_created;
+
+
+create table email_verifications (
+    id            serial PRIMARY KEY,
+    user_id       integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    token_hash    bytea UNIQUE NOT NULL,
+    expires_at    timestamp WITH TIME ZONE NOT NULL,
+    verified_at   timestamp WITH TIME ZONE,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on email_verifications (user_id);
+
+create trigger date_modified_trigger
+before update on email_verifications
+for each row execute procedure update_date_modified();
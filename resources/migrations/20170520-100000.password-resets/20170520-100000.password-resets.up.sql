@@ -0,0 +1,14 @@
+create table password_resets (
+    id            serial PRIMARY KEY,
+    user_id       integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    token_hash    bytea UNIQUE NOT NULL,
+    expires_at    timestamp WITH TIME ZONE NOT NULL,
+    used_at       timestamp WITH TIME ZONE,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on password_resets (user_id);
+
+create trigger date_modified_trigger
+before update on password_resets
+for each row execute procedure update_date_modified();
@@ -0,0 +1,2 @@
+alter table email_verifications drop column new_email;
+drop table audit_log;
@@ -0,0 +1,19 @@
+-- security relevant changes to user accounts
+create table audit_log (
+    id            serial PRIMARY KEY,
+    user_id       integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    action        text NOT NULL CHECK (action in ('password_changed', 'password_reset',
+                                                  'email_change_requested', 'email_changed')),
+    detail        jsonb,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on audit_log (user_id);
+
+create trigger date_modified_trigger
+before update on audit_log
+for each row execute procedure update_date_modified();
+
+
+-- verifying a change of address replaces the user's email with new_email
+alter table email_verifications add column new_email text;
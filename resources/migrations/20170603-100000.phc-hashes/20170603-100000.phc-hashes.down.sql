@@ -0,0 +1,11 @@
+alter table auth add column salt bytea;
+alter table auth add column password bytea;
+update auth set salt = decode(split_part(hash, '$', 4) || '==', 'base64'),
+                password = decode(split_part(hash, '$', 5), 'base64')
+    where hash like '$bcrypt$cost=10$%';
+-- newer hashes can't be carried back; those users will need a password reset
+update auth set salt = decode(md5(random()::text), 'hex'), password = '\x00'::bytea
+    where salt is null;
+alter table auth alter column salt set not null;
+alter table auth alter column password set not null;
+alter table auth drop column hash;
@@ -0,0 +1,11 @@
+-- password hashes become self-describing PHC strings (unpadded base64), e.g.
+--   $bcrypt$cost=10$<salt>$<hash>
+--   $scrypt$ln=15,r=8,p=1$<salt>$<hash>
+-- existing raw bcrypt rows are carried over as-is and upgraded to the
+-- current algorithm the next time their user logs in
+alter table auth add column hash text;
+update auth set hash = '$bcrypt$cost=10$' || rtrim(encode(salt, 'base64'), '=')
+                       || '$' || rtrim(encode(password, 'base64'), '=');
+alter table auth alter column hash set not null;
+alter table auth drop column salt;
+alter table auth drop column password;
@@ -0,0 +1,15 @@
+create table login_attempts (
+    id            serial PRIMARY KEY,
+    email         text NOT NULL,
+    ip            text NOT NULL,
+    succeeded     boolean NOT NULL,
+    cleared_at    timestamp WITH TIME ZONE,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on login_attempts (email, date_created);
+create index on login_attempts (ip, date_created);
+
+create trigger date_modified_trigger
+before update on login_attempts
+for each row execute procedure update_date_modified();
@@ -0,0 +1,3 @@
+drop table pending_logins;
+drop table recovery_codes;
+drop table totp_secrets;
@@ -0,0 +1,48 @@
+-- totp secrets have to be kept as-is to compute codes; enrollment is
+-- pending until the first code is confirmed
+create table totp_secrets (
+    id             serial PRIMARY KEY,
+    user_id        integer UNIQUE NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    secret         bytea NOT NULL,
+    confirmed_at   timestamp WITH TIME ZONE,
+    last_used_step bigint,
+    date_created   timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+create trigger date_modified_trigger
+before update on totp_secrets
+for each row execute procedure update_date_modified();
+
+
+create table recovery_codes (
+    id            serial PRIMARY KEY,
+    user_id       integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    code_hash     bytea UNIQUE NOT NULL,
+    used_at       timestamp WITH TIME ZONE,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on recovery_codes (user_id);
+
+create trigger date_modified_trigger
+before update on recovery_codes
+for each row execute procedure update_date_modified();
+
+
+-- logins waiting on a second factor after the password checked out
+create table pending_logins (
+    id            serial PRIMARY KEY,
+    user_id       integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    token_hash    bytea UNIQUE NOT NULL,
+    expires_at    timestamp WITH TIME ZONE NOT NULL,
+    attempts      integer NOT NULL DEFAULT 0,
+    used_at       timestamp WITH TIME ZONE,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on pending_logins (user_id);
+
+create trigger date_modified_trigger
+before update on pending_logins
+for each row execute procedure update_date_modified();
@@ -0,0 +1,16 @@
+-- sessions for the `postgres` session backend, see `sessions::PgBackend`
+create table sessions (
+    id            serial PRIMARY KEY,
+    token_hash    bytea UNIQUE NOT NULL,
+    user_id       integer REFERENCES "users" ("id") ON DELETE CASCADE,
+    data          text NOT NULL DEFAULT '{}',
+    expires_at    timestamp WITH TIME ZONE NOT NULL,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on sessions (expires_at);
+create index on sessions (user_id);
+
+create trigger date_modified_trigger
+before update on sessions
+for each row execute procedure update_date_modified();
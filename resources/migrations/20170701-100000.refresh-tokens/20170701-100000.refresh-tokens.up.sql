@@ -0,0 +1,17 @@
+-- refresh tokens for the `jwt` session backend, see `tokens`. Each row is
+-- one login; its token is replaced every time it's used
+create table refresh_tokens (
+    id            serial PRIMARY KEY,
+    user_id       integer NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    token_hash    bytea UNIQUE NOT NULL,
+    expires_at    timestamp WITH TIME ZONE NOT NULL,
+    revoked_at    timestamp WITH TIME ZONE,
+    date_created  timestamp WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    date_modified timestamp WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+create index on refresh_tokens (user_id);
+create index on refresh_tokens (expires_at);
+
+create trigger date_modified_trigger
+before update on refresh_tokens
+for each row execute procedure update_date_modified();
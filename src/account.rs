@@ -0,0 +1,89 @@
+//! Account
+//!
+//! Logged-in users changing their own credentials. Both changes require
+//! the current password and are recorded in the audit log; callers should
+//! end the user's other sessions afterwards.
+//!
+use postgres::Connection;
+
+use models::{Auth, User};
+use mailer::{self, Mailer};
+use validation::Validator;
+use audit::{self, Action};
+use signup;
+use auth;
+use sql;
+use errors::*;
+
+
+/// Check a password against the user's stored hash
+pub fn check_password(conn: &Connection, user: &User, password: &str) -> Result<bool> {
+    let user_auth = Auth::get(conn, &user.auth_id).ok_or("auth not found")?;
+    Ok(auth::verify_password(password, &user_auth.hash).unwrap_or(false))
+}
+
+
+/// Record an error on `current_password` unless it's the user's password
+fn require_password(conn: &Connection, v: &mut Validator, user: &User, password: &str) -> Result<()> {
+    if !check_password(conn, user, password)? {
+        v.error("current_password", "is incorrect");
+    }
+    Ok(())
+}
+
+
+/// Replace the user's password, hashed with a fresh salt and the current parameters
+pub fn set_password(conn: &Connection, user: &User, password: &str) -> Result<()> {
+    let hash = auth::hash_password(password)?;
+    conn.execute("update auth set hash=$1 where id=$2", &[&hash, &user.auth_id])
+        .chain_err(|| "Error updating password")?;
+    Ok(())
+}
+
+
+/// Change the user's password. Should be run in `sql::transaction`.
+pub fn change_password(conn: &Connection, user: &User, current: &str, new: &str) -> Result<()> {
+    let mut v = Validator::new();
+    require_password(conn, &mut v, user, current)?;
+    v.password("new_password", new);
+    v.finish()?;
+    set_password(conn, user, new)?;
+    audit::record(conn, user.id, Action::PasswordChanged, None)?;
+    Ok(())
+}
+
+
+/// Start a change of the user's email address. The new address gets a
+/// verification link (see `signup::verify`) and takes over once it's
+/// followed; the current address is told about the request.
+/// Should be run in `sql::transaction`.
+pub fn request_email_change(conn: &Connection, mailer: &Mailer, user: &User,
+                            current_password: &str, new_email: &str) -> Result<()> {
+    let mut v = Validator::new();
+    require_password(conn, &mut v, user, current_password)?;
+    let new_email = new_email.trim().to_lowercase();
+    if !new_email.contains('@') {
+        v.error("email", "is not a valid email address");
+    } else if new_email == user.email {
+        v.error("email", "is already your email address");
+    } else if sql::select_user_by_email(conn, &new_email).is_some() {
+        v.error("email", "is already registered");
+    }
+    v.finish()?;
+
+    let (verification, token) = signup::new_verification(conn, user.id, Some(new_email.clone()))?;
+    let detail = audit::detail(&[("from", user.email.as_str()), ("to", new_email.as_str())]);
+    audit::record(conn, user.id, Action::EmailChangeRequested, Some(detail))?;
+
+    let body = format!("Please confirm your new email address:\n\n\
+                        {}/#/verify?token={}\n\n\
+                        This link expires on {}.",
+                       mailer::public_url(), token, verification.expires_at.format("%Y-%m-%d %H:%M UTC"));
+    mailer.send(&new_email, "Confirm your new email address", &body)
+        .chain_err(|| "Error sending verification email")?;
+    let notice = format!("Someone asked to change your account's email address to {}. \
+                          If it wasn't you, reset your password right away:\n\n{}/#/password/forgot",
+                         new_email, mailer::public_url());
+    mailer.send(&user.email, "Your email address is being changed", &notice)
+        .chain_err(|| "Error sending change notice")
+}
@@ -0,0 +1,105 @@
+//! Activity
+//!
+//! A bidder's personal summary: items they're winning, have been outbid on
+//! and have won, their goal pledges, and what they owe.
+//!
+use chrono;
+use postgres::Connection;
+
+use models::Item;
+use bidding;
+use ledger;
+use sql;
+use errors::*;
+
+
+#[derive(Debug)]
+/// An item the bidder has bid on, with their best bid and the current high bid
+pub struct ItemActivity {
+    pub item: Item,
+    pub my_bid: i64,
+    pub high_bid: i64,
+}
+
+
+#[derive(Debug)]
+pub struct Pledge {
+    pub bid_id: i32,
+    pub item_id: i32,
+    pub title: String,
+    pub amount: i64,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+}
+
+
+#[derive(Debug)]
+pub struct Activity {
+    pub bidder_id: i32,
+    pub winning: Vec<ItemActivity>,
+    pub outbid: Vec<ItemActivity>,
+    pub won: Vec<ItemActivity>,
+    pub pledges: Vec<Pledge>,
+    /// Outstanding ledger balance: awarded items and pledges less payments
+    pub total_owed: i64,
+    /// What the bidder would owe if bidding closed now
+    pub winning_total: i64,
+}
+
+
+/// The bidder's highest bid on an item
+fn best_bid(conn: &Connection, bidder_id: i32, item_id: i32) -> Result<i64> {
+    let qs = "select coalesce(max(amount), 0)::bigint from bids where bidder_id=$1 and item_id=$2";
+    let rows = conn.query(qs, &[&bidder_id, &item_id]).chain_err(|| "Error selecting best bid")?;
+    Ok(rows.iter().next().map(|row| row.get(0)).unwrap_or(0))
+}
+
+
+/// Gather a bidder's activity
+pub fn for_bidder(conn: &Connection, bidder_id: i32) -> Result<Activity> {
+    let qs = "select i.* from items i where not i.is_goal and i.deleted_at is null \
+              and exists (select 1 from bids where item_id=i.id and bidder_id=$1) \
+              order by i.closes_at nulls last, i.id";
+    let items = query_coll!(conn.query(qs, &[&bidder_id]), Item);
+    let mut winning = vec![];
+    let mut outbid = vec![];
+    let mut won = vec![];
+    for item in items.into_iter() {
+        let high = sql::select_high_bid(conn, item.id).ok_or("item has no bids")?;
+        let activity = ItemActivity {
+            my_bid: best_bid(conn, bidder_id, item.id)?,
+            high_bid: high.amount,
+            item: item,
+        };
+        let owner = activity.item.owning_bidder_id;
+        match owner {
+            Some(owner) if owner == bidder_id => won.push(activity),
+            Some(_) => (),
+            None if high.bidder_id == bidder_id => winning.push(activity),
+            None => outbid.push(activity),
+        }
+    }
+
+    let qs = "select b.id, i.id, i.title, b.amount, b.date_created from bids b \
+              join items i on i.id = b.item_id \
+              where b.bidder_id=$1 and i.is_goal and i.deleted_at is null order by b.date_created";
+    let rows = conn.query(qs, &[&bidder_id]).chain_err(|| "Error selecting pledges")?;
+    let pledges = rows.iter().map(|row| {
+        Pledge {
+            bid_id: row.get(0),
+            item_id: row.get(1),
+            title: row.get(2),
+            amount: row.get(3),
+            date_created: row.get(4),
+        }
+    }).collect();
+
+    Ok(Activity {
+        bidder_id: bidder_id,
+        winning: winning,
+        outbid: outbid,
+        won: won,
+        pledges: pledges,
+        total_owed: ledger::bidder_balance(conn, bidder_id)?,
+        winning_total: bidding::winning_total(conn, bidder_id, None)?,
+    })
+}
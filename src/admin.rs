@@ -0,0 +1,427 @@
+//! Admin
+//!
+//! Create & update of organizations, items, bidders and profiles from
+//! admin api input. Every input field is optional so the same input
+//! serves as a full create or a partial update; only fields present in
+//! the input are changed. On create, missing required fields are
+//! reported with the other field errors.
+//!
+//! Monetary fields are strings in the organization's currency (e.g.
+//! "$1,250.00"); a blank string clears optional amounts and times.
+//!
+use postgres::Connection;
+use rustc_serialize::json::{self, Json};
+
+use models::{self, Organization, Item, Bidder, Profile};
+use models::{OrgChanges, ItemChanges, BidderChanges, ProfileChanges};
+use money::Currency;
+use validation::{Validator, FieldError};
+use sql;
+use errors::*;
+
+
+const MAX_NAME_LEN: usize = 255;
+
+
+#[derive(Debug)]
+pub struct OrgInput {
+    pub name: Option<String>,
+    pub extra: Option<Json>,
+    pub bidder_spending_limit: Option<String>,
+    pub preauth_amount: Option<String>,
+}
+
+
+#[derive(Debug)]
+pub struct ItemInput {
+    pub organization_id: Option<i32>,
+    pub is_goal: Option<bool>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub value: Option<String>,
+    pub starting: Option<String>,
+    pub min_bid: Option<String>,
+    pub closes_at: Option<String>,
+}
+
+
+#[derive(Debug)]
+pub struct BidderInput {
+    pub organization_id: Option<i32>,
+    pub id_name: Option<String>,
+    pub spending_limit: Option<String>,
+    pub paddle: Option<i32>,
+}
+
+
+#[derive(Debug)]
+pub struct ProfileInput {
+    pub user_id: Option<i32>,
+    pub bidder_id: Option<i32>,
+    pub payment_info_id: Option<i32>,
+    pub is_primary: Option<bool>,
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub extra: Option<Json>,
+}
+
+
+/// Reads optional fields out of a json object body, recording a field
+/// error for each value of the wrong type. `null` reads as absent.
+struct JsonFields {
+    obj: json::Object,
+    v: Validator,
+}
+impl JsonFields {
+    fn parse(body: &str) -> Result<JsonFields> {
+        let msg = match Json::from_str(body) {
+            Ok(Json::Object(obj)) => return Ok(JsonFields { obj: obj, v: Validator::new() }),
+            Ok(_) => "must be a json object".to_string(),
+            Err(err) => format!("is not valid json: {}", err),
+        };
+        bail!(ErrorKind::InvalidFields(vec![FieldError { field: "body".into(), msg: msg }]))
+    }
+
+    fn value(&self, field: &str) -> Option<Json> {
+        match self.obj.get(field) {
+            Some(&Json::Null) | None => None,
+            Some(value) => Some(value.clone()),
+        }
+    }
+
+    fn string(&mut self, field: &str) -> Option<String> {
+        match self.value(field) {
+            Some(Json::String(s)) => Some(s),
+            Some(_) => { self.v.error(field, "must be a string"); None }
+            None => None,
+        }
+    }
+
+    fn int(&mut self, field: &str) -> Option<i32> {
+        let n = match self.value(field) {
+            Some(Json::I64(n)) => Some(n),
+            Some(Json::U64(n)) if n <= i32::max_value() as u64 => Some(n as i64),
+            Some(_) => None,
+            None => return None,
+        };
+        match n {
+            Some(n) if n >= i32::min_value() as i64 && n <= i32::max_value() as i64 => Some(n as i32),
+            _ => { self.v.error(field, "must be a whole number"); None }
+        }
+    }
+
+    fn boolean(&mut self, field: &str) -> Option<bool> {
+        match self.value(field) {
+            Some(Json::Boolean(b)) => Some(b),
+            Some(_) => { self.v.error(field, "must be true or false"); None }
+            None => None,
+        }
+    }
+
+    fn finish<T>(self, input: T) -> Result<T> {
+        self.v.finish()?;
+        Ok(input)
+    }
+}
+
+
+impl OrgInput {
+    pub fn from_json(body: &str) -> Result<OrgInput> {
+        let mut f = JsonFields::parse(body)?;
+        let input = OrgInput {
+            name: f.string("name"),
+            extra: f.value("extra"),
+            bidder_spending_limit: f.string("bidder_spending_limit"),
+            preauth_amount: f.string("preauth_amount"),
+        };
+        f.finish(input)
+    }
+}
+
+impl ItemInput {
+    pub fn from_json(body: &str) -> Result<ItemInput> {
+        let mut f = JsonFields::parse(body)?;
+        let input = ItemInput {
+            organization_id: f.int("organization_id"),
+            is_goal: f.boolean("is_goal"),
+            title: f.string("title"),
+            description: f.string("description"),
+            value: f.string("value"),
+            starting: f.string("starting"),
+            min_bid: f.string("min_bid"),
+            closes_at: f.string("closes_at"),
+        };
+        f.finish(input)
+    }
+}
+
+impl BidderInput {
+    pub fn from_json(body: &str) -> Result<BidderInput> {
+        let mut f = JsonFields::parse(body)?;
+        let input = BidderInput {
+            organization_id: f.int("organization_id"),
+            id_name: f.string("id_name"),
+            spending_limit: f.string("spending_limit"),
+            paddle: f.int("paddle"),
+        };
+        f.finish(input)
+    }
+}
+
+impl ProfileInput {
+    pub fn from_json(body: &str) -> Result<ProfileInput> {
+        let mut f = JsonFields::parse(body)?;
+        let input = ProfileInput {
+            user_id: f.int("user_id"),
+            bidder_id: f.int("bidder_id"),
+            payment_info_id: f.int("payment_info_id"),
+            is_primary: f.boolean("is_primary"),
+            name: f.string("name"),
+            phone: f.string("phone"),
+            extra: f.value("extra"),
+        };
+        f.finish(input)
+    }
+}
+
+
+/// Currency named by `extra.currency`, recording an error if it's unknown
+fn extra_currency(v: &mut Validator, extra: &Option<Json>) -> Currency {
+    match Organization::currency_from_extra(extra) {
+        Ok(currency) => currency,
+        Err(err) => {
+            v.error("extra.currency", &err.to_string());
+            Currency::default()
+        }
+    }
+}
+
+
+/// Organization's currency, recording an error if it doesn't exist or
+/// its currency is unknown
+fn org_currency(conn: &Connection, v: &mut Validator, field: &str, org_id: i32) -> Currency {
+    match sql::get_organization(conn, org_id) {
+        Some(org) => match org.currency() {
+            Ok(currency) => currency,
+            Err(err) => {
+                v.error(field, &err.to_string());
+                Currency::default()
+            }
+        },
+        None => {
+            v.error(field, "organization not found");
+            Currency::default()
+        }
+    }
+}
+
+
+/// Validate org input against the current `org`
+fn org_changes(v: &mut Validator, org: &Organization, input: OrgInput) -> OrgChanges {
+    let currency = extra_currency(v, &input.extra.clone().or(org.extra.clone()));
+    OrgChanges {
+        name: input.name.and_then(|name| v.text("name", &name, MAX_NAME_LEN)),
+        extra: input.extra.map(Some),
+        bidder_spending_limit: input.bidder_spending_limit
+            .and_then(|limit| v.optional_money("bidder_spending_limit", &limit, currency)),
+        preauth_amount: input.preauth_amount
+            .and_then(|amount| v.optional_money("preauth_amount", &amount, currency)),
+    }
+}
+
+
+pub fn create_org(conn: &Connection, input: OrgInput) -> Result<Organization> {
+    let mut v = Validator::new();
+    v.required("name", input.name.as_ref());
+    let blank = Organization {
+        id: 0, name: String::new(), extra: None,
+        date_created: ::chrono::UTC::now(), date_modified: ::chrono::UTC::now(),
+        bidder_spending_limit: None, preauth_amount: None,
+        next_paddle: 1, display_token_hash: None, deleted_at: None,
+    };
+    let mut changes = org_changes(&mut v, &blank, input);
+    v.finish()?;
+    let name = changes.name.take().unwrap();
+    let extra = changes.extra.take().unwrap_or(None);
+    sql::transaction(conn, |conn| {
+        let created = models::NewOrg::new(&name, &extra).create(conn)?;
+        created.update(conn, changes)
+    })
+}
+
+
+pub fn update_org(conn: &Connection, org: Organization, input: OrgInput) -> Result<Organization> {
+    let mut v = Validator::new();
+    let changes = org_changes(&mut v, &org, input);
+    v.finish()?;
+    org.update(conn, changes)
+}
+
+
+/// Validate item input against the current `item`
+fn item_changes(conn: &Connection, v: &mut Validator, item: &Item, input: ItemInput) -> ItemChanges {
+    let org_id = input.organization_id.unwrap_or(item.organization_id);
+    let currency = org_currency(conn, v, "organization_id", org_id);
+    let money = |v: &mut Validator, field: &str, value: Option<String>| {
+        value.and_then(|value| v.money(field, &value, currency))
+    };
+    ItemChanges {
+        organization_id: input.organization_id,
+        is_goal: input.is_goal,
+        title: input.title.and_then(|title| v.text("title", &title, MAX_NAME_LEN)),
+        description: input.description.map(|d| d.trim().to_string()),
+        value: money(v, "value", input.value),
+        starting: money(v, "starting", input.starting),
+        min_bid: money(v, "min_bid", input.min_bid),
+        closes_at: input.closes_at.and_then(|closes_at| v.optional_time("closes_at", &closes_at)),
+    }
+}
+
+
+pub fn create_item(conn: &Connection, input: ItemInput) -> Result<Item> {
+    let mut v = Validator::new();
+    let org_id = v.required("organization_id", input.organization_id);
+    v.required("title", input.title.as_ref());
+    let blank = Item {
+        id: 0, organization_id: org_id.unwrap_or(0), owning_bidder_id: None, is_goal: false,
+        title: String::new(), description: String::new(), value: 0, starting: 0, min_bid: 0,
+        date_created: ::chrono::UTC::now(), date_modified: ::chrono::UTC::now(), closes_at: None,
+        deleted_at: None,
+    };
+    let changes = if org_id.is_some() { item_changes(conn, &mut v, &blank, input) } else { ItemChanges::default() };
+    v.finish()?;
+    let item = changes.apply_to(blank);
+    models::NewItem {
+        organization_id: item.organization_id, owning_bidder_id: None, is_goal: item.is_goal,
+        title: item.title, description: item.description, value: item.value,
+        starting: item.starting, min_bid: item.min_bid, closes_at: item.closes_at,
+    }.create(conn)
+}
+
+
+pub fn update_item(conn: &Connection, item: Item, input: ItemInput) -> Result<Item> {
+    let mut v = Validator::new();
+    let changes = item_changes(conn, &mut v, &item, input);
+    v.finish()?;
+    item.update(conn, changes)
+}
+
+
+/// Validate bidder input against the current `bidder`
+fn bidder_changes(conn: &Connection, v: &mut Validator, bidder: &Bidder, input: BidderInput) -> BidderChanges {
+    let org_id = input.organization_id.unwrap_or(bidder.organization_id);
+    let currency = org_currency(conn, v, "organization_id", org_id);
+    let paddle = match input.paddle {
+        Some(paddle) if paddle <= 0 => {
+            v.error("paddle", "must be positive");
+            None
+        }
+        Some(paddle) => {
+            let taken = sql::select_bidder_by_paddle(conn, org_id, paddle)
+                .map(|other| other.id != bidder.id)
+                .unwrap_or(false);
+            if taken {
+                v.error("paddle", "is already assigned to another bidder");
+            }
+            Some(Some(paddle))
+        }
+        None => None,
+    };
+    BidderChanges {
+        organization_id: input.organization_id,
+        id_name: input.id_name.and_then(|id_name| v.text("id_name", &id_name, MAX_NAME_LEN)),
+        spending_limit: input.spending_limit
+            .and_then(|limit| v.optional_money("spending_limit", &limit, currency)),
+        paddle: paddle,
+    }
+}
+
+
+pub fn create_bidder(conn: &Connection, input: BidderInput) -> Result<Bidder> {
+    let mut v = Validator::new();
+    let org_id = v.required("organization_id", input.organization_id);
+    v.required("id_name", input.id_name.as_ref());
+    let blank = Bidder {
+        id: 0, organization_id: org_id.unwrap_or(0), id_name: String::new(),
+        date_created: ::chrono::UTC::now(), date_modified: ::chrono::UTC::now(),
+        spending_limit: None, paddle: None, terms_accepted_at: None,
+    };
+    let changes = if org_id.is_some() { bidder_changes(conn, &mut v, &blank, input) } else { BidderChanges::default() };
+    v.finish()?;
+    sql::transaction(conn, |conn| {
+        let created = models::NewBidder::new(org_id.unwrap(), changes.id_name.as_ref().unwrap()).create(conn)?;
+        created.update(conn, BidderChanges { organization_id: None, id_name: None, ..changes })
+    })
+}
+
+
+pub fn update_bidder(conn: &Connection, bidder: Bidder, input: BidderInput) -> Result<Bidder> {
+    let mut v = Validator::new();
+    let changes = bidder_changes(conn, &mut v, &bidder, input);
+    v.finish()?;
+    bidder.update(conn, changes)
+}
+
+
+/// Validate profile input for a profile belonging to `user_id`
+fn profile_changes(conn: &Connection, v: &mut Validator, user_id: i32, input: ProfileInput) -> ProfileChanges {
+    if let Some(bidder_id) = input.bidder_id {
+        if sql::get_bidder(conn, bidder_id).is_none() {
+            v.error("bidder_id", "bidder not found");
+        }
+    }
+    if let Some(payment_info_id) = input.payment_info_id {
+        // cards are owned through the profiles they're attached to
+        if sql::get_payment_info(conn, payment_info_id).is_none() {
+            v.error("payment_info_id", "payment information not found");
+        } else if sql::select_payment_info_user_ids(conn, payment_info_id).iter().any(|&id| id != user_id) {
+            v.error("payment_info_id", "payment information belongs to another user");
+        }
+    }
+    ProfileChanges {
+        bidder_id: input.bidder_id.map(Some),
+        payment_info_id: input.payment_info_id.map(Some),
+        is_primary: input.is_primary,
+        name: input.name.and_then(|name| v.text("name", &name, MAX_NAME_LEN)),
+        phone: input.phone.map(|phone| {
+            let phone = phone.trim();
+            if phone.is_empty() { None } else { Some(phone.to_string()) }
+        }),
+        extra: input.extra.map(Some),
+    }
+}
+
+
+pub fn create_profile(conn: &Connection, input: ProfileInput) -> Result<Profile> {
+    let mut v = Validator::new();
+    let user_id = v.required("user_id", input.user_id);
+    v.required("name", input.name.as_ref());
+    if let Some(user_id) = user_id {
+        if sql::get_user(conn, user_id).is_none() {
+            v.error("user_id", "user not found");
+        } else if sql::select_profile_by_user(conn, &user_id).is_some() {
+            v.error("user_id", "user already has a profile");
+        }
+    }
+    let blank = Profile {
+        id: 0, user_id: user_id.unwrap_or(0), bidder_id: None, payment_info_id: None,
+        is_primary: false, name: String::new(), phone: None, extra: None,
+        date_created: ::chrono::UTC::now(), date_modified: ::chrono::UTC::now(),
+        checked_in_at: None, checked_in_by: None,
+    };
+    let changes = profile_changes(conn, &mut v, blank.user_id, input);
+    v.finish()?;
+    let profile = changes.apply_to(blank);
+    models::NewProfile {
+        user_id: profile.user_id, bidder_id: profile.bidder_id, payment_info_id: profile.payment_info_id,
+        is_primary: profile.is_primary, name: profile.name, phone: profile.phone, extra: profile.extra,
+    }.create(conn)
+}
+
+
+pub fn update_profile(conn: &Connection, profile: Profile, input: ProfileInput) -> Result<Profile> {
+    let mut v = Validator::new();
+    let changes = profile_changes(conn, &mut v, profile.user_id, input);
+    v.finish()?;
+    profile.update(conn, changes)
+}
@@ -0,0 +1,89 @@
+//! Audit
+//!
+//! Record of security relevant changes made to user accounts
+//!
+use std::collections::BTreeMap;
+
+use chrono;
+use postgres::{self, Connection};
+use rustc_serialize::json::Json;
+
+use errors::*;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    PasswordChanged,
+    PasswordReset,
+    EmailChangeRequested,
+    EmailChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    RecoveryCodeUsed,
+    RecoveryCodesReplaced,
+}
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Action::PasswordChanged => "password_changed",
+            Action::PasswordReset => "password_reset",
+            Action::EmailChangeRequested => "email_change_requested",
+            Action::EmailChanged => "email_changed",
+            Action::TwoFactorEnabled => "two_factor_enabled",
+            Action::TwoFactorDisabled => "two_factor_disabled",
+            Action::RecoveryCodeUsed => "recovery_code_used",
+            Action::RecoveryCodesReplaced => "recovery_codes_replaced",
+        }
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct AuditRecord {
+    pub id: i32,
+    pub user_id: i32,
+    pub action: String,
+    pub detail: Option<Json>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl AuditRecord {
+    pub fn from_row(row: postgres::rows::Row) -> AuditRecord {
+        AuditRecord {
+            id: row.get(0),
+            user_id: row.get(1),
+            action: row.get(2),
+            detail: row.get(3),
+            date_created: row.get(4),
+            date_modified: row.get(5),
+        }
+    }
+}
+
+
+/// Json object of string values, for record details
+pub fn detail(pairs: &[(&str, &str)]) -> Json {
+    let mut obj = BTreeMap::new();
+    for &(key, value) in pairs {
+        obj.insert(key.to_string(), Json::String(value.to_string()));
+    }
+    Json::Object(obj)
+}
+
+
+/// Record an action taken on a user's account
+pub fn record(conn: &Connection, user_id: i32, action: Action, detail: Option<Json>) -> Result<AuditRecord> {
+    let qs = "insert into audit_log (user_id, action, detail) values ($1, $2, $3) \
+              returning id, date_created, date_modified";
+    try_insert_to_model!(conn.query(qs, &[&user_id, &action.as_str(), &detail]) ;
+                         AuditRecord ;
+                         id: 0, date_created: 1, date_modified: 2 ;
+                         user_id: user_id, action: action.as_str().to_string(), detail: detail)
+}
+
+
+/// A user's audit records, newest first
+pub fn for_user(conn: &Connection, user_id: i32) -> Vec<AuditRecord> {
+    let qs = "select * from audit_log where user_id=$1 order by date_created desc, id desc";
+    query_coll!(conn.query(qs, &[&user_id]), AuditRecord)
+}
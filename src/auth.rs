@@ -1,9 +1,17 @@
 //! Auth
 //!
-//! Hash and Salt generation
+//! Hash and Salt generation. Passwords are stored as self-describing
+//! hash strings (see `PasswordHash`) so algorithms and costs can change
+//! without breaking existing logins.
 //!
+use std::collections::HashMap;
+
 use rand::{Rng, OsRng};
-use crypto::bcrypt;
+use crypto::{bcrypt, scrypt};
+use crypto::sha2::Sha256;
+use crypto::digest::Digest;
+use rustc_serialize::hex::ToHex;
+use rustc_serialize::base64::{Config, FromBase64, ToBase64, STANDARD};
 use errors::*;
 
 
@@ -21,25 +29,210 @@ pub fn new_salt() -> Result<Vec<u8>> {
 }
 
 
-/// Return a bcrypt salted hash with provided 'string' and 'salt' or error
-pub fn hash(string: &str, salt: &[u8]) -> Result<Vec<u8>> {
-    if salt.len() != 16 || (string.len() == 0 || string.len() > 72) {
-        bail!("Salt or String error");
+/// Longest password (in bytes) bcrypt will hash. Only older hashes use
+/// bcrypt; scrypt takes passwords of any length.
+const BCRYPT_MAX_BYTES: usize = 72;
+
+/// Parameters new password hashes are made with. Stored hashes using
+/// anything else are upgraded on the user's next login.
+pub const CURRENT_PARAMS: Params = Params::Scrypt { log_n: 15, r: 8, p: 1 };
+
+const HASH_SIZE: usize = 24;
+
+
+/// Password hashing algorithms and their cost parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Params {
+    Bcrypt { cost: u32 },
+    Scrypt { log_n: u8, r: u32, p: u32 },
+}
+impl Params {
+    fn name(&self) -> &'static str {
+        match *self {
+            Params::Bcrypt { .. } => "bcrypt",
+            Params::Scrypt { .. } => "scrypt",
+        }
+    }
+
+    fn encode(&self) -> String {
+        match *self {
+            Params::Bcrypt { cost } => format!("cost={}", cost),
+            Params::Scrypt { log_n, r, p } => format!("ln={},r={},p={}", log_n, r, p),
+        }
+    }
+
+    fn decode(name: &str, params: &str) -> Result<Params> {
+        let mut values = HashMap::new();
+        for pair in params.split(',') {
+            let mut kv = pair.splitn(2, '=');
+            match (kv.next(), kv.next().and_then(|v| v.parse::<u32>().ok())) {
+                (Some(k), Some(v)) => { values.insert(k, v); }
+                _ => bail!("invalid hash parameters"),
+            }
+        }
+        let get = |k: &str| values.get(k).cloned().ok_or_else(|| Error::from("missing hash parameter"));
+        Ok(match name {
+            "bcrypt" => Params::Bcrypt { cost: get("cost")? },
+            "scrypt" => {
+                let log_n = get("ln")?;
+                if log_n == 0 || log_n > 31 { bail!("invalid hash parameters"); }
+                Params::Scrypt { log_n: log_n as u8, r: get("r")?, p: get("p")? }
+            }
+            _ => bail!("unknown hash algorithm"),
+        })
+    }
+
+    /// Hash `password` with `salt` using these parameters
+    pub fn derive(&self, password: &str, salt: &[u8]) -> Result<Vec<u8>> {
+        if password.len() == 0 {
+            bail!("Salt or String error");
+        }
+        let mut hashed = vec![0u8; HASH_SIZE];
+        match *self {
+            Params::Bcrypt { cost } => {
+                if salt.len() != 16 || password.len() > BCRYPT_MAX_BYTES || cost < 4 || cost > 31 {
+                    bail!("Salt or String error");
+                }
+                bcrypt::bcrypt(cost, salt, password.as_bytes(), &mut hashed);
+            }
+            Params::Scrypt { log_n, r, p } => {
+                if r == 0 || p == 0 {
+                    bail!("invalid hash parameters");
+                }
+                scrypt::scrypt(password.as_bytes(), salt, &scrypt::ScryptParams::new(log_n, r, p), &mut hashed);
+            }
+        }
+        Ok(hashed)
+    }
+}
+
+
+/// A self-describing password hash, stored in the PHC string format
+/// `$<algorithm>$<params>$<salt>$<hash>` with unpadded base64 salt and hash,
+/// e.g. `$scrypt$ln=15,r=8,p=1$<salt>$<hash>`
+#[derive(Debug, PartialEq)]
+pub struct PasswordHash {
+    pub params: Params,
+    pub salt: Vec<u8>,
+    pub hash: Vec<u8>,
+}
+impl PasswordHash {
+    /// Hash a password with a fresh salt and the current parameters
+    pub fn new(password: &str) -> Result<PasswordHash> {
+        let salt = new_salt()?;
+        let hash = CURRENT_PARAMS.derive(password, &salt)?;
+        Ok(PasswordHash { params: CURRENT_PARAMS, salt: salt, hash: hash })
+    }
+
+    pub fn parse(phc: &str) -> Result<PasswordHash> {
+        let parts = phc.split('$').collect::<Vec<_>>();
+        if parts.len() != 5 || !parts[0].is_empty() {
+            bail!("invalid password hash");
+        }
+        let params = Params::decode(parts[1], parts[2])?;
+        let salt = parts[3].from_base64().chain_err(|| "invalid password hash salt")?;
+        let hash = parts[4].from_base64().chain_err(|| "invalid password hash")?;
+        Ok(PasswordHash { params: params, salt: salt, hash: hash })
+    }
+
+    pub fn encode(&self) -> String {
+        let b64 = Config { pad: false, ..STANDARD };
+        format!("${}${}${}${}", self.params.name(), self.params.encode(),
+                self.salt.to_base64(b64), self.hash.to_base64(b64))
+    }
+
+    /// Check a password against this hash
+    pub fn verify(&self, password: &str) -> bool {
+        match self.params.derive(password, &self.salt) {
+            Ok(hash) => const_eq(&hash, &self.hash),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether this hash was made with outdated parameters
+    pub fn needs_rehash(&self) -> bool {
+        self.params != CURRENT_PARAMS
+    }
+}
+
+
+/// Return the PHC string of a password hashed with the current parameters
+pub fn hash_password(password: &str) -> Result<String> {
+    Ok(PasswordHash::new(password)?.encode())
+}
+
+
+/// Check a password against a stored PHC string
+pub fn verify_password(password: &str, phc: &str) -> Result<bool> {
+    Ok(PasswordHash::parse(phc)?.verify(password))
+}
+
+
+/// Stand-in for a missing stored hash, made with the current parameters so
+/// checking against it costs the same as checking a real one
+pub fn dummy_hash() -> PasswordHash {
+    PasswordHash { params: CURRENT_PARAMS, salt: vec![0u8; 16], hash: vec![0u8; HASH_SIZE] }
+}
+
+
+/// Take as long as checking a password against a current hash. Used when
+/// there's no usable stored hash (e.g. the email isn't registered) so
+/// response times don't reveal it. Nothing hashes to all zeros, so in
+/// practice this never passes.
+pub fn dummy_verify(password: &str) -> bool {
+    dummy_hash().verify(password)
+}
+
+
+/// Check a login's password against the account's stored PHC string, if
+/// there is one, returning the parsed hash when it matches. Missing and
+/// unparseable hashes are checked with `dummy_verify` instead, so every
+/// refusal takes about as long as one for a current hash. Accounts still
+/// on a legacy bcrypt hash take a different time until their next login
+/// rehashes them, so their timing can still tell them apart.
+pub fn check_login(stored: Option<&str>, password: &str) -> Option<PasswordHash> {
+    match stored.map(PasswordHash::parse) {
+        Some(Ok(hash)) => if hash.verify(password) { Some(hash) } else { None },
+        _ => {
+            dummy_verify(password);
+            None
+        }
     }
-    const COST: u32 = 10;
-    const OUTPUT_SIZE: usize = 24;
-    let mut hashed = [0u8; OUTPUT_SIZE];
-    bcrypt::bcrypt(COST, salt, string.as_bytes(), &mut hashed);
-    Ok(hashed.iter().cloned().collect::<Vec<u8>>())
 }
 
 
-/// Do a constant time comparison of two hashed byte slices
+/// Do a constant time comparison of two hashed byte slices. Slices of
+/// different lengths are compared over the longer length rather than
+/// returning early, so the time taken only depends on the lengths.
 pub fn const_eq(one: &[u8], two: &[u8]) -> bool {
-    let mut ok = true;
-    if one.len() != two.len() { ok = false; }
-    for (a, b) in one.iter().zip(two.iter()) {
-        if a != b { ok = false; }
+    let len = ::std::cmp::max(one.len(), two.len());
+    let mut diff = one.len() ^ two.len();
+    for i in 0..len {
+        let a = one.get(i).cloned().unwrap_or(0);
+        let b = two.get(i).cloned().unwrap_or(0);
+        diff |= (a ^ b) as usize;
     }
-    ok
+    diff == 0
+}
+
+
+/// Return a random 256bit url-safe (hex) token for single-use links
+pub fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    match OsRng::new() {
+        Ok(mut rng) => rng.fill_bytes(&mut bytes),
+        Err(_) => bail!("rng error"),
+    };
+    Ok(bytes.to_hex())
+}
+
+
+/// Return the sha256 of a token. Tokens are high-entropy so they're
+/// stored hashed without a salt and looked up by their hash.
+pub fn hash_token(token: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input_str(token);
+    let mut out = vec![0u8; hasher.output_bytes()];
+    hasher.result(&mut out);
+    out
 }
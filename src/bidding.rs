@@ -0,0 +1,226 @@
+//! Bidding
+//!
+//! Validation and acceptance of new bids
+//!
+use std::collections::BTreeMap;
+use chrono::{self, TimeZone};
+use postgres::Connection;
+
+use models::{Bid, NewBid, Item, Bidder, User};
+use ledger;
+use sql;
+use errors::*;
+
+
+/// Select a bidder, locking its row until the end of the current transaction
+fn lock_bidder(conn: &Connection, bidder_id: i32) -> Result<Bidder> {
+    let qs = "select * from bidders where id=$1 for update";
+    query_or_none!(conn.query(qs, &[&bidder_id]), Bidder).ok_or_else(|| "bidder not found".into())
+}
+
+
+/// Smallest acceptable next bid on an item. Goal items take any pledge of
+/// at least `min_bid`; other items must beat the current high bid by `min_bid`.
+pub fn minimum_bid(conn: &Connection, item: &Item) -> i64 {
+    if item.is_goal {
+        return item.min_bid.max(1);
+    }
+    match sql::select_high_bid(conn, item.id) {
+        Some(high) => high.amount.saturating_add(item.min_bid.max(1)),
+        None => item.starting,
+    }
+}
+
+
+/// Total a bidder is currently on the hook for: every goal pledge plus every
+/// bid that is the high bid on its item. Bids on `except_item` are skipped,
+/// since a new bid on an item replaces the bidder's own high bid there.
+pub fn winning_total(conn: &Connection, bidder_id: i32, except_item: Option<i32>) -> Result<i64> {
+    let qs = "select coalesce(sum(b.amount), 0)::bigint from bids b \
+              join items i on i.id = b.item_id \
+              where b.bidder_id = $1 and ($2::integer is null or b.item_id <> $2) and i.deleted_at is null \
+              and (i.is_goal or b.id = (select top.id from bids top where top.item_id = b.item_id \
+                                        order by top.amount desc, top.date_created desc limit 1))";
+    let rows = conn.query(qs, &[&bidder_id, &except_item]).chain_err(|| "Error totaling winning bids")?;
+    Ok(rows.iter().next().map(|row| row.get(0)).unwrap_or(0))
+}
+
+
+/// Reject a bid of `amount` on `item` that would put the bidder over their
+/// (or their organization's) spending limit
+pub fn check_spending_limit(conn: &Connection, bidder: &Bidder, item: &Item, amount: i64) -> Result<()> {
+    let org = sql::get_organization(conn, bidder.organization_id).ok_or("organization not found")?;
+    let limit = match bidder.effective_spending_limit(&org) {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let except = if item.is_goal { None } else { Some(item.id) };
+    let exposure = winning_total(conn, bidder.id, except)?
+        .checked_add(amount)
+        .ok_or(ErrorKind::MoneyOverflow)?;
+    if exposure > limit {
+        bail!(ErrorKind::SpendingLimitExceeded(limit, exposure));
+    }
+    Ok(())
+}
+
+
+/// Validate and insert a new bid, posting a pledge to the ledger for goal items.
+///
+/// This should be called inside `sql::transaction`: the bidder's and then the
+/// item's rows are locked so concurrent bids by the same bidder or on the same
+/// item are validated one at a time, and the bid and its ledger posting commit
+/// together.
+pub fn place_bid(conn: &Connection, new_bid: NewBid) -> Result<Bid> {
+    let bidder = lock_bidder(conn, new_bid.bidder_id)?;
+    let item = sql::lock_item(conn, new_bid.item_id).ok_or("item not found")?;
+    if bidder.organization_id != item.organization_id {
+        bail!("bidder is not registered with this item's organization");
+    }
+    // paper bids are entered after the fact, so judge them by when they were written
+    let closed = match new_bid.written_at {
+        Some(written_at) => item.owning_bidder_id.is_some() ||
+            item.closes_at.map(|closes| written_at > closes).unwrap_or(false),
+        None => item.is_closed(),
+    };
+    if closed {
+        bail!(ErrorKind::ItemClosed(item.id));
+    }
+    let minimum = minimum_bid(conn, &item);
+    if new_bid.amount < minimum {
+        bail!(ErrorKind::BidTooLow(minimum));
+    }
+    check_spending_limit(conn, &bidder, &item, new_bid.amount)?;
+
+    let bid = new_bid.create(conn).chain_err(|| "Error creating bid")?;
+    if item.is_goal {
+        ledger::post_pledge(conn, &item, &bid).chain_err(|| "Error posting pledge")?;
+    }
+    Ok(bid)
+}
+
+
+#[derive(Debug)]
+/// One row transcribed from a paper bid sheet. `row` is the caller's
+/// row number, used when reporting errors.
+pub struct PaperBid {
+    pub row: usize,
+    pub paddle: i32,
+    pub item_id: i32,
+    pub amount: i64,
+    pub written_at: chrono::DateTime<chrono::UTC>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, RustcEncodable)]
+pub struct RowError {
+    pub row: usize,
+    pub msg: String,
+}
+impl RowError {
+    pub fn new(row: usize, msg: &str) -> RowError {
+        RowError { row: row, msg: msg.into() }
+    }
+}
+
+
+/// Parse a bid sheet time: rfc3339, or `YYYY-MM-DD HH:MM[:SS]` in server-local time
+pub fn parse_written_at(s: &str) -> Result<chrono::DateTime<chrono::UTC>> {
+    let s = s.trim();
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&chrono::UTC));
+    }
+    for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"].iter() {
+        if let Ok(naive) = chrono::NaiveDateTime::parse_from_str(s, fmt) {
+            if let Some(dt) = chrono::Local.from_local_datetime(&naive).earliest() {
+                return Ok(dt.with_timezone(&chrono::UTC));
+            }
+        }
+    }
+    bail!("invalid time '{}', expected YYYY-MM-DD HH:MM", s)
+}
+
+
+/// Sort one item's rows into written-time order and check that their
+/// amounts strictly increase, returning an error for each row that doesn't
+/// follow on from the one written before it.
+pub fn check_order<T>(item_id: i32, rows: &mut [(PaperBid, T)]) -> Vec<RowError> {
+    rows.sort_by_key(|&(ref row, _)| (row.written_at, row.row));
+    let mut errors = vec![];
+    for pair in rows.windows(2) {
+        let (ref prev, _) = pair[0];
+        let (ref next, _) = pair[1];
+        if next.written_at == prev.written_at {
+            errors.push(RowError::new(next.row, &format!(
+                "written at the same time as row {} on item {}", prev.row, item_id)));
+        } else if next.amount <= prev.amount {
+            errors.push(RowError::new(next.row, &format!(
+                "amount must be greater than row {} written earlier on item {}", prev.row, item_id)));
+        }
+    }
+    errors
+}
+
+
+/// Whether a `place_bid` error is a problem with the bid itself rather than
+/// with the database
+fn is_rejected_bid(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::BidTooLow(..) | ErrorKind::ItemClosed(..) |
+        ErrorKind::SpendingLimitExceeded(..) | ErrorKind::MoneyOverflow => true,
+        _ => false,
+    }
+}
+
+
+/// Enter a batch of paper bid sheet rows for an organization.
+///
+/// Rows are grouped by item and must form a strictly increasing sequence of
+/// amounts in written-time order (see `check_order`); each is then placed
+/// like an online bid (minimum increment, spending limits) in its own
+/// savepoint and recorded as a manual bid entered by `volunteer`. Fails with
+/// `InvalidRows` listing every row-level error found, or with the first
+/// database error. This should be called inside `sql::transaction` so that
+/// nothing is inserted unless every row is.
+pub fn enter_paper_bids(conn: &Connection, org_id: i32, volunteer: Option<&User>,
+                        rows: Vec<PaperBid>) -> Result<Vec<Bid>> {
+    let mut errors = vec![];
+    let mut by_item: BTreeMap<i32, Vec<(PaperBid, i32)>> = BTreeMap::new();
+    for row in rows.into_iter() {
+        let bidder = sql::select_bidder_by_paddle(conn, org_id, row.paddle);
+        let item = sql::get_item(conn, row.item_id);
+        match (bidder, item) {
+            (None, _) => errors.push(RowError::new(row.row, &format!("unknown paddle {}", row.paddle))),
+            (_, None) => errors.push(RowError::new(row.row, &format!("unknown item {}", row.item_id))),
+            (Some(_), Some(ref item)) if item.organization_id != org_id => {
+                errors.push(RowError::new(row.row, &format!("item {} belongs to another organization", item.id)))
+            }
+            (Some(bidder), Some(_)) => by_item.entry(row.item_id).or_insert(vec![]).push((row, bidder.id)),
+        }
+    }
+    for (item_id, item_rows) in by_item.iter_mut() {
+        errors.extend(check_order(*item_id, item_rows));
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.row);
+        bail!(ErrorKind::InvalidRows(errors));
+    }
+
+    let volunteer_id = volunteer.map(|v| v.id);
+    let mut bids = vec![];
+    for (_, item_rows) in by_item.into_iter() {
+        for (row, bidder_id) in item_rows.into_iter() {
+            let new_bid = NewBid::manual(bidder_id, row.item_id, row.amount, volunteer_id, row.written_at);
+            match sql::transaction(conn, |conn| place_bid(conn, new_bid)) {
+                Ok(bid) => bids.push(bid),
+                Err(ref err) if is_rejected_bid(err) => errors.push(RowError::new(row.row, &err.to_string())),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.row);
+        bail!(ErrorKind::InvalidRows(errors));
+    }
+    Ok(bids)
+}
@@ -4,7 +4,7 @@ extern crate uuid;
 use uuid::Uuid;
 use bidrs::models;
 use bidrs::service;
-use bidrs::auth;
+use bidrs::roles;
 
 
 pub fn main() {
@@ -16,12 +16,12 @@ pub fn main() {
     let user_emails = ["james.k@gmail.com", "bob@gmail.com", "lauren@gmail.com", "brian.k@gmail.com"];
     let mut users = vec![];
     for email in user_emails.iter() {
-        let salt = auth::new_salt().expect("failed salt");
-        let new_auth = models::NewAuth { salt: salt, password: "enter".into() };
+        let new_auth = models::NewAuth::new("bidrs-enter").expect("failed password");
         let auth = new_auth.create(&conn).expect("failed auth creation");
 
-        let new_user = models::NewUser { auth_id: auth.id, level_: 10, email: email.to_string(), uuid_: Uuid::new_v4() };
-        let user = new_user.create(&conn).expect("failed user creation");
+        let new_user = models::NewUser { auth_id: auth.id, email: email.to_string(), uuid_: Uuid::new_v4(), verified_at: None };
+        let user = new_user.verified().create(&conn).expect("failed user creation");
+        roles::grant(&conn, user.id, roles::Role::SuperAdmin, None).expect("failed role grant");
 
         println!("created user [{}] with id={}", user.email, user.id);
         users.push(user);
@@ -79,7 +79,7 @@ pub fn main() {
         let new_item = models::NewItem {
             organization_id: org.id, owning_bidder_id: None, is_goal: false,
             title: info.0.to_string(), description: info.0.to_string(),
-            value: info.1, starting: info.2, min_bid: info.3,
+            value: info.1, starting: info.2, min_bid: info.3, closes_at: None,
         };
         let item = new_item.create(&conn).expect("failed creating item");
 
@@ -89,7 +89,7 @@ pub fn main() {
 
     // add bid
     println!("");
-    let new_bid = models::NewBid { bidder_id: bidders[0].id, item_id: items[0].id, amount: 5000 };
+    let new_bid = models::NewBid::new(bidders[0].id, items[0].id, 5000);
     let bid = new_bid.create(&conn).expect("failed creating bid");
     println!("created bid [{}] on item={}", bid.amount, bid.item_id);
 
@@ -50,7 +50,76 @@ pub fn main() {
                          .help("create new item"))
                     .arg(Arg::with_name("create-bid")
                          .long("create-bid")
-                         .help("create new bid")))
+                         .help("create new bid"))
+                    .arg(Arg::with_name("award-item")
+                         .long("award-item")
+                         .help("award an item to its high bid and post the sale"))
+                    .arg(Arg::with_name("record-payment")
+                         .long("record-payment")
+                         .help("post a bidder payment to the ledger"))
+                    .arg(Arg::with_name("record-refund")
+                         .long("record-refund")
+                         .help("post a bidder refund to the ledger"))
+                    .arg(Arg::with_name("trial-balance")
+                         .long("trial-balance")
+                         .help("print the ledger trial balance for an organization"))
+                    .arg(Arg::with_name("account-statement")
+                         .long("account-statement")
+                         .help("print the ledger statement for an account"))
+                    .arg(Arg::with_name("receipts")
+                         .long("receipts")
+                         .help("write year-end donation receipts"))
+                    .arg(Arg::with_name("set-spending-limit")
+                         .long("set-spending-limit")
+                         .help("set or clear an organization or bidder spending limit"))
+                    .arg(Arg::with_name("preauthorize")
+                         .long("preauthorize")
+                         .help("place a pre-authorization hold on a bidder's card"))
+                    .arg(Arg::with_name("assign-paddles")
+                         .long("assign-paddles")
+                         .help("assign paddle numbers to an organization's unnumbered bidders"))
+                    .arg(Arg::with_name("print-paddles")
+                         .long("print-paddles")
+                         .help("print paddle numbers for an organization"))
+                    .arg(Arg::with_name("check-in")
+                         .long("check-in")
+                         .help("check-in desk: look up and check in guests"))
+                    .arg(Arg::with_name("walk-in")
+                         .long("walk-in")
+                         .help("register and check in a walk-in guest"))
+                    .arg(Arg::with_name("bulk-bids")
+                         .long("bulk-bids")
+                         .help("enter paper bid sheets from a csv file"))
+                    .arg(Arg::with_name("notify-closing")
+                         .long("notify-closing")
+                         .help("email watchers of items closing soon"))
+                    .arg(Arg::with_name("display-token")
+                         .long("display-token")
+                         .help("issue a new display board url for an organization"))
+                    .arg(Arg::with_name("grant-role")
+                         .long("grant-role")
+                         .help("grant a role to a user"))
+                    .arg(Arg::with_name("revoke-role")
+                         .long("revoke-role")
+                         .help("revoke a role from a user"))
+                    .arg(Arg::with_name("delete-user")
+                         .long("delete-user")
+                         .help("soft delete a user"))
+                    .arg(Arg::with_name("restore")
+                         .long("restore")
+                         .help("restore a deleted organization, item or user"))
+                    .arg(Arg::with_name("lockouts")
+                         .long("lockouts")
+                         .help("list emails and ip addresses throttled from logging in"))
+                    .arg(Arg::with_name("clear-lockout")
+                         .long("clear-lockout")
+                         .help("clear an email or ip address's failed logins"))
+                    .arg(Arg::with_name("enroll-two-factor")
+                         .long("enroll-two-factor")
+                         .help("enroll a user in two-factor authentication"))
+                    .arg(Arg::with_name("reset-two-factor")
+                         .long("reset-two-factor")
+                         .help("remove a user's two-factor authentication")))
         .get_matches();
 
     if let Err(ref e) = run(matches) {
@@ -126,6 +195,121 @@ fn run(matches: ArgMatches) -> Result<()> {
                 .chain_err(|| "Error creating new bid")?;
         }
 
+        if cli_matches.is_present("award-item") {
+            cli::award_item()
+                .chain_err(|| "Error awarding item")?;
+        }
+
+        if cli_matches.is_present("record-payment") {
+            cli::record_payment()
+                .chain_err(|| "Error recording payment")?;
+        }
+
+        if cli_matches.is_present("record-refund") {
+            cli::record_refund()
+                .chain_err(|| "Error recording refund")?;
+        }
+
+        if cli_matches.is_present("trial-balance") {
+            cli::trial_balance()
+                .chain_err(|| "Error building trial balance")?;
+        }
+
+        if cli_matches.is_present("account-statement") {
+            cli::account_statement()
+                .chain_err(|| "Error building account statement")?;
+        }
+
+        if cli_matches.is_present("receipts") {
+            cli::write_receipts()
+                .chain_err(|| "Error writing receipts")?;
+        }
+
+        if cli_matches.is_present("set-spending-limit") {
+            cli::set_spending_limit()
+                .chain_err(|| "Error setting spending limit")?;
+        }
+
+        if cli_matches.is_present("preauthorize") {
+            cli::preauthorize_bidder()
+                .chain_err(|| "Error pre-authorizing bidder")?;
+        }
+
+        if cli_matches.is_present("assign-paddles") {
+            cli::assign_paddles()
+                .chain_err(|| "Error assigning paddles")?;
+        }
+
+        if cli_matches.is_present("print-paddles") {
+            cli::print_paddles()
+                .chain_err(|| "Error printing paddles")?;
+        }
+
+        if cli_matches.is_present("check-in") {
+            cli::check_in()
+                .chain_err(|| "Error checking in")?;
+        }
+
+        if cli_matches.is_present("walk-in") {
+            cli::walk_in()
+                .chain_err(|| "Error registering walk-in")?;
+        }
+
+        if cli_matches.is_present("bulk-bids") {
+            cli::bulk_bids()
+                .chain_err(|| "Error entering bid sheets")?;
+        }
+
+        if cli_matches.is_present("notify-closing") {
+            cli::notify_closing()
+                .chain_err(|| "Error sending closing notifications")?;
+        }
+
+        if cli_matches.is_present("display-token") {
+            cli::display_token()
+                .chain_err(|| "Error issuing display token")?;
+        }
+
+        if cli_matches.is_present("grant-role") {
+            cli::grant_role()
+                .chain_err(|| "Error granting role")?;
+        }
+
+        if cli_matches.is_present("revoke-role") {
+            cli::revoke_role()
+                .chain_err(|| "Error revoking role")?;
+        }
+
+        if cli_matches.is_present("delete-user") {
+            cli::delete_user()
+                .chain_err(|| "Error deleting user")?;
+        }
+
+        if cli_matches.is_present("restore") {
+            cli::restore()
+                .chain_err(|| "Error restoring record")?;
+        }
+
+        if cli_matches.is_present("lockouts") {
+            cli::lockouts()
+                .chain_err(|| "Error listing lockouts")?;
+        }
+
+        if cli_matches.is_present("clear-lockout") {
+            cli::clear_lockout()
+                .chain_err(|| "Error clearing lockout")?;
+        }
+
+        if cli_matches.is_present("enroll-two-factor") {
+            cli::enroll_two_factor()
+                .chain_err(|| "Error enrolling two-factor")?;
+        }
+
+        if cli_matches.is_present("reset-two-factor") {
+            cli::reset_two_factor()
+                .chain_err(|| "Error resetting two-factor")?;
+        }
+
     }
 
     Ok(())
@@ -0,0 +1,134 @@
+//! Check-in
+//!
+//! Door check-in for event night: guest lookup by name, email or paddle,
+//! marking guests as arrived, and one-step walk-in registration.
+//!
+use chrono;
+use postgres::{self, Connection};
+
+use models::{self, User};
+use registration;
+use roles::{self, Role};
+use auth;
+use sql;
+use errors::*;
+
+
+#[derive(Debug, RustcEncodable)]
+/// A guest as seen from the check-in desk
+pub struct Guest {
+    pub profile_id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub email: String,
+    pub phone: Option<String>,
+    pub bidder_id: Option<i32>,
+    pub paddle: Option<i32>,
+    pub checked_in_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub has_payment_method: bool,
+}
+impl Guest {
+    pub fn from_row(row: postgres::rows::Row) -> Guest {
+        Guest {
+            profile_id: row.get(0),
+            user_id: row.get(1),
+            name: row.get(2),
+            email: row.get(3),
+            phone: row.get(4),
+            bidder_id: row.get(5),
+            paddle: row.get(6),
+            checked_in_at: row.get(7),
+            has_payment_method: row.get(8),
+        }
+    }
+
+    pub fn get(conn: &Connection, profile_id: i32) -> Option<Guest> {
+        let qs = format!("{} where p.id=$1", GUEST_SELECT);
+        query_or_none!(conn.query(&qs, &[&profile_id]), Guest)
+    }
+}
+
+const GUEST_SELECT: &'static str =
+    "select p.id, u.id, p.name, u.email, p.phone, b.id, b.paddle, p.checked_in_at, \
+     p.payment_info_id is not null \
+     from profiles p join users u on u.id = p.user_id \
+     left join bidders b on b.id = p.bidder_id";
+
+
+/// Look up guests by paddle number (all digits), email (contains an '@'),
+/// or the start of their profile name, optionally within one organization.
+pub fn search(conn: &Connection, query: &str, org_id: Option<i32>) -> Vec<Guest> {
+    let query = query.trim();
+    let org_filter = "and ($2::integer is null or b.organization_id = $2) \
+                      order by lower(p.name), p.id limit 25";
+    if !query.is_empty() && query.chars().all(|c| c.is_digit(10)) {
+        let paddle = query.parse::<i32>().unwrap_or(0);
+        let qs = format!("{} where b.paddle = $1 {}", GUEST_SELECT, org_filter);
+        query_coll!(conn.query(&qs, &[&paddle, &org_id]), Guest)
+    } else if query.contains('@') {
+        let qs = format!("{} where lower(u.email) = lower($1) {}", GUEST_SELECT, org_filter);
+        query_coll!(conn.query(&qs, &[&query, &org_id]), Guest)
+    } else {
+        // escaped so names containing '%' or '_' match literally; a prefix
+        // pattern can use the `text_pattern_ops` index on lower(name)
+        let pattern = format!("{}%", query.to_lowercase()
+                              .replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let qs = format!("{} where lower(p.name) like $1 {}", GUEST_SELECT, org_filter);
+        query_coll!(conn.query(&qs, &[&pattern, &org_id]), Guest)
+    }
+}
+
+
+/// Mark a guest as arrived. Checking in twice keeps the original time.
+/// `volunteer` is `None` when checking in from the server cli.
+pub fn check_in(conn: &Connection, volunteer: Option<&User>, profile_id: i32) -> Result<Guest> {
+    let volunteer_id = volunteer.map(|v| v.id);
+    let qs = "update profiles set checked_in_at=coalesce(checked_in_at, now()), \
+              checked_in_by=coalesce(checked_in_by, $2) where id=$1";
+    let updated = conn.execute(qs, &[&profile_id, &volunteer_id]).chain_err(|| "Error checking in guest")?;
+    if updated == 0 {
+        bail!("guest not found");
+    }
+    Guest::get(conn, profile_id).ok_or("guest not found".into())
+}
+
+
+#[derive(Debug)]
+/// A guest arriving without an account
+pub struct WalkIn {
+    pub organization_id: i32,
+    pub email: String,
+    pub name: String,
+    pub phone: Option<String>,
+}
+
+
+/// Create a user, profile and bidder (with paddle) for a walk-in guest and
+/// check them in. The account gets a random password; the guest can use
+/// password recovery to take it over later. Should be run in `sql::transaction`.
+pub fn walk_in(conn: &Connection, volunteer: Option<&User>, walk_in: WalkIn) -> Result<Guest> {
+    let email = walk_in.email.trim().to_lowercase();
+    if !email.contains('@') {
+        bail!("invalid email address");
+    }
+    if walk_in.name.trim().is_empty() {
+        bail!("name is required");
+    }
+    if sql::select_user_by_email(conn, &email).is_some() {
+        bail!("a user with that email already exists");
+    }
+    sql::get_organization(conn, walk_in.organization_id).ok_or("organization not found")?;
+
+    let password = auth::new_token()?;
+    let new_auth = models::NewAuth::new(&password)?.create(conn).chain_err(|| "Error creating auth")?;
+    let user = models::NewUser::new(&email, &new_auth).verified().create(conn).chain_err(|| "Error creating user")?;
+    roles::grant(conn, user.id, Role::Bidder, Some(walk_in.organization_id))?;
+    let bidder = models::NewBidder::new(walk_in.organization_id, walk_in.name.trim()).create(conn)
+        .chain_err(|| "Error creating bidder")?;
+    let bidder = registration::assign_paddle(conn, bidder)?;
+    let phone = walk_in.phone.as_ref().map(|p| p.as_str()).filter(|p| !p.is_empty());
+    let profile = models::NewProfile::new(user.id, Some(bidder.id), None, true,
+                                          walk_in.name.trim(), phone, None)
+        .create(conn).chain_err(|| "Error creating profile")?;
+    check_in(conn, volunteer, profile.id)
+}
@@ -1,10 +1,27 @@
 use std::io::{self, Read, BufRead, Write};
+use std::fs;
+use std::path::Path;
 use rpassword::read_password;
 use rustc_serialize::json::Json;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, Duration};
+use postgres::Connection;
 
 use service::establish_connection;
 use models;
+use sql;
+use ledger;
+use money::{Money, Currency};
+use receipts;
+use bidding;
+use payments;
+use registration;
+use checkin;
+use watchlist;
+use roles::{self, Role};
+use mailer;
+use display;
+use login_throttle;
+use two_factor;
 use errors::*;
 
 
@@ -81,19 +98,168 @@ pub fn create_user() -> Result<models::User> {
                                .secure()
                                .capture()
                                .chain_err(|| "Prompter Error")?;
-    let new_level = Prompter::new("$ User level >> ").capture()
-                             .chain_err(|| "prompter error")?
-                             .parse::<i32>()
-                             .chain_err(|| "error parsing user.level")?;
-    let new_auth: models::Auth = models::NewAuth::new(&new_password).create(&conn)
+    let new_auth: models::Auth = models::NewAuth::new(&new_password)?.create(&conn)
         .chain_err(|| "Error creating auth")?;
-    let new_user: models::User = models::NewUser::new(&new_email, &new_auth, new_level)
-        .create(&conn).chain_err(|| "Error creating user")?;
+    let new_user: models::User = models::NewUser::new(&new_email, &new_auth)
+        .verified().create(&conn).chain_err(|| "Error creating user")?;
     println!("User created with id, email: {}, {}", new_user.id, new_user.email);
     Ok(new_user)
 }
 
 
+/// Prompt for a user, role and (optional) organization
+fn capture_role_grant(conn: &Connection) -> Result<(i32, Role, Option<i32>)> {
+    let args = ["user email", "role (super_admin, org_admin, volunteer, bidder)",
+                "organization_id (blank for all organizations)"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let user = sql::select_user_by_email(conn, &args[0]).ok_or("user not found")?;
+    let role = Role::from_str(&args[1]).ok_or("unknown role")?;
+    let org_id = if args[2].is_empty() {
+        None
+    } else {
+        Some(args[2].parse::<i32>().chain_err(|| "org_id error")?)
+    };
+    Ok((user.id, role, org_id))
+}
+
+
+pub fn grant_role() -> Result<()> {
+    println!("Granting role...");
+    let conn = establish_connection();
+    let (user_id, role, org_id) = capture_role_grant(&conn)?;
+    roles::grant(&conn, user_id, role, org_id)?;
+    println!("Granted {} to user {}", role.as_str(), user_id);
+    Ok(())
+}
+
+
+pub fn revoke_role() -> Result<()> {
+    println!("Revoking role...");
+    let conn = establish_connection();
+    let (user_id, role, org_id) = capture_role_grant(&conn)?;
+    if roles::revoke(&conn, user_id, role, org_id)? {
+        println!("Revoked {} from user {}", role.as_str(), user_id);
+    } else {
+        println!("User {} did not hold that role", user_id);
+    }
+    Ok(())
+}
+
+
+/// Soft delete a user by email, see `restore`
+pub fn delete_user() -> Result<()> {
+    let conn = establish_connection();
+    let email = Prompter::new("$ user email >> ").capture().expect("Prompter Error");
+    let user = sql::select_user_by_email(&conn, &email).ok_or("user not found")?;
+    sql::transaction(&conn, |conn| sql::delete_user_by_id(conn, user.id))?;
+    println!("Deleted user {} ({}), restore with --restore", user.id, user.email);
+    Ok(())
+}
+
+
+/// List soft deleted organizations, items or users and restore one by id
+pub fn restore() -> Result<()> {
+    println!("Restoring deleted record...");
+    let conn = establish_connection();
+    let kind = Prompter::new("$ kind (organization, item, user) >> ").capture().expect("Prompter Error");
+    let deleted = match kind.as_str() {
+        "organization" => sql::select_deleted_organizations(&conn).into_iter()
+            .map(|org| (org.id, org.name, org.deleted_at)).collect::<Vec<_>>(),
+        "item" => sql::select_deleted_items(&conn).into_iter()
+            .map(|item| (item.id, item.title, item.deleted_at)).collect(),
+        "user" => sql::select_deleted_users(&conn).into_iter()
+            .map(|user| (user.id, user.email, user.deleted_at)).collect(),
+        _ => bail!("unknown kind: {}", kind),
+    };
+    if deleted.is_empty() {
+        println!("No deleted {}s", kind);
+        return Ok(());
+    }
+    for (id, name, deleted_at) in deleted {
+        let deleted_at = deleted_at.map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string()).unwrap_or_default();
+        println!("{:>6}  {:<40}  deleted {}", id, name, deleted_at);
+    }
+    let id = Prompter::new("$ id >> ").capture().expect("Prompter Error");
+    let id = id.parse::<i32>().chain_err(|| "id error")?;
+    match kind.as_str() {
+        "organization" => { sql::restore_organization(&conn, id)?; }
+        "item" => { sql::restore_item(&conn, id)?; }
+        _ => { sql::restore_user(&conn, id)?; }
+    }
+    println!("Restored {} {}", kind, id);
+    Ok(())
+}
+
+
+/// Enroll a user in two-factor authentication, printing the uri to scan
+/// (e.g. `qrencode -t ansiutf8 '<uri>'`) and their recovery codes
+pub fn enroll_two_factor() -> Result<()> {
+    let conn = establish_connection();
+    let email = Prompter::new("$ user email >> ").capture().expect("Prompter Error");
+    let user = sql::select_user_by_email(&conn, &email).ok_or("user not found")?;
+    let enrollment = sql::transaction(&conn, |conn| two_factor::enroll(conn, &user))?;
+    println!("Add this to an authenticator app (or enter the secret {}):\n\n{}\n", enrollment.secret, enrollment.uri);
+    let code = Prompter::new("$ code from the app >> ").capture().expect("Prompter Error");
+    let codes = sql::transaction(&conn, |conn| two_factor::confirm(conn, &user, &code))?;
+    println!("Two-factor enabled for {}. Recovery codes, each usable once:\n", user.email);
+    for code in codes {
+        println!("    {}", code);
+    }
+    Ok(())
+}
+
+
+/// Remove a user's second factor, e.g. after they've lost their device
+pub fn reset_two_factor() -> Result<()> {
+    let conn = establish_connection();
+    let email = Prompter::new("$ user email >> ").capture().expect("Prompter Error");
+    let user = sql::select_user_by_email(&conn, &email).ok_or("user not found")?;
+    sql::transaction(&conn, |conn| two_factor::reset(conn, &user))?;
+    println!("Removed two-factor for {}, they can enroll again after logging in", user.email);
+    Ok(())
+}
+
+
+/// List emails and ip addresses that are throttled or locked out of logging in
+pub fn lockouts() -> Result<()> {
+    let conn = establish_connection();
+    let throttled = login_throttle::throttled(&conn)?;
+    if throttled.is_empty() {
+        println!("No throttled logins");
+        return Ok(());
+    }
+    for status in throttled {
+        let (kind, value) = match status.key {
+            login_throttle::Key::Email(ref email) => ("email", email.clone()),
+            login_throttle::Key::Ip(ref ip) => ("ip", ip.clone()),
+        };
+        let until = status.retry_at.map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string()).unwrap_or_default();
+        println!("{:<5}  {:<40}  {:>3} failures  {} until {}",
+                 kind, value, status.failures, if status.locked { "locked" } else { "waiting" }, until);
+    }
+    Ok(())
+}
+
+
+/// Clear an email or ip address's failed logins, ending its lockout
+pub fn clear_lockout() -> Result<()> {
+    let conn = establish_connection();
+    let kind = Prompter::new("$ kind (email, ip) >> ").capture().expect("Prompter Error");
+    let value = Prompter::new(&format!("$ {} >> ", kind)).capture().expect("Prompter Error");
+    let key = match kind.as_str() {
+        "email" => login_throttle::Key::Email(value.to_lowercase()),
+        "ip" => login_throttle::Key::Ip(value),
+        _ => bail!("unknown kind: {}", kind),
+    };
+    let cleared = login_throttle::clear(&conn, &key)?;
+    println!("Cleared {} failed logins", cleared);
+    Ok(())
+}
+
+
 pub fn create_org() -> Result<models::Organization> {
     println!("Creating new organization...");
     let conn = establish_connection();
@@ -190,21 +356,30 @@ pub fn create_item() -> Result<models::Item> {
     println!("Creating new item...");
     let conn = establish_connection();
     let args = ["organization_id", "is_goal", "title", "description",
-                "value [in cents]", "starting [in cents]", "min_bid [in cents]"].iter().map(|arg| {
+                "value", "starting", "min_bid", "closes_at (blank for none)"].iter().map(|arg| {
                     Prompter::new(&format!("$ {} >> ", arg))
                             .capture()
                             .expect("Prompter Error")
                 }).collect::<Vec<_>>();
+    let org_id = args[0].parse::<i32>().chain_err(|| "org_id error")?;
+    let currency = org_currency(&conn, org_id)?;
     let new_item = models::NewItem::new(
-        args[0].parse::<i32>().chain_err(|| "org_id error")?,
+        org_id,
         args[1].parse::<bool>().chain_err(|| "is_goal error")?,
         &args[2], &args[3],
-        args[4].parse::<i64>().chain_err(|| "value error")?,
-        args[5].parse::<i64>().chain_err(|| "starting error")?,
-        args[6].parse::<i64>().chain_err(|| "min_bid error")?,
-    ).create(&conn).chain_err(|| "Error creating item")?;
-    println!("Item created with id, title: {}, {}",
-             new_item.id, new_item.title);
+        parse_amount(&args[4], currency, "value")?,
+        parse_amount(&args[5], currency, "starting")?,
+        parse_amount(&args[6], currency, "min_bid")?,
+    );
+    let closes_at = if args[7].trim().is_empty() {
+        None
+    } else {
+        Some(bidding::parse_written_at(&args[7]).chain_err(|| "closes_at error")?)
+    };
+    let new_item = models::NewItem { closes_at: closes_at, ..new_item }
+        .create(&conn).chain_err(|| "Error creating item")?;
+    println!("Item created with id, title, value: {}, {}, {}",
+             new_item.id, new_item.title, Money::new(new_item.value, currency));
     Ok(new_item)
 }
 
@@ -212,17 +387,471 @@ pub fn create_item() -> Result<models::Item> {
 pub fn create_bid() -> Result<models::Bid> {
     println!("Creating new bid...");
     let conn = establish_connection();
-    let args = ["bidder_id", "item_id", "amount (in cents)"].iter().map(|arg| {
+    let args = ["bidder_id", "item_id", "amount"].iter().map(|arg| {
         Prompter::new(&format!("$ {} >> ", arg))
                 .capture()
                 .expect("Prompter Error")
     }).collect::<Vec<_>>();
+    let item_id = args[1].parse::<i32>().chain_err(|| "item_id error")?;
+    let item = sql::get_item(&conn, item_id).ok_or("item not found")?;
+    let currency = org_currency(&conn, item.organization_id)?;
+    let amount = parse_amount(&args[2], currency, "amount")?;
     let new_bid = models::NewBid::new(
         args[0].parse::<i32>().chain_err(|| "bidder_id error")?,
-        args[1].parse::<i32>().chain_err(|| "item_id error")?,
-        args[2].parse::<i64>().chain_err(|| "amount error")?,
-    ).create(&conn).chain_err(|| "Error creating bid")?;
+        item.id,
+        amount,
+    );
+    let new_bid = sql::transaction(&conn, |conn| bidding::place_bid(conn, new_bid))?;
     println!("Bid created with id, amount: {}, {}",
-             new_bid.id, new_bid.amount);
+             new_bid.id, Money::new(new_bid.amount, currency));
     Ok(new_bid)
 }
+
+
+/// Award an item to its current high bid, posting the sale to the ledger
+pub fn award_item() -> Result<models::Item> {
+    println!("Awarding item...");
+    let conn = establish_connection();
+    let item_id = Prompter::new("$ item_id >> ")
+                          .capture()
+                          .chain_err(|| "Prompter Error")?
+                          .parse::<i32>()
+                          .chain_err(|| "item_id error")?;
+    let item = sql::get_item(&conn, item_id).ok_or("item not found")?;
+    if item.is_goal {
+        bail!("goal items are pledged, not awarded");
+    }
+    let (item, bid) = sql::transaction(&conn, |conn| {
+        // locked so a concurrent award or bid waits for this one
+        let item = sql::lock_item(conn, item.id).ok_or("item not found")?;
+        if item.owning_bidder_id.is_some() {
+            bail!("item has already been awarded");
+        }
+        let bid = sql::select_high_bid(conn, item.id).ok_or("item has no bids")?;
+        conn.execute("update items set owning_bidder_id=$1 where id=$2", &[&bid.bidder_id, &item.id])
+            .chain_err(|| "Error updating item owner")?;
+        ledger::post_sale(conn, &item, &bid).chain_err(|| "Error posting sale")?;
+        Ok((models::Item { owning_bidder_id: Some(bid.bidder_id), ..item }, bid))
+    })?;
+    println!("Item {} awarded to bidder {} for {}",
+             item.id, bid.bidder_id, Money::new(bid.amount, org_currency(&conn, item.organization_id)?));
+    Ok(item)
+}
+
+
+fn capture_ledger_movement(conn: &Connection) -> Result<(i32, i32, i64, String)> {
+    let args = ["organization_id", "bidder_id", "amount", "memo"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let org_id = args[0].parse::<i32>().chain_err(|| "org_id error")?;
+    let amount = Money::parse(&args[2], org_currency(conn, org_id)?).chain_err(|| "amount error")?;
+    if amount.amount <= 0 {
+        bail!("amount must be positive");
+    }
+    Ok((org_id,
+        args[1].parse::<i32>().chain_err(|| "bidder_id error")?,
+        amount.amount,
+        args[3].clone()))
+}
+
+
+pub fn record_payment() -> Result<ledger::LedgerTransaction> {
+    println!("Recording payment...");
+    let conn = establish_connection();
+    let (org_id, bidder_id, amount, memo) = capture_ledger_movement(&conn)?;
+    let tx = sql::transaction(&conn, |conn| {
+        ledger::post_payment(conn, org_id, bidder_id, amount, &memo)
+    }).chain_err(|| "Error posting payment")?;
+    println!("Payment recorded with ledger transaction id: {}", tx.id);
+    Ok(tx)
+}
+
+
+pub fn record_refund() -> Result<ledger::LedgerTransaction> {
+    println!("Recording refund...");
+    let conn = establish_connection();
+    let (org_id, bidder_id, amount, memo) = capture_ledger_movement(&conn)?;
+    let tx = sql::transaction(&conn, |conn| {
+        ledger::post_refund(conn, org_id, bidder_id, amount, &memo)
+    }).chain_err(|| "Error posting refund")?;
+    println!("Refund recorded with ledger transaction id: {}", tx.id);
+    Ok(tx)
+}
+
+
+pub fn trial_balance() -> Result<()> {
+    let conn = establish_connection();
+    let org_id = Prompter::new("$ Org id >> ")
+                         .capture()
+                         .chain_err(|| "Prompter Error")?
+                         .parse::<i32>()
+                         .chain_err(|| "Error parsing i32")?;
+    let currency = org_currency(&conn, org_id)?;
+    let fmt = |cents: i64| Money::new(cents, currency).to_string();
+    let balances = ledger::trial_balance(&conn, org_id)?;
+    println!("\n{:<20} {:>14} {:>14}", "account", "debit", "credit");
+    let (mut debits, mut credits) = (0, 0);
+    for bal in balances.iter() {
+        println!("{:<20} {:>14} {:>14}", bal.account, fmt(bal.debit), fmt(bal.credit));
+        debits += bal.debit;
+        credits += bal.credit;
+    }
+    println!("{:<20} {:>14} {:>14}", "total", fmt(debits), fmt(credits));
+    if debits != credits {
+        bail!("trial balance is out of balance by {}", fmt(debits - credits));
+    }
+    Ok(())
+}
+
+
+pub fn account_statement() -> Result<()> {
+    let conn = establish_connection();
+    let args = ["organization_id", "account", "bidder_id (optional)"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let org_id = args[0].parse::<i32>().chain_err(|| "org_id error")?;
+    let account = match ledger::Account::from_str(&args[1]) {
+        Some(acc) => acc,
+        None => bail!("unknown account, expected one of: {}",
+                      ledger::Account::all().iter().map(|a| a.as_str()).collect::<Vec<_>>().join(", ")),
+    };
+    let bidder_id = if args[2].is_empty() { None } else {
+        Some(args[2].parse::<i32>().chain_err(|| "bidder_id error")?)
+    };
+    let currency = org_currency(&conn, org_id)?;
+    let fmt = |cents: i64| Money::new(cents, currency).to_string();
+    let lines = ledger::account_statement(&conn, org_id, account, bidder_id)?;
+    println!("\n{:<26} {:>6} {:<8} {:>12} {:>12} {:>14}  {}",
+             "date", "tx", "kind", "debit", "credit", "balance", "memo");
+    for line in lines.iter() {
+        println!("{:<26} {:>6} {:<8} {:>12} {:>12} {:>14}  {}",
+                 line.date_created.format("%Y-%m-%d %H:%M:%S").to_string(), line.transaction_id,
+                 line.kind, fmt(line.debit), fmt(line.credit),
+                 fmt(line.balance), line.memo);
+    }
+    Ok(())
+}
+
+
+/// Set or clear the spending limit on an organization (applies to all of its
+/// bidders) or on a single bidder
+pub fn set_spending_limit() -> Result<()> {
+    let conn = establish_connection();
+    let args = ["organization or bidder (o/b)", "id", "limit (blank to clear)"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let id = args[1].parse::<i32>().chain_err(|| "id error")?;
+    let org_id = match args[0].as_str() {
+        "o" => id,
+        "b" => sql::get_bidder(&conn, id).ok_or("bidder not found")?.organization_id,
+        _ => bail!("expected 'o' or 'b'"),
+    };
+    let limit = if args[2].is_empty() { None } else {
+        Some(Money::parse(&args[2], org_currency(&conn, org_id)?).chain_err(|| "limit error")?)
+    };
+    let amount = limit.map(|l| l.amount);
+    let qs = if args[0] == "o" {
+        "update organizations set bidder_spending_limit=$1 where id=$2"
+    } else {
+        "update bidders set spending_limit=$1 where id=$2"
+    };
+    conn.execute(qs, &[&amount, &id]).chain_err(|| "Error setting spending limit")?;
+    match limit {
+        Some(limit) => println!("Spending limit set to {}", limit),
+        None => println!("Spending limit cleared"),
+    }
+    Ok(())
+}
+
+
+/// Place a pre-authorization hold on a bidder's card
+pub fn preauthorize_bidder() -> Result<payments::PaymentHold> {
+    println!("Pre-authorizing bidder...");
+    let conn = establish_connection();
+    let args = ["bidder_id", "payment_info_id", "amount (blank for organization default)"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let bidder = sql::get_bidder(&conn, args[0].parse::<i32>().chain_err(|| "bidder_id error")?)
+        .ok_or("bidder not found")?;
+    let payment_id = args[1].parse::<i32>().chain_err(|| "payment_info_id error")?;
+    let payment = sql::select_payment_info_for_bidder(&conn, bidder.id, payment_id)
+        .ok_or("payment info not found for this bidder")?;
+    let org = sql::get_organization(&conn, bidder.organization_id).ok_or("organization not found")?;
+    let currency = org.currency()?;
+    let amount = if args[2].is_empty() {
+        Money::new(org.preauth_amount.ok_or("organization has no default pre-authorization amount")?, currency)
+    } else {
+        Money::new(parse_amount(&args[2], currency, "amount")?, currency)
+    };
+    let gateway = payments::gateway_from_env()?;
+    let hold = payments::preauthorize(&conn, &*gateway, &bidder, &payment, &amount)?;
+    println!("Hold placed with id, reference: {}, {}", hold.id, hold.gateway_ref);
+    Ok(hold)
+}
+
+
+/// Number every bidder in an organization that doesn't have a paddle yet
+pub fn assign_paddles() -> Result<Vec<models::Bidder>> {
+    println!("Assigning paddle numbers...");
+    let conn = establish_connection();
+    let org_id = Prompter::new("$ Org id >> ")
+                         .capture()
+                         .chain_err(|| "Prompter Error")?
+                         .parse::<i32>()
+                         .chain_err(|| "Error parsing i32")?;
+    let bidders = sql::transaction(&conn, |conn| registration::assign_paddles(conn, org_id))?;
+    for bidder in bidders.iter() {
+        println!("paddle {:>4}: {}", bidder.paddle.unwrap_or(0), bidder.id_name);
+    }
+    println!("Assigned {} paddle numbers", bidders.len());
+    Ok(bidders)
+}
+
+
+/// Print the paddle sheet for an organization, or a single paddle
+pub fn print_paddles() -> Result<()> {
+    let conn = establish_connection();
+    let args = ["organization_id", "paddle (blank for all)"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let org_id = args[0].parse::<i32>().chain_err(|| "org_id error")?;
+    let paddle = if args[1].is_empty() { None } else {
+        Some(args[1].parse::<i32>().chain_err(|| "paddle error")?)
+    };
+    let mut bidders = sql::select_bidders_by_org(&conn, org_id).into_iter()
+        .filter(|b| b.paddle.is_some() && (paddle.is_none() || b.paddle == paddle))
+        .collect::<Vec<_>>();
+    if bidders.is_empty() {
+        bail!("no numbered bidders found");
+    }
+    println!("\n{:>6}  {:<30} {}", "paddle", "bidder", "primary");
+    bidders.sort_by_key(|b| b.paddle);
+    for bidder in bidders.iter() {
+        let primary = sql::select_primary_profile(&conn, bidder.id)
+            .map(|p| p.name)
+            .unwrap_or(String::new());
+        println!("{:>6}  {:<30} {}", bidder.paddle.unwrap_or(0), bidder.id_name, primary);
+    }
+    Ok(())
+}
+
+
+/// Check-in desk: look up guests and mark them as arrived
+pub fn check_in() -> Result<()> {
+    let conn = establish_connection();
+    loop {
+        let query = Prompter::new("$ name, email or paddle (blank to quit) >> ")
+                            .capture()
+                            .chain_err(|| "Prompter Error")?;
+        if query.is_empty() { return Ok(()); }
+        let guests = checkin::search(&conn, &query, None);
+        if guests.is_empty() {
+            println!("No guests found");
+            continue;
+        }
+        println!("\n{:>8} {:>6}  {:<24} {:<30} {:<5} {}",
+                 "profile", "paddle", "name", "email", "card", "checked in");
+        for guest in guests.iter() {
+            println!("{:>8} {:>6}  {:<24} {:<30} {:<5} {}",
+                     guest.profile_id, guest.paddle.map(|p| p.to_string()).unwrap_or(String::new()),
+                     guest.name, guest.email, if guest.has_payment_method { "yes" } else { "no" },
+                     guest.checked_in_at.map(|t| t.format("%H:%M").to_string()).unwrap_or(String::new()));
+        }
+        let profile_id = Prompter::new("$ profile to check in (blank to skip) >> ")
+                                 .capture()
+                                 .chain_err(|| "Prompter Error")?;
+        if profile_id.is_empty() { continue; }
+        let profile_id = profile_id.parse::<i32>().chain_err(|| "profile_id error")?;
+        let guest = checkin::check_in(&conn, None, profile_id)?;
+        println!("Checked in {} (paddle {})\n", guest.name,
+                 guest.paddle.map(|p| p.to_string()).unwrap_or("none".into()));
+    }
+}
+
+
+/// Create and check in a user, profile and bidder for a walk-in guest
+pub fn walk_in() -> Result<checkin::Guest> {
+    println!("Registering walk-in guest...");
+    let conn = establish_connection();
+    let args = ["organization_id", "email", "name", "phone"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let walk_in = checkin::WalkIn {
+        organization_id: args[0].parse::<i32>().chain_err(|| "org_id error")?,
+        email: args[1].clone(),
+        name: args[2].clone(),
+        phone: if args[3].is_empty() { None } else { Some(args[3].clone()) },
+    };
+    let guest = sql::transaction(&conn, |conn| checkin::walk_in(conn, None, walk_in))?;
+    println!("Checked in {} with paddle {}", guest.name,
+             guest.paddle.map(|p| p.to_string()).unwrap_or("none".into()));
+    Ok(guest)
+}
+
+
+/// Issue a new display board token for an organization and print the board url.
+/// Previously printed urls stop working.
+pub fn display_token() -> Result<()> {
+    let conn = establish_connection();
+    let org_id = Prompter::new("$ organization_id >> ").capture().expect("Prompter Error");
+    let org_id = org_id.parse::<i32>().chain_err(|| "org_id error")?;
+    let token = display::rotate_token(&conn, org_id)?;
+    println!("Display board url:\n{}/static/display.html?org={}&token={}",
+             mailer::public_url(), org_id, token);
+    Ok(())
+}
+
+
+/// Email watchers of items closing within the given number of minutes
+pub fn notify_closing() -> Result<()> {
+    println!("Sending closing soon notifications...");
+    let conn = establish_connection();
+    let minutes = Prompter::new("$ minutes >> ").capture().expect("Prompter Error");
+    let minutes = minutes.parse::<i64>().chain_err(|| "minutes error")?;
+    let mailer = mailer::mailer_from_env()?;
+    let sent = watchlist::notify_closing(&conn, &*mailer, Duration::minutes(minutes))?;
+    println!("Sent {} notifications", sent);
+    Ok(())
+}
+
+
+/// Enter paper bid sheets from a csv file of `paddle,item_id,amount,written_at`
+/// rows (amounts containing commas must be quoted). All rows are entered or
+/// none are.
+pub fn bulk_bids() -> Result<Vec<models::Bid>> {
+    println!("Entering paper bid sheets...");
+    let conn = establish_connection();
+    let args = ["organization_id", "csv file"].iter().map(|arg| {
+        Prompter::new(&format!("$ {} >> ", arg))
+                .capture()
+                .expect("Prompter Error")
+    }).collect::<Vec<_>>();
+    let org_id = args[0].parse::<i32>().chain_err(|| "org_id error")?;
+    let currency = org_currency(&conn, org_id)?;
+    let mut contents = String::new();
+    fs::File::open(&args[1]).chain_err(|| "Error opening csv file")?
+        .read_to_string(&mut contents).chain_err(|| "Error reading csv file")?;
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line_no = i + 1;
+        let fields = split_csv_line(line);
+        if fields.iter().all(|f| f.is_empty()) { continue; }
+        if fields.len() != 4 {
+            errors.push(format!("line {}: expected 4 fields", line_no));
+            continue;
+        }
+        // allow a header row
+        if line_no == 1 && fields[0].parse::<i32>().is_err() { continue; }
+        let parsed = fields[0].parse::<i32>().chain_err(|| "paddle error")
+            .and_then(|paddle| fields[1].parse::<i32>().chain_err(|| "item_id error").map(|item| (paddle, item)))
+            .and_then(|(paddle, item)| Money::parse(&fields[2], currency).map(|amount| (paddle, item, amount)))
+            .and_then(|(paddle, item, amount)| {
+                bidding::parse_written_at(&fields[3]).map(|written_at| bidding::PaperBid {
+                    row: line_no, paddle: paddle, item_id: item, amount: amount.amount, written_at: written_at,
+                })
+            });
+        match parsed {
+            Ok(row) => rows.push(row),
+            Err(err) => errors.push(format!("line {}: {}", line_no, err)),
+        }
+    }
+    if errors.is_empty() {
+        match sql::transaction(&conn, |conn| bidding::enter_paper_bids(conn, org_id, None, rows)) {
+            Ok(bids) => {
+                println!("Entered {} bids", bids.len());
+                return Ok(bids);
+            }
+            Err(Error(ErrorKind::InvalidRows(row_errors), _)) => {
+                errors.extend(row_errors.into_iter().map(|e| format!("line {}: {}", e.row, e.msg)));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    for err in errors.iter() {
+        println!("{}", err);
+    }
+    bail!("{} invalid rows, no bids were entered", errors.len())
+}
+
+
+/// Split a csv line on commas outside of double quotes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+
+/// Write year-end donation receipts as text and html files
+pub fn write_receipts() -> Result<()> {
+    println!("Generating receipts...");
+    let conn = establish_connection();
+    let args = ["organization_id", "year", "bidder_id (blank for all bidders)",
+                "output directory"].iter().map(|arg| {
+                    Prompter::new(&format!("$ {} >> ", arg))
+                            .capture()
+                            .expect("Prompter Error")
+                }).collect::<Vec<_>>();
+    let org_id = args[0].parse::<i32>().chain_err(|| "org_id error")?;
+    let year = args[1].parse::<i32>().chain_err(|| "year error")?;
+    let bidders = if args[2].is_empty() {
+        sql::select_bidders_by_org(&conn, org_id)
+    } else {
+        let bidder_id = args[2].parse::<i32>().chain_err(|| "bidder_id error")?;
+        vec![sql::get_bidder(&conn, bidder_id).ok_or("bidder not found")?]
+    };
+    let out_dir = Path::new(if args[3].is_empty() { "." } else { args[3].as_str() });
+    fs::create_dir_all(out_dir).chain_err(|| "Error creating output directory")?;
+
+    for bidder in bidders.iter().filter(|b| b.organization_id == org_id) {
+        let receipt = receipts::Receipt::build(&conn, bidder, year)?;
+        if receipt.is_empty() { continue; }
+        let name = format!("receipt-{}-{}", year, bidder.id);
+        for &(ext, ref body) in [("txt", receipt.to_text()), ("html", receipt.to_html())].iter() {
+            let path = out_dir.join(format!("{}.{}", name, ext));
+            let mut file = fs::File::create(&path).chain_err(|| "Error creating receipt file")?;
+            file.write_all(body.as_bytes()).chain_err(|| "Error writing receipt file")?;
+        }
+        println!("Receipt for bidder {}: {} deductible", bidder.id, receipt.total_deductible);
+    }
+    Ok(())
+}
+
+
+/// Look up the currency configured for an organization
+fn org_currency(conn: &Connection, org_id: i32) -> Result<Currency> {
+    sql::get_organization(conn, org_id)
+        .ok_or("organization not found")?
+        .currency()
+}
+
+
+/// Parse a prompted amount of `currency`, refusing negative amounts
+fn parse_amount(input: &str, currency: Currency, field: &str) -> Result<i64> {
+    let money = Money::parse(input, currency).chain_err(|| format!("{} error", field))?;
+    if money.is_negative() {
+        bail!(format!("{} must not be negative", field));
+    }
+    Ok(money.amount)
+}
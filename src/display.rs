@@ -0,0 +1,174 @@
+//! Display Board
+//!
+//! Live leaderboard for the big screen at an event: most active items,
+//! goal thermometers, total raised and a ticker of recent bids. Boards are
+//! read without a session, using a per-organization display token.
+//!
+use chrono;
+use postgres::Connection;
+
+use money::Money;
+use auth;
+use sql;
+use errors::*;
+
+
+/// Number of items shown in the leaderboard
+pub const TOP_ITEMS: i64 = 10;
+
+/// Number of bids shown in the recent bid ticker
+pub const RECENT_BIDS: i64 = 15;
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct TopItem {
+    pub item_id: i32,
+    pub title: String,
+    pub bid_count: i64,
+    pub high_bid: Option<Money>,
+    pub closes_at: Option<chrono::DateTime<chrono::UTC>>,
+}
+
+
+#[derive(Debug, RustcEncodable)]
+/// Progress of a goal item towards its `value`
+pub struct GoalProgress {
+    pub item_id: i32,
+    pub title: String,
+    pub raised: Money,
+    pub target: Money,
+    pub percent: i64,
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct RecentBid {
+    pub item_title: String,
+    pub bidder: String,
+    pub amount: Money,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct Board {
+    pub organization: String,
+    pub total_raised: Money,
+    pub top_items: Vec<TopItem>,
+    pub goals: Vec<GoalProgress>,
+    pub recent_bids: Vec<RecentBid>,
+    pub generated_at: chrono::DateTime<chrono::UTC>,
+}
+
+
+/// Set a new display token for an organization, returning it. Any
+/// previously issued display urls stop working.
+pub fn rotate_token(conn: &Connection, org_id: i32) -> Result<String> {
+    let token = auth::new_token()?;
+    let updated = conn.execute("update organizations set display_token_hash=$1 where id=$2",
+                               &[&auth::hash_token(&token), &org_id])
+        .chain_err(|| "Error setting display token")?;
+    if updated == 0 {
+        bail!("organization not found");
+    }
+    Ok(token)
+}
+
+
+/// Whether `token` is the organization's current display token
+pub fn check_token(conn: &Connection, org_id: i32, token: &str) -> bool {
+    let qs = "select display_token_hash from organizations where id=$1";
+    let rows = match conn.query(qs, &[&org_id]) {
+        Ok(rows) => rows,
+        Err(_) => return false,
+    };
+    let stored: Option<Vec<u8>> = rows.iter().next().and_then(|row| row.get(0));
+    match stored {
+        Some(hash) => auth::const_eq(&hash, &auth::hash_token(token)),
+        None => false,
+    }
+}
+
+
+/// Shorten a bidder's name for public display: "Jane Q. Smith" -> "J*** S."
+pub fn mask_name(name: &str) -> String {
+    let words = name.split_whitespace().collect::<Vec<_>>();
+    let initial = |word: &str| word.chars().next().map(|c| c.to_uppercase().collect::<String>()).unwrap_or_default();
+    match words.len() {
+        0 => "Anonymous".to_string(),
+        1 => format!("{}***", initial(words[0])),
+        n => format!("{}*** {}.", initial(words[0]), initial(words[n - 1])),
+    }
+}
+
+
+/// Build the current board for an organization
+pub fn board(conn: &Connection, org_id: i32) -> Result<Board> {
+    let org = sql::get_organization(conn, org_id).ok_or("organization not found")?;
+    let currency = org.currency()?;
+
+    let qs = "select i.id, i.title, count(b.id), max(b.amount), i.closes_at \
+              from items i left join bids b on b.item_id = i.id \
+              where i.organization_id=$1 and not i.is_goal and i.deleted_at is null \
+              group by i.id order by count(b.id) desc, max(b.amount) desc nulls last, i.id limit $2";
+    let rows = conn.query(qs, &[&org_id, &TOP_ITEMS]).chain_err(|| "Error selecting top items")?;
+    let top_items = rows.iter().map(|row| {
+        let high_bid: Option<i64> = row.get(3);
+        TopItem {
+            item_id: row.get(0),
+            title: row.get(1),
+            bid_count: row.get(2),
+            high_bid: high_bid.map(|amount| Money::new(amount, currency)),
+            closes_at: row.get(4),
+        }
+    }).collect::<Vec<_>>();
+
+    let qs = "select i.id, i.title, i.value, coalesce(sum(b.amount), 0)::bigint \
+              from items i left join bids b on b.item_id = i.id \
+              where i.organization_id=$1 and i.is_goal and i.deleted_at is null group by i.id order by i.id";
+    let rows = conn.query(qs, &[&org_id]).chain_err(|| "Error selecting goals")?;
+    let goals = rows.iter().map(|row| {
+        let target: i64 = row.get(2);
+        let raised: i64 = row.get(3);
+        let percent = if target > 0 { raised.saturating_mul(100) / target } else { 0 };
+        GoalProgress {
+            item_id: row.get(0),
+            title: row.get(1),
+            raised: Money::new(raised, currency),
+            target: Money::new(target, currency),
+            percent: percent,
+        }
+    }).collect::<Vec<_>>();
+
+    // high bids on regular items, plus every goal pledge
+    let qs = "select coalesce(sum(high.amount), 0)::bigint from \
+              (select distinct on (b.item_id) b.amount from bids b join items i on i.id = b.item_id \
+               where i.organization_id=$1 and not i.is_goal and i.deleted_at is null \
+               order by b.item_id, b.amount desc, b.date_created desc) high";
+    let rows = conn.query(qs, &[&org_id]).chain_err(|| "Error totaling high bids")?;
+    let high_total = Money::new(rows.iter().next().map(|row| row.get(0)).unwrap_or(0), currency);
+    let total_raised = Money::sum(currency, goals.iter().map(|g| &g.raised))?.checked_add(&high_total)?;
+
+    let qs = "select i.title, bd.id_name, b.amount, b.date_created from bids b \
+              join items i on i.id = b.item_id join bidders bd on bd.id = b.bidder_id \
+              where i.organization_id=$1 and i.deleted_at is null order by b.date_created desc limit $2";
+    let rows = conn.query(qs, &[&org_id, &RECENT_BIDS]).chain_err(|| "Error selecting recent bids")?;
+    let recent_bids = rows.iter().map(|row| {
+        let name: String = row.get(1);
+        RecentBid {
+            item_title: row.get(0),
+            bidder: mask_name(&name),
+            amount: Money::new(row.get(2), currency),
+            date_created: row.get(3),
+        }
+    }).collect();
+
+    Ok(Board {
+        organization: org.name,
+        total_raised: total_raised,
+        top_items: top_items,
+        goals: goals,
+        recent_bids: recent_bids,
+        generated_at: chrono::UTC::now(),
+    })
+}
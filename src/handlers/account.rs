@@ -0,0 +1,95 @@
+//! Account Handlers
+//!
+//! - `POST /me/password` json data containing the 'current_password' and a
+//!   'new_password'
+//! - `POST /me/email` json data containing the 'current_password' and the new
+//!   'email', which must be verified before it replaces the current one
+//!
+//! Both end the user's other sessions.
+use std::io::Read;
+
+use super::prelude::*;
+use account;
+
+
+#[derive(RustcDecodable)]
+struct ApiChangePassword {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiChangeEmail {
+    current_password: String,
+    email: String,
+}
+
+
+pub struct ChangePasswordHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl ChangePasswordHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> ChangePasswordHandler {
+        ChangePasswordHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for ChangePasswordHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let change: ApiChangePassword = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let result = sql::transaction(&conn, |conn| {
+            account::change_password(conn, &user, &change.current_password, &change.new_password)
+        });
+        match result {
+            Ok(()) => {
+                self.s_store.delete_others_by_request(user.id, &request);
+                Ok(Response::with((status::Ok, "password changed")))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
+
+
+pub struct ChangeEmailHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    mailer: SMailer,
+}
+impl ChangeEmailHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore, mailer: SMailer) -> ChangeEmailHandler {
+        ChangeEmailHandler { db_pool: db_pool, s_store: s_store, mailer: mailer }
+    }
+}
+impl Handler for ChangeEmailHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let change: ApiChangeEmail = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let mailer = &*self.mailer;
+        let result = sql::transaction(&conn, |conn| {
+            account::request_email_change(conn, mailer, &user, &change.current_password, &change.email)
+        });
+        match result {
+            Ok(()) => {
+                self.s_store.delete_others_by_request(user.id, &request);
+                Ok(Response::with((status::Accepted, "verification sent to the new address")))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Activity Handler
+//!
+//! GET /me/activity
+//!
+//! The current user's bidder's items winning, outbid on and won, their
+//! pledges, and what they owe.
+use chrono;
+
+use super::prelude::*;
+use super::items::{ApiItem, CurrencyCache};
+use money::{Money, Currency};
+use activity::{self, ItemActivity, Pledge};
+
+
+#[derive(RustcEncodable)]
+struct ApiItemActivity {
+    item: ApiItem,
+    my_bid: Money,
+    high_bid: Money,
+}
+impl ApiItemActivity {
+    fn new(act: ItemActivity, currency: Currency) -> ApiItemActivity {
+        ApiItemActivity {
+            my_bid: Money::new(act.my_bid, currency),
+            high_bid: Money::new(act.high_bid, currency),
+            item: ApiItem::new(act.item, currency),
+        }
+    }
+}
+
+#[derive(RustcEncodable)]
+struct ApiPledge {
+    bid_id: i32,
+    item_id: i32,
+    title: String,
+    amount: Money,
+    date_created: chrono::DateTime<chrono::UTC>,
+}
+impl ApiPledge {
+    fn new(pledge: Pledge, currency: Currency) -> ApiPledge {
+        ApiPledge {
+            bid_id: pledge.bid_id, item_id: pledge.item_id, title: pledge.title,
+            amount: Money::new(pledge.amount, currency), date_created: pledge.date_created,
+        }
+    }
+}
+
+#[derive(RustcEncodable)]
+struct ApiActivity {
+    bidder_id: i32,
+    winning: Vec<ApiItemActivity>,
+    outbid: Vec<ApiItemActivity>,
+    won: Vec<ApiItemActivity>,
+    pledges: Vec<ApiPledge>,
+    total_owed: Money,
+    winning_total: Money,
+}
+
+
+pub struct ActivityHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl ActivityHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> ActivityHandler {
+        ActivityHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for ActivityHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let bidder = match sql::select_profile_by_user(&conn, &user.id)
+            .and_then(|p| p.bidder_id)
+            .and_then(|id| sql::get_bidder(&conn, id)) {
+            Some(bidder) => bidder,
+            None => return Ok(Response::with((status::NotFound, "no bidder registered for user"))),
+        };
+        let act = try_server_error!(activity::for_bidder(&conn, bidder.id));
+        let currency = match CurrencyCache::new(&conn).get(bidder.organization_id) {
+            Ok(currency) => currency,
+            Err(err) => return error_response(status::InternalServerError, &err),
+        };
+
+        let convert = |acts: Vec<ItemActivity>| {
+            acts.into_iter().map(|act| ApiItemActivity::new(act, currency)).collect::<Vec<_>>()
+        };
+        let resp = ApiActivity {
+            bidder_id: act.bidder_id,
+            winning: convert(act.winning),
+            outbid: convert(act.outbid),
+            won: convert(act.won),
+            pledges: act.pledges.into_iter().map(|p| ApiPledge::new(p, currency)).collect(),
+            total_owed: Money::new(act.total_owed, currency),
+            winning_total: Money::new(act.winning_total, currency),
+        };
+        Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+    }
+}
@@ -0,0 +1,389 @@
+//! Admin Handlers
+//!
+//! JSON crud for organizations, items, bidders and profiles:
+//!
+//! - `GET    /admin/<resource>`      list (items, bidders and profiles take `?organization_id=`)
+//! - `POST   /admin/<resource>`      create
+//! - `GET    /admin/<resource>/:id`  read
+//! - `PUT    /admin/<resource>/:id`  partial update
+//! - `DELETE /admin/<resource>/:id`  delete (organizations and items are soft deleted)
+//!
+//! Creating or deleting organizations requires `ManageOrganizations`;
+//! everything else requires `ManageOrganization` in the record's organization.
+//! Invalid input is rejected with a 400 listing every field error; deletes
+//! of records still in use, and changes to the pricing, goal flag or
+//! organization of items that already have bids, are rejected with a 409.
+use std::io::Read;
+use chrono;
+use postgres::Connection;
+use rustc_serialize::json::Json;
+
+use super::prelude::*;
+use super::items::ApiItem;
+use money::{Money, Currency};
+use admin::{self, OrgInput, ItemInput, BidderInput, ProfileInput};
+use validation::Validator;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Resource {
+    Organizations,
+    Items,
+    Bidders,
+    Profiles,
+}
+
+
+#[derive(RustcEncodable)]
+struct ApiOrg {
+    id: i32,
+    name: String,
+    extra: Option<Json>,
+    currency: String,
+    bidder_spending_limit: Option<Money>,
+    preauth_amount: Option<Money>,
+    date_created: chrono::DateTime<chrono::UTC>,
+    date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl ApiOrg {
+    fn new(org: models::Organization) -> Result<ApiOrg> {
+        let currency = org.currency()?;
+        Ok(ApiOrg {
+            currency: currency.code.to_string(),
+            bidder_spending_limit: org.bidder_spending_limit.map(|a| Money::new(a, currency)),
+            preauth_amount: org.preauth_amount.map(|a| Money::new(a, currency)),
+            id: org.id, name: org.name, extra: org.extra,
+            date_created: org.date_created, date_modified: org.date_modified,
+        })
+    }
+}
+
+#[derive(RustcEncodable)]
+struct ApiBidder {
+    id: i32,
+    organization_id: i32,
+    id_name: String,
+    paddle: Option<i32>,
+    spending_limit: Option<Money>,
+    terms_accepted_at: Option<chrono::DateTime<chrono::UTC>>,
+    date_created: chrono::DateTime<chrono::UTC>,
+    date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl ApiBidder {
+    fn new(bidder: models::Bidder, currency: Currency) -> ApiBidder {
+        ApiBidder {
+            spending_limit: bidder.spending_limit.map(|a| Money::new(a, currency)),
+            id: bidder.id, organization_id: bidder.organization_id, id_name: bidder.id_name,
+            paddle: bidder.paddle, terms_accepted_at: bidder.terms_accepted_at,
+            date_created: bidder.date_created, date_modified: bidder.date_modified,
+        }
+    }
+}
+
+/// Decoded request body for a resource
+enum Input {
+    Org(OrgInput),
+    Item(ItemInput),
+    Bidder(BidderInput),
+    Profile(ProfileInput),
+}
+impl Input {
+    /// Organization the input places its record in, if it names one
+    fn organization_id(&self, conn: &Connection) -> Option<i32> {
+        match *self {
+            Input::Org(_) => None,
+            Input::Item(ref input) => input.organization_id,
+            Input::Bidder(ref input) => input.organization_id,
+            Input::Profile(ref input) => org_of_bidder(conn, input.bidder_id),
+        }
+    }
+}
+
+
+/// A record to send back, encoded with its organization's currency
+enum Output {
+    Org(models::Organization),
+    Item(models::Item),
+    Bidder(models::Bidder),
+    Profile(models::Profile),
+}
+impl Output {
+    fn encode(self, conn: &Connection) -> Result<String> {
+        Ok(match self {
+            Output::Org(org) => json::encode(&ApiOrg::new(org)?).unwrap(),
+            Output::Item(item) => {
+                let currency = currency(conn, item.organization_id)?;
+                json::encode(&ApiItem::new(item, currency)).unwrap()
+            }
+            Output::Bidder(bidder) => {
+                let currency = currency(conn, bidder.organization_id)?;
+                json::encode(&ApiBidder::new(bidder, currency)).unwrap()
+            }
+            Output::Profile(profile) => json::encode(&profile).unwrap(),
+        })
+    }
+
+    fn respond(self, conn: &Connection, status: status::Status) -> IronResult<Response> {
+        match self.encode(conn) {
+            Ok(body) => Ok(Response::with((status, body))),
+            Err(err) => error_response(status::InternalServerError, &err),
+        }
+    }
+}
+
+
+#[derive(RustcEncodable)]
+struct List<T> {
+    results: Vec<T>,
+}
+
+
+fn currency(conn: &Connection, org_id: i32) -> Result<Currency> {
+    sql::get_organization(conn, org_id).ok_or("organization not found")?.currency()
+}
+
+fn ok_json<T: ::rustc_serialize::Encodable>(status: status::Status, body: &T) -> IronResult<Response> {
+    Ok(Response::with((status, json::encode(body).unwrap())))
+}
+
+fn not_found() -> IronResult<Response> {
+    Ok(Response::with((status::NotFound, "not found")))
+}
+
+fn org_of_bidder(conn: &Connection, bidder_id: Option<i32>) -> Option<i32> {
+    bidder_id.and_then(|id| sql::get_bidder(conn, id)).map(|bidder| bidder.organization_id)
+}
+
+
+pub struct AdminHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    resource: Resource,
+    member: bool,
+}
+impl AdminHandler {
+    /// Handler for `/admin/<resource>`
+    pub fn collection(db_pool: PgPool, s_store: SStore, resource: Resource) -> AdminHandler {
+        AdminHandler { db_pool: db_pool, s_store: s_store, resource: resource, member: false }
+    }
+    /// Handler for `/admin/<resource>/:id`
+    pub fn member(db_pool: PgPool, s_store: SStore, resource: Resource) -> AdminHandler {
+        AdminHandler { db_pool: db_pool, s_store: s_store, resource: resource, member: true }
+    }
+}
+impl Handler for AdminHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+
+        let method = request.method.clone();
+        if !self.member {
+            return match method {
+                Method::Get => {
+                    let org_id = query_param(request, "organization_id").and_then(|id| id.parse::<i32>().ok());
+                    self.list(&conn, &user, org_id)
+                }
+                Method::Post => self.create(&conn, &user, &req_body),
+                _ => Ok(Response::with(status::MethodNotAllowed)),
+            };
+        }
+
+        let id = match path_id(request, "id") {
+            Some(id) => id,
+            None => return not_found(),
+        };
+        match method {
+            Method::Get => self.read(&conn, &user, id),
+            Method::Put => self.update(&conn, &user, id, &req_body),
+            Method::Delete => self.delete(&conn, &user, id),
+            _ => Ok(Response::with(status::MethodNotAllowed)),
+        }
+    }
+}
+impl AdminHandler {
+    /// Organization a record belongs to, `None` for organizations-less profiles.
+    /// The outer `None` means the record doesn't exist.
+    fn scope(&self, conn: &Connection, id: i32) -> Option<Option<i32>> {
+        match self.resource {
+            Resource::Organizations => sql::get_organization(conn, id).map(|org| Some(org.id)),
+            Resource::Items => sql::get_item(conn, id).map(|item| Some(item.organization_id)),
+            Resource::Bidders => sql::get_bidder(conn, id).map(|bidder| Some(bidder.organization_id)),
+            Resource::Profiles => sql::get_profile(conn, id).map(|profile| org_of_bidder(conn, profile.bidder_id)),
+        }
+    }
+
+    fn list(&self, conn: &Connection, user: &models::User, org_id: Option<i32>) -> IronResult<Response> {
+        if self.resource == Resource::Organizations {
+            let orgs = sql::select_organizations(conn).into_iter()
+                .filter(|org| roles::has_permission(conn, user, Permission::ManageOrganization, Some(org.id)))
+                .map(ApiOrg::new)
+                .collect::<Result<Vec<_>>>();
+            return match orgs {
+                Ok(orgs) => ok_json(status::Ok, &List { results: orgs }),
+                Err(err) => error_response(status::InternalServerError, &err),
+            };
+        }
+
+        let mut v = Validator::new();
+        let org_id = v.required("organization_id", org_id);
+        if let Err(err) = v.finish() {
+            return error_response(status::BadRequest, &err);
+        }
+        let org_id = org_id.unwrap();
+        if let Err(err) = roles::require(conn, user, Permission::ManageOrganization, Some(org_id)) {
+            return error_response(status::Forbidden, &err);
+        }
+        let currency = match currency(conn, org_id) {
+            Ok(currency) => currency,
+            Err(err) => return error_response(status::InternalServerError, &err),
+        };
+        match self.resource {
+            Resource::Items => {
+                let items = sql::select_items_by_org(conn, org_id).into_iter()
+                    .map(|item| ApiItem::new(item, currency)).collect();
+                ok_json(status::Ok, &List { results: items })
+            }
+            Resource::Bidders => {
+                let bidders = sql::select_bidders_by_org(conn, org_id).into_iter()
+                    .map(|bidder| ApiBidder::new(bidder, currency)).collect();
+                ok_json(status::Ok, &List { results: bidders })
+            }
+            Resource::Profiles => ok_json(status::Ok, &List { results: sql::select_profiles_by_org(conn, org_id) }),
+            Resource::Organizations => unreachable!(),
+        }
+    }
+
+    fn read(&self, conn: &Connection, user: &models::User, id: i32) -> IronResult<Response> {
+        let scope = match self.scope(conn, id) {
+            Some(scope) => scope,
+            None => return not_found(),
+        };
+        if let Err(err) = roles::require(conn, user, Permission::ManageOrganization, scope) {
+            return error_response(status::Forbidden, &err);
+        }
+        let output = match self.resource {
+            Resource::Organizations => Output::Org(sql::get_organization(conn, id).unwrap()),
+            Resource::Items => Output::Item(sql::get_item(conn, id).unwrap()),
+            Resource::Bidders => Output::Bidder(sql::get_bidder(conn, id).unwrap()),
+            Resource::Profiles => Output::Profile(sql::get_profile(conn, id).unwrap()),
+        };
+        output.respond(conn, status::Ok)
+    }
+
+    fn decode(&self, body: &str) -> Result<Input> {
+        Ok(match self.resource {
+            Resource::Organizations => Input::Org(OrgInput::from_json(body)?),
+            Resource::Items => Input::Item(ItemInput::from_json(body)?),
+            Resource::Bidders => Input::Bidder(BidderInput::from_json(body)?),
+            Resource::Profiles => Input::Profile(ProfileInput::from_json(body)?),
+        })
+    }
+
+    fn create(&self, conn: &Connection, user: &models::User, body: &str) -> IronResult<Response> {
+        let input = match self.decode(body) {
+            Ok(input) => input,
+            Err(err) => return error_response(status::BadRequest, &err),
+        };
+        let (permission, scope) = match input {
+            Input::Org(_) => (Permission::ManageOrganizations, None),
+            _ => (Permission::ManageOrganization, input.organization_id(conn)),
+        };
+        if let Err(err) = roles::require(conn, user, permission, scope) {
+            return error_response(status::Forbidden, &err);
+        }
+
+        let result = match input {
+            Input::Org(input) => admin::create_org(conn, input).map(Output::Org),
+            Input::Item(input) => admin::create_item(conn, input).map(Output::Item),
+            Input::Bidder(input) => admin::create_bidder(conn, input).map(Output::Bidder),
+            Input::Profile(input) => admin::create_profile(conn, input).map(Output::Profile),
+        };
+        match result {
+            Ok(output) => output.respond(conn, status::Created),
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+
+    fn update(&self, conn: &Connection, user: &models::User, id: i32, body: &str) -> IronResult<Response> {
+        let scope = match self.scope(conn, id) {
+            Some(scope) => scope,
+            None => return not_found(),
+        };
+        if let Err(err) = roles::require(conn, user, Permission::ManageOrganization, scope) {
+            return error_response(status::Forbidden, &err);
+        }
+        let input = match self.decode(body) {
+            Ok(input) => input,
+            Err(err) => return error_response(status::BadRequest, &err),
+        };
+
+        // moving a record to another organization needs permission there too
+        let new_scope = match input {
+            Input::Org(_) => scope,
+            _ => input.organization_id(conn).or(scope),
+        };
+        if new_scope != scope {
+            if let Err(err) = roles::require(conn, user, Permission::ManageOrganization, new_scope) {
+                return error_response(status::Forbidden, &err);
+            }
+        }
+
+        let result = match input {
+            Input::Org(input) => {
+                let org = sql::get_organization(conn, id).unwrap();
+                admin::update_org(conn, org, input).map(Output::Org)
+            }
+            Input::Item(input) => {
+                let item = sql::get_item(conn, id).unwrap();
+                admin::update_item(conn, item, input).map(Output::Item)
+            }
+            Input::Bidder(input) => {
+                let bidder = sql::get_bidder(conn, id).unwrap();
+                admin::update_bidder(conn, bidder, input).map(Output::Bidder)
+            }
+            Input::Profile(input) => {
+                let profile = sql::get_profile(conn, id).unwrap();
+                admin::update_profile(conn, profile, input).map(Output::Profile)
+            }
+        };
+        match result {
+            Ok(output) => output.respond(conn, status::Ok),
+            Err(err) => match *err.kind() {
+                ErrorKind::ItemHasBids(..) | ErrorKind::BidderHasBids(..) =>
+                    error_response(status::Conflict, &err),
+                _ => error_response(status::BadRequest, &err),
+            },
+        }
+    }
+
+    fn delete(&self, conn: &Connection, user: &models::User, id: i32) -> IronResult<Response> {
+        let scope = match self.scope(conn, id) {
+            Some(scope) => scope,
+            None => return not_found(),
+        };
+        let permission = match self.resource {
+            Resource::Organizations => Permission::ManageOrganizations,
+            _ => Permission::ManageOrganization,
+        };
+        let scope = if permission == Permission::ManageOrganizations { None } else { scope };
+        if let Err(err) = roles::require(conn, user, permission, scope) {
+            return error_response(status::Forbidden, &err);
+        }
+
+        let result = match self.resource {
+            Resource::Organizations => sql::get_organization(conn, id).unwrap().delete(conn),
+            Resource::Items => sql::get_item(conn, id).unwrap().delete(conn),
+            Resource::Bidders => sql::get_bidder(conn, id).unwrap().delete(conn),
+            Resource::Profiles => sql::get_profile(conn, id).unwrap().delete(conn),
+        };
+        match result {
+            Ok(()) => Ok(Response::with(status::NoContent)),
+            Err(err) => error_response(status::Conflict, &err),
+        }
+    }
+}
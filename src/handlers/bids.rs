@@ -0,0 +1,107 @@
+//! Bid Handlers
+//!
+//! Accepts POSTs with json data containing an 'item_id' and an 'amount'
+//! (e.g. "$1,250.00") to be bid on behalf of the current user's bidder.
+//! Users must have verified their email address to bid.
+use std::io::Read;
+use chrono;
+
+use super::prelude::*;
+use money::Money;
+use bidding;
+use watchlist;
+
+
+#[derive(RustcDecodable)]
+struct ApiBid {
+    item_id: i32,
+    amount: String,
+}
+
+#[derive(RustcEncodable)]
+struct BidPlaced {
+    id: i32,
+    bidder_id: i32,
+    item_id: i32,
+    amount: Money,
+    date_created: chrono::DateTime<chrono::UTC>,
+}
+
+
+pub struct PlaceBidHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    mailer: SMailer,
+}
+impl PlaceBidHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore, mailer: SMailer) -> PlaceBidHandler {
+        PlaceBidHandler { db_pool: db_pool, s_store: s_store, mailer: mailer }
+    }
+}
+impl Handler for PlaceBidHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let api_bid: ApiBid = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        if !user.is_verified() {
+            let err: Error = ErrorKind::EmailNotVerified.into();
+            return error_response(status::Forbidden, &err);
+        }
+        let bidder = match sql::select_profile_by_user(&conn, &user.id)
+                .and_then(|p| p.bidder_id)
+                .and_then(|id| sql::get_bidder(&conn, id)) {
+            Some(bidder) => bidder,
+            None => return Ok(Response::with((status::Forbidden, "no bidder registered for user"))),
+        };
+
+        let item = match sql::get_item(&conn, api_bid.item_id) {
+            Some(item) => item,
+            None => return Ok(Response::with((status::NotFound, "item not found"))),
+        };
+        if bidder.organization_id != item.organization_id {
+            return Ok(Response::with((status::Forbidden, "bidder is not registered with this item's organization")));
+        }
+        if let Err(err) = roles::require(&conn, &user, Permission::PlaceBids, Some(item.organization_id)) {
+            return error_response(status::Forbidden, &err);
+        }
+        let currency = try_server_error!(
+            sql::get_organization(&conn, item.organization_id).ok_or("organization not found")
+                .map_err(Error::from)
+                .and_then(|org| org.currency()));
+        let amount = match Money::parse(&api_bid.amount, currency) {
+            Ok(amount) => amount,
+            Err(err) => return error_response(status::BadRequest, &err),
+        };
+
+        let new_bid = models::NewBid::new(bidder.id, item.id, amount.amount);
+        match sql::transaction(&conn, |conn| bidding::place_bid(conn, new_bid)) {
+            Ok(bid) => {
+                // the bid stands even if watchers can't be emailed
+                if let Err(err) = watchlist::notify_outbid(&conn, &*self.mailer, &bid) {
+                    println!("Error sending outbid notifications for bid {}: {}", bid.id, err);
+                }
+                let placed = BidPlaced {
+                    id: bid.id, bidder_id: bid.bidder_id, item_id: bid.item_id,
+                    amount: Money::new(bid.amount, currency), date_created: bid.date_created,
+                };
+                Ok(Response::with((status::Ok, json::encode(&placed).unwrap())))
+            }
+            Err(err) => match *err.kind() {
+                ErrorKind::BidTooLow(..) | ErrorKind::ItemClosed(..) |
+                ErrorKind::SpendingLimitExceeded(..) | ErrorKind::MoneyOverflow => {
+                    error_response(status::BadRequest, &err)
+                }
+                _ => {
+                    println!("Error placing bid on item {}: {}", item.id, err);
+                    Ok(Response::with((status::InternalServerError, "unknown error")))
+                }
+            },
+        }
+    }
+}
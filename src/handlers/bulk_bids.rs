@@ -0,0 +1,102 @@
+//! Bulk bid entry handler
+//!
+//! Accepts POSTs from users with the `EnterBids` permission, with json data containing an
+//! 'organization_id' and a list of paper bid sheet 'rows', each with a
+//! 'paddle', 'item_id', 'amount' (e.g. "$1,250.00") and 'written_at' time.
+//! Rows are inserted all together or not at all; row-level errors are
+//! returned for anything invalid.
+use std::io::Read;
+
+use super::prelude::*;
+use money::Money;
+use bidding::{self, PaperBid, RowError};
+
+
+#[derive(RustcDecodable)]
+struct ApiRow {
+    paddle: i32,
+    item_id: i32,
+    amount: String,
+    written_at: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiBidSheet {
+    organization_id: i32,
+    rows: Vec<ApiRow>,
+}
+
+#[derive(RustcEncodable)]
+struct Entered {
+    entered: usize,
+}
+
+#[derive(RustcEncodable)]
+struct Rejected {
+    error: String,
+    rows: Vec<RowError>,
+}
+
+
+fn rejected(rows: Vec<RowError>) -> IronResult<Response> {
+    let rejected = Rejected { error: "invalid_rows".into(), rows: rows };
+    Ok(Response::with((status::BadRequest, json::encode(&rejected).unwrap())))
+}
+
+
+pub struct BulkBidsHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl BulkBidsHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> BulkBidsHandler {
+        BulkBidsHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for BulkBidsHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let sheet: ApiBidSheet = try_server_error!(json::decode(&req_body));
+        if let Err(err) = roles::require(&conn, &user, Permission::EnterBids, Some(sheet.organization_id)) {
+            return error_response(status::Forbidden, &err);
+        }
+        let currency = match sql::get_organization(&conn, sheet.organization_id) {
+            Some(org) => try_server_error!(org.currency()),
+            None => return Ok(Response::with((status::NotFound, "organization not found"))),
+        };
+
+        // row numbers reported back are 1-based positions in the submitted list
+        let mut errors = vec![];
+        let mut rows = vec![];
+        for (i, row) in sheet.rows.into_iter().enumerate() {
+            let amount = Money::parse(&row.amount, currency);
+            let written_at = bidding::parse_written_at(&row.written_at);
+            match (amount, written_at) {
+                (Ok(amount), Ok(written_at)) => rows.push(PaperBid {
+                    row: i + 1, paddle: row.paddle, item_id: row.item_id,
+                    amount: amount.amount, written_at: written_at,
+                }),
+                (Err(err), _) | (_, Err(err)) => errors.push(RowError { row: i + 1, msg: err.to_string() }),
+            }
+        }
+
+        if !errors.is_empty() {
+            return rejected(errors);
+        }
+        let org_id = sheet.organization_id;
+        match sql::transaction(&conn, |conn| bidding::enter_paper_bids(conn, org_id, Some(&user), rows)) {
+            Ok(bids) => Ok(Response::with((status::Ok, json::encode(&Entered { entered: bids.len() }).unwrap()))),
+            Err(err) => match *err.kind() {
+                ErrorKind::InvalidRows(ref rows) => rejected(rows.clone()),
+                _ => error_response(status::InternalServerError, &err),
+            },
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Check-in Handlers
+//!
+//! Volunteer check-in desk: guest search, check-in and walk-in registration.
+//! All of these require the `CheckIn` permission in the guest's organization.
+use std::io::Read;
+
+use super::prelude::*;
+use checkin::{self, WalkIn};
+
+
+#[derive(RustcDecodable)]
+struct ApiSearch {
+    query: String,
+    organization_id: Option<i32>,
+}
+
+#[derive(RustcDecodable)]
+struct ApiCheckIn {
+    profile_id: i32,
+}
+
+#[derive(RustcDecodable)]
+struct ApiWalkIn {
+    organization_id: i32,
+    email: String,
+    name: String,
+    phone: Option<String>,
+}
+
+#[derive(RustcEncodable)]
+struct Guests {
+    guests: Vec<checkin::Guest>,
+}
+
+
+#[derive(Clone, Copy)]
+enum CheckInAction {
+    Search,
+    CheckIn,
+    WalkIn,
+}
+
+
+pub struct CheckInHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    action: CheckInAction,
+}
+impl CheckInHandler {
+    pub fn search(db_pool: PgPool, s_store: SStore) -> CheckInHandler {
+        CheckInHandler { db_pool: db_pool, s_store: s_store, action: CheckInAction::Search }
+    }
+    pub fn check_in(db_pool: PgPool, s_store: SStore) -> CheckInHandler {
+        CheckInHandler { db_pool: db_pool, s_store: s_store, action: CheckInAction::CheckIn }
+    }
+    pub fn walk_in(db_pool: PgPool, s_store: SStore) -> CheckInHandler {
+        CheckInHandler { db_pool: db_pool, s_store: s_store, action: CheckInAction::WalkIn }
+    }
+}
+impl Handler for CheckInHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let result = match self.action {
+            CheckInAction::Search => {
+                let search: ApiSearch = try_server_error!(json::decode(&req_body));
+                if let Err(err) = roles::require(&conn, &user, Permission::CheckIn, search.organization_id) {
+                    return error_response(status::Forbidden, &err);
+                }
+                let guests = Guests { guests: checkin::search(&conn, &search.query, search.organization_id) };
+                return Ok(Response::with((status::Ok, json::encode(&guests).unwrap())));
+            }
+            CheckInAction::CheckIn => {
+                let check_in: ApiCheckIn = try_server_error!(json::decode(&req_body));
+                let org_id = sql::get_profile(&conn, check_in.profile_id)
+                    .and_then(|p| p.bidder_id)
+                    .and_then(|id| sql::get_bidder(&conn, id))
+                    .map(|bidder| bidder.organization_id);
+                if let Err(err) = roles::require(&conn, &user, Permission::CheckIn, org_id) {
+                    return error_response(status::Forbidden, &err);
+                }
+                checkin::check_in(&conn, Some(&user), check_in.profile_id)
+            }
+            CheckInAction::WalkIn => {
+                let api_walk_in: ApiWalkIn = try_server_error!(json::decode(&req_body));
+                if let Err(err) = roles::require(&conn, &user, Permission::CheckIn, Some(api_walk_in.organization_id)) {
+                    return error_response(status::Forbidden, &err);
+                }
+                let walk_in = WalkIn {
+                    organization_id: api_walk_in.organization_id,
+                    email: api_walk_in.email,
+                    name: api_walk_in.name,
+                    phone: api_walk_in.phone,
+                };
+                sql::transaction(&conn, |conn| checkin::walk_in(conn, Some(&user), walk_in))
+            }
+        };
+        match result {
+            Ok(guest) => Ok(Response::with((status::Ok, json::encode(&guest).unwrap()))),
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Display Board Handler
+//!
+//! GET /display/:org_id?token=<display token>
+//!
+//! Exempt from the session middleware: the organization's display token
+//! is the only credential, so the board can run on an unattended screen.
+use super::prelude::*;
+use display;
+
+
+pub struct DisplayHandler {
+    db_pool: PgPool,
+}
+impl DisplayHandler {
+    pub fn new(db_pool: PgPool) -> DisplayHandler {
+        DisplayHandler { db_pool: db_pool }
+    }
+}
+impl Handler for DisplayHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let org_id = match path_id(request, "org_id") {
+            Some(id) => id,
+            None => return Ok(Response::with((status::NotFound, "organization not found"))),
+        };
+        let token = query_param(request, "token");
+
+        let conn = self.db_pool.get().unwrap();
+        match token {
+            Some(ref token) if display::check_token(&conn, org_id, token) => (),
+            _ => return unauthorized(Some("invalid display token".to_string())),
+        }
+        match display::board(&conn, org_id) {
+            Ok(board) => Ok(Response::with((status::Ok, json::encode(&board).unwrap()))),
+            Err(err) => error_response(status::InternalServerError, &err),
+        }
+    }
+}
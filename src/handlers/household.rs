@@ -0,0 +1,186 @@
+//! Household Handlers
+//!
+//! Membership of a shared bidder: list members, invite by email,
+//! accept an invitation, remove a member and transfer primary status.
+use std::io::Read;
+
+use super::prelude::*;
+use household;
+
+
+#[derive(RustcEncodable)]
+struct Member {
+    profile_id: i32,
+    name: String,
+    is_primary: bool,
+}
+
+#[derive(RustcEncodable)]
+struct Household {
+    bidder_id: i32,
+    members: Vec<Member>,
+}
+impl Household {
+    fn new(bidder_id: i32, profiles: Vec<models::Profile>) -> Household {
+        Household {
+            bidder_id: bidder_id,
+            members: profiles.into_iter().map(|p| {
+                Member { profile_id: p.id, name: p.name, is_primary: p.is_primary }
+            }).collect(),
+        }
+    }
+}
+
+#[derive(RustcDecodable)]
+struct ApiInvite {
+    email: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiAccept {
+    token: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiMember {
+    profile_id: i32,
+}
+
+
+/// Return the current bidder's members
+pub struct HouseholdHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl HouseholdHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> HouseholdHandler {
+        HouseholdHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for HouseholdHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let bidder_id = match sql::select_profile_by_user(&conn, &user.id).and_then(|p| p.bidder_id) {
+            Some(id) => id,
+            None => return Ok(Response::with((status::NotFound, "no bidder registered for user"))),
+        };
+        let resp = Household::new(bidder_id, household::members(&conn, bidder_id));
+        Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+    }
+}
+
+
+/// Invite an email address to join the current (primary) user's bidder
+pub struct InviteHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    mailer: SMailer,
+}
+impl InviteHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore, mailer: SMailer) -> InviteHandler {
+        InviteHandler { db_pool: db_pool, s_store: s_store, mailer: mailer }
+    }
+}
+impl Handler for InviteHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let invite: ApiInvite = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        match household::invite(&conn, &*self.mailer, &user, &invite.email) {
+            Ok(invitation) => {
+                let msg = Msg { msg: format!("invitation sent to {}", invitation.email) };
+                Ok(Response::with((status::Ok, json::encode(&msg).unwrap())))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
+
+
+/// Accept an invitation token, joining the inviting bidder
+pub struct AcceptInviteHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl AcceptInviteHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> AcceptInviteHandler {
+        AcceptInviteHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for AcceptInviteHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let accept: ApiAccept = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        match sql::transaction(&conn, |conn| household::accept(conn, &user, &accept.token)) {
+            Ok(profile) => {
+                let bidder_id = profile.bidder_id.unwrap();
+                let resp = Household::new(bidder_id, household::members(&conn, bidder_id));
+                Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
+
+
+/// Remove a member from, or hand primary status to a member of,
+/// the current (primary) user's bidder
+pub struct ManageMemberHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    transfer: bool,
+}
+impl ManageMemberHandler {
+    pub fn remove(db_pool: PgPool, s_store: SStore) -> ManageMemberHandler {
+        ManageMemberHandler { db_pool: db_pool, s_store: s_store, transfer: false }
+    }
+    pub fn transfer(db_pool: PgPool, s_store: SStore) -> ManageMemberHandler {
+        ManageMemberHandler { db_pool: db_pool, s_store: s_store, transfer: true }
+    }
+}
+impl Handler for ManageMemberHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let member: ApiMember = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let bidder_id = sql::select_profile_by_user(&conn, &user.id).and_then(|p| p.bidder_id);
+        let result = sql::transaction(&conn, |conn| {
+            if self.transfer {
+                household::transfer_primary(conn, &user, member.profile_id)
+            } else {
+                household::remove_member(conn, &user, member.profile_id)
+            }
+        });
+        match result {
+            Ok(_) => {
+                let bidder_id = bidder_id.unwrap();
+                let resp = Household::new(bidder_id, household::members(&conn, bidder_id));
+                Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
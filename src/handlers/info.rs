@@ -16,11 +16,18 @@ impl InfoHandler {
 }
 
 
+#[derive(RustcEncodable)]
+struct ApiRole {
+    role: String,
+    organization_id: Option<i32>,
+}
+
 #[derive(RustcEncodable)]
 struct Info {
     email: String,
     uuid_: Uuid,
-    level_: i32,
+    verified: bool,
+    roles: Vec<ApiRole>,
     is_primary: bool,
     name: String,
     phone: Option<String>,
@@ -29,18 +36,21 @@ struct Info {
 
 impl Handler for InfoHandler {
     fn handle(&self, request: &mut Request) -> IronResult<Response> {
-        let mut store = self.store.lock().unwrap();
-        let session = store.get_mut_from_request(&request);
-
-        let id = session.and_then(|sess| sess.user_id).unwrap();
         let conn = self.pool.get().unwrap();
-        let user = sql::get_user(&conn, id).unwrap();
+        let user = match self.store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
         let profile = sql::select_profile_by_user(&conn, &user.id).unwrap();
 
+        let roles = roles::user_roles(&conn, user.id).into_iter().map(|r| {
+            ApiRole { role: r.role, organization_id: r.organization_id }
+        }).collect();
         let info = Info {
+            verified: user.is_verified(),
             email: user.email,
             uuid_: user.uuid_,
-            level_: user.level_,
+            roles: roles,
             is_primary: profile.is_primary,
             name: profile.name,
             phone: profile.phone,
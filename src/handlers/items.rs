@@ -1,10 +1,79 @@
 //! Items Handlers
 //!
+use std::collections::HashMap;
+use chrono;
+use postgres::Connection;
+
 use super::prelude::*;
+use money::{Money, Currency};
+
+
+#[derive(RustcEncodable)]
+/// `models::Item` with its monetary fields in the organization's currency
+pub struct ApiItem {
+    pub id: i32,
+    pub organization_id: i32,
+    pub owning_bidder_id: Option<i32>,
+    pub is_goal: bool,
+    pub title: String,
+    pub description: String,
+    pub value: Money,
+    pub starting: Money,
+    pub min_bid: Money,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub closes_at: Option<chrono::DateTime<chrono::UTC>>,
+}
+impl ApiItem {
+    pub fn new(item: models::Item, currency: Currency) -> ApiItem {
+        ApiItem {
+            id: item.id,
+            organization_id: item.organization_id,
+            owning_bidder_id: item.owning_bidder_id,
+            is_goal: item.is_goal,
+            title: item.title,
+            description: item.description,
+            value: Money::new(item.value, currency),
+            starting: Money::new(item.starting, currency),
+            min_bid: Money::new(item.min_bid, currency),
+            date_created: item.date_created,
+            date_modified: item.date_modified,
+            closes_at: item.closes_at,
+        }
+    }
+}
+
+
+/// Caches organization currencies while converting a batch of items
+pub struct CurrencyCache<'a> {
+    conn: &'a Connection,
+    currencies: HashMap<i32, Currency>,
+}
+impl<'a> CurrencyCache<'a> {
+    pub fn new(conn: &'a Connection) -> CurrencyCache<'a> {
+        CurrencyCache { conn: conn, currencies: HashMap::new() }
+    }
+
+    pub fn get(&mut self, org_id: i32) -> Result<Currency> {
+        if let Some(currency) = self.currencies.get(&org_id) {
+            return Ok(*currency);
+        }
+        let org = sql::get_organization(self.conn, org_id).ok_or("organization not found")?;
+        let currency = org.currency()?;
+        self.currencies.insert(org_id, currency);
+        Ok(currency)
+    }
+
+    pub fn item(&mut self, item: models::Item) -> Result<ApiItem> {
+        let currency = self.get(item.organization_id)?;
+        Ok(ApiItem::new(item, currency))
+    }
+}
+
 
 #[derive(RustcEncodable)]
 struct Items {
-    items: Vec<models::Item>
+    items: Vec<ApiItem>
 }
 
 pub struct ItemsHandler {
@@ -19,11 +88,16 @@ impl ItemsHandler {
 impl Handler for ItemsHandler {
     fn handle(&self, request: &mut Request) -> IronResult<Response> {
         let conn = self.db_pool.get().unwrap();
-        let store = self.s_store.lock().unwrap();
-        let user = store.get_user_from_request(&conn, &request).unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
 
-        let items = sql::filter_items_for_user(&conn, user.id);
-        let items = Items { items: items };
+        let mut currencies = CurrencyCache::new(&conn);
+        let items = sql::filter_items_for_user(&conn, user.id).into_iter()
+            .map(|item| currencies.item(item))
+            .collect::<Result<Vec<_>>>();
+        let items = Items { items: try_server_error!(items) };
         Ok(Response::with((status::Ok, json::encode(&items).unwrap())))
     }
 }
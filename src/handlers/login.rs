@@ -1,10 +1,23 @@
-//! Login handler
+//! Login handlers
 //!
-//! Accepts unauthenticated POSTs with json data containing
-//! an 'email' and 'password' to be authenticated.
-//! Returns a new auth/session token on success.
+//! - `POST /login` accepts unauthenticated json data containing an 'email'
+//!   and 'password' to be authenticated. Returns a new auth/session token on
+//!   success, or a `429` with a retry-after hint once the email or client has
+//!   failed too often (see `login_throttle`). Users with two-factor enabled
+//!   get a `202` with a short-lived 'pending_token' instead.
+//! - `POST /login/two-factor` json data containing the 'pending_token' and a
+//!   'code' from the user's authenticator app (or a recovery code). Returns
+//!   a new auth/session token on success. Wrong codes count towards the
+//!   same throttling as wrong passwords.
+//! - `POST /login/refresh` json data containing a 'refresh_token'. Returns a
+//!   new 'token' and 'refresh_token' when the `jwt` session store is used,
+//!   since its tokens expire quickly and can't be extended.
 use std::io::Read;
+use postgres::Connection;
 use super::prelude::*;
+use account;
+use login_throttle;
+use two_factor;
 
 
 #[derive(RustcEncodable, RustcDecodable)]
@@ -12,10 +25,51 @@ struct ApiAuth {
     email: String,
     password: String,
 }
+#[derive(RustcDecodable)]
+struct ApiTwoFactor {
+    pending_token: String,
+    code: String,
+}
+#[derive(RustcDecodable)]
+struct ApiRefresh {
+    refresh_token: String,
+}
 #[derive(Debug, RustcEncodable, RustcDecodable)]
 struct AuthSuccess {
     token: String,
+    refresh_token: Option<String>,
     admin: bool,
+    verified: bool,
+    two_factor_setup_required: bool,
+}
+#[derive(Debug, RustcEncodable)]
+struct Refreshed {
+    token: String,
+    refresh_token: Option<String>,
+}
+#[derive(Debug, RustcEncodable)]
+struct TwoFactorPending {
+    pending_token: String,
+    expires_in: i64,
+}
+
+
+/// Initialize a new session for a fully authenticated user and send back its token
+fn start_session(s_store: &SStore, conn: &Connection, user: &models::User) -> IronResult<Response> {
+    let new_sess = Session::new().with_user(user.id);
+    let issued = match s_store.add(new_sess) {
+        Ok(issued) => issued,
+        Err(err) => return error_response(status::InternalServerError, &err),
+    };
+    let is_admin = roles::has_permission_anywhere(conn, user, Permission::ManageOrganization);
+    let resp = AuthSuccess {
+        token: issued.token,
+        refresh_token: issued.refresh_token,
+        admin: is_admin,
+        verified: user.is_verified(),
+        two_factor_setup_required: two_factor::is_required(conn, user) && !two_factor::is_enabled(conn, user.id),
+    };
+    Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
 }
 
 
@@ -38,34 +92,124 @@ impl Handler for LoginHandler {
         request.body.read_to_string(&mut req_body).unwrap();
         let auth_info: ApiAuth = try_server_error!(json::decode(&req_body));
 
-        // look for user by email
+        // refuse emails and addresses with too many recent failures
         let conn = self.db_pool.get().unwrap();
-        let user = match sql::select_user_by_email(&conn, &auth_info.email) {
-            Some(u) => u,
-            None => return unauthorized(None),
+        let email = auth_info.email.trim().to_lowercase();
+        let ip = request.remote_addr.ip().to_string();
+        let attempt = match login_throttle::begin(&conn, &email, &ip) {
+            Ok(attempt) => attempt,
+            Err(err) => return match *err.kind() {
+                ErrorKind::TooManyAttempts(..) => error_response(status::TooManyRequests, &err),
+                _ => error_response(status::InternalServerError, &err),
+            },
         };
-        let user_auth = models::Auth::get(&conn, &user.auth_id).unwrap();
 
-        // hash the provided password with the found-user's salt
-        let hash = match auth::hash(&auth_info.password, &user_auth.salt) {
-            Ok(h) => h,
+        // look for user by email and check the password against their stored
+        // hash, hashing anyway when there isn't one so unknown emails take as
+        // long to refuse as wrong passwords
+        let user = sql::select_user_by_email(&conn, &email);
+        let user_auth = user.as_ref().and_then(|u| models::Auth::get(&conn, &u.auth_id));
+        let stored = auth::check_login(user_auth.as_ref().map(|a| a.hash.as_str()), &auth_info.password);
+        let (user, stored) = match (user, stored) {
+            (Some(user), Some(stored)) => (user, stored),
             _ => return unauthorized(None),
         };
 
-        if !auth::const_eq(hash.as_slice(), &user_auth.password) {
-            return unauthorized(None);
+        // upgrade hashes made with outdated parameters while we have the password
+        if stored.needs_rehash() {
+            if let Err(err) = account::set_password(&conn, &user, &auth_info.password) {
+                println!("Error rehashing password for user {}: {}", user.id, err);
+            }
+        }
+
+        // enrolled users still need a second factor before getting a session,
+        // so the attempt stays a failure and nothing is cleared until it's given
+        if two_factor::is_enabled(&conn, user.id) {
+            return match two_factor::start_login(&conn, &user) {
+                Ok((_, token)) => {
+                    let pending = TwoFactorPending {
+                        pending_token: token,
+                        expires_in: two_factor::PENDING_LIFE_MINUTES * 60,
+                    };
+                    Ok(Response::with((status::Accepted, json::encode(&pending).unwrap())))
+                }
+                Err(err) => error_response(status::InternalServerError, &err),
+            };
         }
 
-        // auth was successful, initialize a new session
-        let new_sess = Session::new().with_user(user.id);
-        let is_admin = user.level_ > 9;
-        let resp = AuthSuccess {
-            token: new_sess.token.clone(),
-            admin: is_admin,
+        // auth was successful, send back a new auth/session token
+        if let Err(err) = attempt.succeeded(&conn) {
+            println!("Error recording login: {}", err);
+        }
+        start_session(&self.s_store, &conn, &user)
+    }
+}
+
+
+pub struct TwoFactorLoginHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl TwoFactorLoginHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> TwoFactorLoginHandler {
+        TwoFactorLoginHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for TwoFactorLoginHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let second: ApiTwoFactor = try_server_error!(json::decode(&req_body));
+
+        // codes are throttled along with passwords, so new pending logins
+        // don't each get a fresh set of guesses
+        let conn = self.db_pool.get().unwrap();
+        let email = match two_factor::pending_user(&conn, &second.pending_token) {
+            Some(user) => user.email.to_lowercase(),
+            None => return unauthorized(Some("login has expired, please log in again".into())),
         };
-        self.s_store.lock().unwrap().add(new_sess);
+        let ip = request.remote_addr.ip().to_string();
+        let attempt = match login_throttle::begin(&conn, &email, &ip) {
+            Ok(attempt) => attempt,
+            Err(err) => return match *err.kind() {
+                ErrorKind::TooManyAttempts(..) => error_response(status::TooManyRequests, &err),
+                _ => error_response(status::InternalServerError, &err),
+            },
+        };
+
+        match two_factor::finish_login(&conn, &second.pending_token, &second.code) {
+            Ok(user) => {
+                if let Err(err) = attempt.succeeded(&conn) {
+                    println!("Error recording login: {}", err);
+                }
+                start_session(&self.s_store, &conn, &user)
+            }
+            Err(err) => error_response(status::Unauthorized, &err),
+        }
+    }
+}
 
-        // send back the new auth/session token
-        Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+
+pub struct RefreshHandler {
+    s_store: SStore,
+}
+impl RefreshHandler {
+    pub fn new(s_store: SStore) -> RefreshHandler {
+        RefreshHandler { s_store: s_store }
+    }
+}
+impl Handler for RefreshHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let refresh: ApiRefresh = try_server_error!(json::decode(&req_body));
+
+        match self.s_store.refresh(&refresh.refresh_token) {
+            Ok(issued) => {
+                let resp = Refreshed { token: issued.token, refresh_token: issued.refresh_token };
+                Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+            }
+            Err(err) => error_response(status::Unauthorized, &err),
+        }
     }
 }
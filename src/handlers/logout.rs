@@ -16,8 +16,7 @@ impl LogoutHandler {
 }
 impl Handler for LogoutHandler {
     fn handle(&self, request: &mut Request) -> IronResult<Response> {
-        let mut store = self.store.lock().unwrap();
-        store.delete_by_request(request);
+        self.store.delete_by_request(request);
         let msg = Msg{ msg: "logged out".to_string() };
         Ok(Response::with((status::Ok, json::encode(&msg).expect("json encode fail"))))
     }
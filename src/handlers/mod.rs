@@ -2,15 +2,20 @@
 //!
 //! Handlers manager & handler-prelude
 //!
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use r2d2::Pool;
 use r2d2_postgres::PostgresConnectionManager;
 
 use sessions::SessionStore;
+use payments::PaymentGateway;
+use mailer::Mailer;
+use roles::Permission;
 
 pub type PgPool = Pool<PostgresConnectionManager>;
-pub type SStore = Arc<Mutex<SessionStore>>;
+pub type SStore = Arc<SessionStore>;
+pub type PGateway = Arc<PaymentGateway>;
+pub type SMailer = Arc<Mailer>;
 
 // handler defs
 mod hello;
@@ -19,6 +24,22 @@ mod login;
 mod logout;
 
 mod items;
+mod bids;
+mod register;
+mod signup;
+mod password;
+mod account;
+mod two_factor;
+mod household;
+mod checkin;
+mod bulk_bids;
+mod watchlist;
+mod display;
+mod activity;
+mod admin;
+mod require;
+
+pub use self::require::Require;
 
 
 /// handler prelude of imports needed by handlers, so handler mods
@@ -30,6 +51,8 @@ mod prelude {
     // iron stuff
     pub use iron::{Handler, Request, Response, IronResult, status, headers};
     pub use iron::modifiers::Redirect;
+    pub use iron::method::Method;
+    use router::Router;
     pub use rustc_serialize::json;
 
     // our libs
@@ -37,10 +60,15 @@ mod prelude {
     pub use models;
     pub use auth;
     pub use sessions::{Session, SessionStore};
+    pub use errors::{Error, ErrorKind, Result};
+    pub use roles::{self, Permission};
+    use validation::FieldError;
 
     // local types
     pub use super::PgPool;
     pub use super::SStore;
+    pub use super::PGateway;
+    pub use super::SMailer;
 
     #[derive(Debug, RustcEncodable)]
     pub struct Msg { pub msg: String }
@@ -54,6 +82,65 @@ mod prelude {
         let msg = Msg { msg: _msg };
         return Ok(Response::with((status::Unauthorized, json::encode(&msg).unwrap())))
     }
+
+    /// Value of a url query parameter, e.g. `token` in `/display/1?token=abc`.
+    /// Values aren't percent-decoded.
+    pub fn query_param(request: &Request, name: &str) -> Option<String> {
+        request.url.query().and_then(|query| {
+            query.split('&')
+                .filter_map(|pair| {
+                    let mut kv = pair.splitn(2, '=');
+                    match (kv.next(), kv.next()) {
+                        (Some(key), Some(value)) if key == name => Some(value.to_string()),
+                        _ => None,
+                    }
+                })
+                .next()
+        })
+    }
+
+    /// Integer route parameter, e.g. `org_id` in `/display/:org_id`
+    pub fn path_id(request: &Request, name: &str) -> Option<i32> {
+        request.extensions.get::<Router>()
+            .and_then(|params| params.find(name))
+            .and_then(|id| id.parse::<i32>().ok())
+    }
+
+    /// Return a forbidden response naming the missing permission
+    pub fn forbidden(permission: Permission) -> IronResult<Response> {
+        let err: Error = ErrorKind::PermissionDenied(permission.as_str().into()).into();
+        error_response(status::Forbidden, &err)
+    }
+
+    #[derive(Debug, RustcEncodable)]
+    pub struct ApiError { pub error: String, pub msg: String }
+
+    #[derive(Debug, RustcEncodable)]
+    pub struct ApiInvalidFields { pub error: String, pub msg: String, pub fields: Vec<FieldError> }
+
+    #[derive(Debug, RustcEncodable)]
+    pub struct ApiRetry { pub error: String, pub msg: String, pub retry_after: i64 }
+
+    /// Return an error response carrying the error's machine readable code,
+    /// the individual field errors for `InvalidFields`, and a retry-after
+    /// hint (in the body and `Retry-After` header) for `TooManyAttempts`
+    pub fn error_response(status: status::Status, err: &Error) -> IronResult<Response> {
+        let code = err.kind().code().to_string();
+        let body = match *err.kind() {
+            ErrorKind::InvalidFields(ref fields) => {
+                let body = ApiInvalidFields { error: code, msg: err.to_string(), fields: fields.clone() };
+                json::encode(&body).unwrap()
+            }
+            ErrorKind::TooManyAttempts(retry_after) => {
+                let body = ApiRetry { error: code, msg: err.to_string(), retry_after: retry_after };
+                let mut resp = Response::with((status::TooManyRequests, json::encode(&body).unwrap()));
+                resp.headers.set_raw("Retry-After", vec![retry_after.to_string().into_bytes()]);
+                return Ok(resp);
+            }
+            _ => json::encode(&ApiError { error: code, msg: err.to_string() }).unwrap(),
+        };
+        Ok(Response::with((status, body)))
+    }
 }
 
 /// Handler Manager
@@ -63,17 +150,103 @@ pub struct Handlers {
     pub hello: hello::HelloHandler,
     pub info: info::InfoHandler,
     pub login: login::LoginHandler,
+    pub login_two_factor: login::TwoFactorLoginHandler,
+    pub login_refresh: login::RefreshHandler,
     pub logout: logout::LogoutHandler,
     pub items: items::ItemsHandler,
+    pub place_bid: bids::PlaceBidHandler,
+    pub register: register::RegisterHandler,
+    pub signup: signup::SignupHandler,
+    pub verify: signup::VerifyHandler,
+    pub verify_resend: signup::ResendVerificationHandler,
+    pub password_forgot: password::ForgotPasswordHandler,
+    pub password_reset: password::ResetPasswordHandler,
+    pub change_password: account::ChangePasswordHandler,
+    pub change_email: account::ChangeEmailHandler,
+    pub two_factor_enroll: two_factor::TwoFactorHandler,
+    pub two_factor_confirm: two_factor::TwoFactorHandler,
+    pub two_factor_recovery_codes: two_factor::TwoFactorHandler,
+    pub two_factor_disable: two_factor::TwoFactorHandler,
+    pub household: household::HouseholdHandler,
+    pub household_invite: household::InviteHandler,
+    pub household_accept: household::AcceptInviteHandler,
+    pub household_remove: household::ManageMemberHandler,
+    pub household_transfer: household::ManageMemberHandler,
+    pub checkin_search: Require<checkin::CheckInHandler>,
+    pub checkin: Require<checkin::CheckInHandler>,
+    pub checkin_walk_in: Require<checkin::CheckInHandler>,
+    pub bulk_bids: Require<bulk_bids::BulkBidsHandler>,
+    pub watchlist: watchlist::WatchlistHandler,
+    pub watchlist_add: watchlist::WatchHandler,
+    pub watchlist_remove: watchlist::WatchHandler,
+    pub display: display::DisplayHandler,
+    pub activity: activity::ActivityHandler,
+    pub admin_orgs: Require<admin::AdminHandler>,
+    pub admin_org: Require<admin::AdminHandler>,
+    pub admin_items: Require<admin::AdminHandler>,
+    pub admin_item: Require<admin::AdminHandler>,
+    pub admin_bidders: Require<admin::AdminHandler>,
+    pub admin_bidder: Require<admin::AdminHandler>,
+    pub admin_profiles: Require<admin::AdminHandler>,
+    pub admin_profile: Require<admin::AdminHandler>,
 }
 impl Handlers {
-    pub fn new(db_pool: PgPool, s_store: SStore) -> Handlers {
+    pub fn new(db_pool: PgPool, s_store: SStore, gateway: PGateway, mailer: SMailer) -> Handlers {
         Handlers {
             hello: hello::HelloHandler::new(),
             info: info::InfoHandler::new(db_pool.clone(), s_store.clone()),
             login: login::LoginHandler::new(db_pool.clone(), s_store.clone()),
+            login_two_factor: login::TwoFactorLoginHandler::new(db_pool.clone(), s_store.clone()),
+            login_refresh: login::RefreshHandler::new(s_store.clone()),
             logout: logout::LogoutHandler::new(s_store.clone()),
             items: items::ItemsHandler::new(db_pool.clone(), s_store.clone()),
+            place_bid: bids::PlaceBidHandler::new(db_pool.clone(), s_store.clone(), mailer.clone()),
+            register: register::RegisterHandler::new(db_pool.clone(), s_store.clone(), gateway.clone()),
+            signup: signup::SignupHandler::new(db_pool.clone(), mailer.clone()),
+            verify: signup::VerifyHandler::new(db_pool.clone()),
+            verify_resend: signup::ResendVerificationHandler::new(db_pool.clone(), s_store.clone(), mailer.clone()),
+            password_forgot: password::ForgotPasswordHandler::new(db_pool.clone(), mailer.clone()),
+            password_reset: password::ResetPasswordHandler::new(db_pool.clone(), s_store.clone()),
+            change_password: account::ChangePasswordHandler::new(db_pool.clone(), s_store.clone()),
+            change_email: account::ChangeEmailHandler::new(db_pool.clone(), s_store.clone(), mailer.clone()),
+            two_factor_enroll: two_factor::TwoFactorHandler::enroll(db_pool.clone(), s_store.clone()),
+            two_factor_confirm: two_factor::TwoFactorHandler::confirm(db_pool.clone(), s_store.clone()),
+            two_factor_recovery_codes: two_factor::TwoFactorHandler::recovery_codes(db_pool.clone(), s_store.clone()),
+            two_factor_disable: two_factor::TwoFactorHandler::disable(db_pool.clone(), s_store.clone()),
+            household: household::HouseholdHandler::new(db_pool.clone(), s_store.clone()),
+            household_invite: household::InviteHandler::new(db_pool.clone(), s_store.clone(), mailer.clone()),
+            household_accept: household::AcceptInviteHandler::new(db_pool.clone(), s_store.clone()),
+            household_remove: household::ManageMemberHandler::remove(db_pool.clone(), s_store.clone()),
+            household_transfer: household::ManageMemberHandler::transfer(db_pool.clone(), s_store.clone()),
+            checkin_search: Require::new(db_pool.clone(), s_store.clone(), Permission::CheckIn,
+                                         checkin::CheckInHandler::search(db_pool.clone(), s_store.clone())),
+            checkin: Require::new(db_pool.clone(), s_store.clone(), Permission::CheckIn,
+                                  checkin::CheckInHandler::check_in(db_pool.clone(), s_store.clone())),
+            checkin_walk_in: Require::new(db_pool.clone(), s_store.clone(), Permission::CheckIn,
+                                          checkin::CheckInHandler::walk_in(db_pool.clone(), s_store.clone())),
+            bulk_bids: Require::new(db_pool.clone(), s_store.clone(), Permission::EnterBids,
+                                    bulk_bids::BulkBidsHandler::new(db_pool.clone(), s_store.clone())),
+            watchlist: watchlist::WatchlistHandler::new(db_pool.clone(), s_store.clone()),
+            watchlist_add: watchlist::WatchHandler::add(db_pool.clone(), s_store.clone()),
+            watchlist_remove: watchlist::WatchHandler::remove(db_pool.clone(), s_store.clone()),
+            display: display::DisplayHandler::new(db_pool.clone()),
+            activity: activity::ActivityHandler::new(db_pool.clone(), s_store.clone()),
+            admin_orgs: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                     admin::AdminHandler::collection(db_pool.clone(), s_store.clone(), admin::Resource::Organizations)),
+            admin_org: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                    admin::AdminHandler::member(db_pool.clone(), s_store.clone(), admin::Resource::Organizations)),
+            admin_items: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                      admin::AdminHandler::collection(db_pool.clone(), s_store.clone(), admin::Resource::Items)),
+            admin_item: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                     admin::AdminHandler::member(db_pool.clone(), s_store.clone(), admin::Resource::Items)),
+            admin_bidders: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                        admin::AdminHandler::collection(db_pool.clone(), s_store.clone(), admin::Resource::Bidders)),
+            admin_bidder: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                       admin::AdminHandler::member(db_pool.clone(), s_store.clone(), admin::Resource::Bidders)),
+            admin_profiles: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                         admin::AdminHandler::collection(db_pool.clone(), s_store.clone(), admin::Resource::Profiles)),
+            admin_profile: Require::new(db_pool.clone(), s_store.clone(), Permission::ManageOrganization,
+                                        admin::AdminHandler::member(db_pool.clone(), s_store.clone(), admin::Resource::Profiles)),
         }
     }
 }
@@ -0,0 +1,74 @@
+//! Password Reset Handlers
+//!
+//! - `POST /password/forgot` (unauthenticated) json data containing an 'email'.
+//!   Always succeeds, so it can't be used to find registered addresses.
+//! - `POST /password/reset` (unauthenticated) json data containing the emailed
+//!   'token' and a new 'password'. Ends all of the user's sessions.
+use std::io::Read;
+
+use super::prelude::*;
+use password_reset;
+
+
+#[derive(RustcDecodable)]
+struct ApiForgot {
+    email: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiReset {
+    token: String,
+    password: String,
+}
+
+
+pub struct ForgotPasswordHandler {
+    db_pool: PgPool,
+    mailer: SMailer,
+}
+impl ForgotPasswordHandler {
+    pub fn new(db_pool: PgPool, mailer: SMailer) -> ForgotPasswordHandler {
+        ForgotPasswordHandler { db_pool: db_pool, mailer: mailer }
+    }
+}
+impl Handler for ForgotPasswordHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let forgot: ApiForgot = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let mailer = &*self.mailer;
+        if let Err(err) = sql::transaction(&conn, |conn| password_reset::request_reset(conn, mailer, &forgot.email)) {
+            println!("Error sending password reset: {}", err);
+        }
+        Ok(Response::with((status::Accepted, "if that email is registered, a reset link has been sent")))
+    }
+}
+
+
+pub struct ResetPasswordHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl ResetPasswordHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> ResetPasswordHandler {
+        ResetPasswordHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for ResetPasswordHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let reset: ApiReset = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        match sql::transaction(&conn, |conn| password_reset::reset(conn, &reset.token, &reset.password)) {
+            Ok(user) => {
+                self.s_store.delete_by_user(user.id);
+                Ok(Response::with((status::Ok, "password reset, please log in")))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
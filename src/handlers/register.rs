@@ -0,0 +1,94 @@
+//! Registration handler
+//!
+//! Accepts POSTs from logged-in users with json data containing an
+//! 'organization_id', 'accept_terms', an optional bidder 'id_name' and
+//! optional 'card' details, registering the user's profile as a bidder.
+//! Returns the bidder's paddle number on success. If the organization
+//! requires a card hold and the card is declined, the bidder stays
+//! registered and a 402 is returned; registering again retries the hold.
+use std::io::Read;
+use chrono::NaiveDate;
+
+use super::prelude::*;
+use registration::{self, Registration, CardDetails};
+
+
+#[derive(RustcDecodable)]
+struct ApiCard {
+    number: String,
+    pin: String,
+    exp: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiRegistration {
+    organization_id: i32,
+    id_name: Option<String>,
+    accept_terms: bool,
+    card: Option<ApiCard>,
+}
+
+#[derive(RustcEncodable)]
+struct Registered {
+    bidder_id: i32,
+    id_name: String,
+    paddle: Option<i32>,
+    preauthorized: bool,
+}
+
+
+pub struct RegisterHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    gateway: PGateway,
+}
+impl RegisterHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore, gateway: PGateway) -> RegisterHandler {
+        RegisterHandler { db_pool: db_pool, s_store: s_store, gateway: gateway }
+    }
+}
+impl Handler for RegisterHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let api_reg: ApiRegistration = try_server_error!(json::decode(&req_body));
+
+        let card = match api_reg.card {
+            Some(card) => {
+                let exp = match NaiveDate::parse_from_str(&format!("{}/01", card.exp), "%m/%Y/%d") {
+                    Ok(exp) => exp,
+                    Err(_) => return Ok(Response::with((status::BadRequest, "invalid card expiration, expected mm/yyyy"))),
+                };
+                Some(CardDetails { number: card.number, pin: card.pin, exp: exp })
+            }
+            None => None,
+        };
+        let reg = Registration {
+            organization_id: api_reg.organization_id,
+            id_name: api_reg.id_name,
+            accept_terms: api_reg.accept_terms,
+            card: card,
+        };
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let registered = match sql::transaction(&conn, |conn| registration::register(conn, &user, reg)) {
+            Ok(registered) => registered,
+            Err(err) => return error_response(status::BadRequest, &err),
+        };
+        let hold = match registration::place_hold(&conn, &*self.gateway, &registered) {
+            Ok(hold) => hold,
+            Err(err) => return error_response(status::PaymentRequired, &err),
+        };
+        let resp = Registered {
+            bidder_id: registered.bidder.id,
+            id_name: registered.bidder.id_name,
+            paddle: registered.bidder.paddle,
+            preauthorized: hold.is_some(),
+        };
+        Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+    }
+}
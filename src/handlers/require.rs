@@ -0,0 +1,41 @@
+//! Permission wrapper
+//!
+//! Wraps a handler so requests from users that don't hold a permission
+//! in any scope are rejected with a 403 before the handler runs. Handlers
+//! acting on a specific organization still check that scope themselves
+//! with `roles::require`. Users whose roles require two-factor
+//! authentication are rejected until they've enabled it.
+use super::prelude::*;
+use two_factor;
+
+
+pub struct Require<H: Handler> {
+    db_pool: PgPool,
+    s_store: SStore,
+    permission: Permission,
+    handler: H,
+}
+impl<H: Handler> Require<H> {
+    pub fn new(db_pool: PgPool, s_store: SStore, permission: Permission, handler: H) -> Require<H> {
+        Require { db_pool: db_pool, s_store: s_store, permission: permission, handler: handler }
+    }
+}
+impl<H: Handler> Handler for Require<H> {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        { // drop the connection before calling the handler
+            let conn = self.db_pool.get().unwrap();
+            let user = match self.s_store.get_user_from_request(&conn, &request) {
+                Some(user) => user,
+                None => return unauthorized(None),
+            };
+            if !roles::has_permission_anywhere(&conn, &user, self.permission) {
+                return forbidden(self.permission);
+            }
+            if two_factor::is_required(&conn, &user) && !two_factor::is_enabled(&conn, user.id) {
+                let err: Error = ErrorKind::TwoFactorRequired.into();
+                return error_response(status::Forbidden, &err);
+            }
+        }
+        self.handler.handle(request)
+    }
+}
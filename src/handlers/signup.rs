@@ -0,0 +1,114 @@
+//! Signup Handlers
+//!
+//! - `POST /signup` (unauthenticated) json data containing an 'email',
+//!   'password' and profile 'name'. Creates an unverified account and
+//!   emails a verification link. The response is the same whether or not
+//!   the email is already registered.
+//! - `POST /verify` (unauthenticated) json data containing the emailed 'token'
+//! - `POST /me/verify` re-sends the current user's verification link
+use std::io::Read;
+
+use super::prelude::*;
+use signup::{self, Signup};
+
+
+#[derive(RustcDecodable)]
+struct ApiSignup {
+    email: String,
+    password: String,
+    name: String,
+}
+
+#[derive(RustcDecodable)]
+struct ApiVerify {
+    token: String,
+}
+
+#[derive(RustcEncodable)]
+struct ApiAccount {
+    id: i32,
+    email: String,
+    verified: bool,
+}
+impl ApiAccount {
+    fn new(user: models::User) -> ApiAccount {
+        ApiAccount { id: user.id, verified: user.is_verified(), email: user.email }
+    }
+}
+
+
+pub struct SignupHandler {
+    db_pool: PgPool,
+    mailer: SMailer,
+}
+impl SignupHandler {
+    pub fn new(db_pool: PgPool, mailer: SMailer) -> SignupHandler {
+        SignupHandler { db_pool: db_pool, mailer: mailer }
+    }
+}
+impl Handler for SignupHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let api_signup: ApiSignup = try_server_error!(json::decode(&req_body));
+        let signup = Signup { email: api_signup.email, password: api_signup.password, name: api_signup.name };
+
+        let conn = self.db_pool.get().unwrap();
+        let mailer = &*self.mailer;
+        match sql::transaction(&conn, |conn| signup::signup(conn, mailer, signup)) {
+            Ok(_) => {
+                let msg = Msg { msg: "check your email to finish signing up".into() };
+                Ok(Response::with((status::Accepted, json::encode(&msg).unwrap())))
+            }
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
+
+
+pub struct VerifyHandler {
+    db_pool: PgPool,
+}
+impl VerifyHandler {
+    pub fn new(db_pool: PgPool) -> VerifyHandler {
+        VerifyHandler { db_pool: db_pool }
+    }
+}
+impl Handler for VerifyHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let verify: ApiVerify = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        match sql::transaction(&conn, |conn| signup::verify(conn, &verify.token)) {
+            Ok(user) => Ok(Response::with((status::Ok, json::encode(&ApiAccount::new(user)).unwrap()))),
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
+
+
+pub struct ResendVerificationHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    mailer: SMailer,
+}
+impl ResendVerificationHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore, mailer: SMailer) -> ResendVerificationHandler {
+        ResendVerificationHandler { db_pool: db_pool, s_store: s_store, mailer: mailer }
+    }
+}
+impl Handler for ResendVerificationHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        match signup::send_verification(&conn, &*self.mailer, &user) {
+            Ok(_) => Ok(Response::with((status::Ok, json::encode(&ApiAccount::new(user)).unwrap()))),
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Two-Factor Handlers
+//!
+//! - `POST /me/two-factor` starts enrollment, returning the 'secret' and the
+//!   'uri' to show as a QR code
+//! - `POST /me/two-factor/confirm` json data containing a first 'code' from
+//!   the app. Enables two-factor and returns the 'recovery_codes'
+//! - `POST /me/two-factor/recovery-codes` json data containing a 'code'.
+//!   Returns a new set of 'recovery_codes', replacing the old ones
+//! - `POST /me/two-factor/disable` json data containing a 'code'. Refused
+//!   for admins, who are required to keep it
+use std::io::Read;
+
+use super::prelude::*;
+use two_factor;
+
+
+#[derive(RustcDecodable)]
+struct ApiCode {
+    code: String,
+}
+
+#[derive(Debug, RustcEncodable)]
+struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Enroll,
+    Confirm,
+    RecoveryCodes,
+    Disable,
+}
+
+
+pub struct TwoFactorHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    action: Action,
+}
+impl TwoFactorHandler {
+    fn new(db_pool: PgPool, s_store: SStore, action: Action) -> TwoFactorHandler {
+        TwoFactorHandler { db_pool: db_pool, s_store: s_store, action: action }
+    }
+    pub fn enroll(db_pool: PgPool, s_store: SStore) -> TwoFactorHandler {
+        TwoFactorHandler::new(db_pool, s_store, Action::Enroll)
+    }
+    pub fn confirm(db_pool: PgPool, s_store: SStore) -> TwoFactorHandler {
+        TwoFactorHandler::new(db_pool, s_store, Action::Confirm)
+    }
+    pub fn recovery_codes(db_pool: PgPool, s_store: SStore) -> TwoFactorHandler {
+        TwoFactorHandler::new(db_pool, s_store, Action::RecoveryCodes)
+    }
+    pub fn disable(db_pool: PgPool, s_store: SStore) -> TwoFactorHandler {
+        TwoFactorHandler::new(db_pool, s_store, Action::Disable)
+    }
+}
+impl Handler for TwoFactorHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        if self.action == Action::Enroll {
+            return match sql::transaction(&conn, |conn| two_factor::enroll(conn, &user)) {
+                Ok(enrollment) => Ok(Response::with((status::Ok, json::encode(&enrollment).unwrap()))),
+                Err(err) => error_response(status::BadRequest, &err),
+            };
+        }
+
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let api_code: ApiCode = try_server_error!(json::decode(&req_body));
+        let result = sql::transaction(&conn, |conn| {
+            match self.action {
+                Action::Confirm => two_factor::confirm(conn, &user, &api_code.code).map(Some),
+                Action::RecoveryCodes => two_factor::regenerate_recovery_codes(conn, &user, &api_code.code).map(Some),
+                _ => two_factor::disable(conn, &user, &api_code.code).map(|_| None),
+            }
+        });
+        match result {
+            Ok(Some(codes)) => {
+                let resp = RecoveryCodes { recovery_codes: codes };
+                Ok(Response::with((status::Ok, json::encode(&resp).unwrap())))
+            }
+            Ok(None) => Ok(Response::with((status::Ok, "two-factor authentication disabled"))),
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
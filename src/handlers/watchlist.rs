@@ -0,0 +1,119 @@
+//! Watchlist Handlers
+//!
+//! The current user's starred items with their high bids, and
+//! POSTs with json data containing an 'item_id' to watch or unwatch.
+use std::io::Read;
+use postgres::Connection;
+
+use super::prelude::*;
+use super::items::{ApiItem, CurrencyCache};
+use money::Money;
+use watchlist;
+
+
+#[derive(RustcDecodable)]
+struct ApiWatch {
+    item_id: i32,
+}
+
+#[derive(RustcEncodable)]
+struct ApiWatchedItem {
+    item: ApiItem,
+    high_bid: Option<Money>,
+    is_winning: bool,
+}
+
+#[derive(RustcEncodable)]
+struct Watchlist {
+    items: Vec<ApiWatchedItem>,
+}
+impl Watchlist {
+    fn new(conn: &Connection, profile: &models::Profile) -> Result<Watchlist> {
+        let mut currencies = CurrencyCache::new(conn);
+        let items = watchlist::watched(conn, profile).into_iter().map(|watched| -> Result<ApiWatchedItem> {
+            let currency = currencies.get(watched.item.organization_id)?;
+            Ok(ApiWatchedItem {
+                high_bid: watched.high_bid.map(|bid| Money::new(bid.amount, currency)),
+                is_winning: watched.is_winning,
+                item: ApiItem::new(watched.item, currency),
+            })
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(Watchlist { items: items })
+    }
+
+    fn respond(conn: &Connection, profile: &models::Profile) -> IronResult<Response> {
+        match Watchlist::new(conn, profile) {
+            Ok(resp) => Ok(Response::with((status::Ok, json::encode(&resp).unwrap()))),
+            Err(err) => error_response(status::InternalServerError, &err),
+        }
+    }
+}
+
+
+/// Return the current user's watched items
+pub struct WatchlistHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+}
+impl WatchlistHandler {
+    pub fn new(db_pool: PgPool, s_store: SStore) -> WatchlistHandler {
+        WatchlistHandler { db_pool: db_pool, s_store: s_store }
+    }
+}
+impl Handler for WatchlistHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let profile = match sql::select_profile_by_user(&conn, &user.id) {
+            Some(profile) => profile,
+            None => return Ok(Response::with((status::NotFound, "user has no profile"))),
+        };
+        Watchlist::respond(&conn, &profile)
+    }
+}
+
+
+/// Add an item to, or remove one from, the current user's watchlist,
+/// returning the updated watchlist
+pub struct WatchHandler {
+    db_pool: PgPool,
+    s_store: SStore,
+    remove: bool,
+}
+impl WatchHandler {
+    pub fn add(db_pool: PgPool, s_store: SStore) -> WatchHandler {
+        WatchHandler { db_pool: db_pool, s_store: s_store, remove: false }
+    }
+    pub fn remove(db_pool: PgPool, s_store: SStore) -> WatchHandler {
+        WatchHandler { db_pool: db_pool, s_store: s_store, remove: true }
+    }
+}
+impl Handler for WatchHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        let mut req_body = String::new();
+        request.body.read_to_string(&mut req_body).unwrap();
+        let watch: ApiWatch = try_server_error!(json::decode(&req_body));
+
+        let conn = self.db_pool.get().unwrap();
+        let user = match self.s_store.get_user_from_request(&conn, &request) {
+            Some(user) => user,
+            None => return unauthorized(None),
+        };
+        let profile = match sql::select_profile_by_user(&conn, &user.id) {
+            Some(profile) => profile,
+            None => return Ok(Response::with((status::NotFound, "user has no profile"))),
+        };
+        let result = if self.remove {
+            watchlist::remove(&conn, &profile, watch.item_id)
+        } else {
+            watchlist::add(&conn, &profile, watch.item_id)
+        };
+        match result {
+            Ok(()) => Watchlist::respond(&conn, &profile),
+            Err(err) => error_response(status::BadRequest, &err),
+        }
+    }
+}
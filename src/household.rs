@@ -0,0 +1,177 @@
+//! Household
+//!
+//! Shared bidders: a bidder's primary profile invites others by email,
+//! removes members, and hands off primary status. A bidder always has
+//! exactly one primary profile.
+//!
+use chrono;
+use postgres::{self, Connection};
+
+use models::{User, Profile};
+use mailer::{self, Mailer};
+use roles::{self, Role};
+use auth;
+use sql;
+use errors::*;
+
+
+/// Invitations expire after this many hours
+pub const INVITATION_LIFE_HOURS: i64 = 7 * 24;
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct Invitation {
+    pub id: i32,
+    pub bidder_id: i32,
+    pub inviter_profile_id: i32,
+    pub email: String,
+    pub token_hash: Vec<u8>,
+    pub expires_at: chrono::DateTime<chrono::UTC>,
+    pub accepted_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl Invitation {
+    pub fn from_row(row: postgres::rows::Row) -> Invitation {
+        Invitation {
+            id: row.get(0),
+            bidder_id: row.get(1),
+            inviter_profile_id: row.get(2),
+            email: row.get(3),
+            token_hash: row.get(4),
+            expires_at: row.get(5),
+            accepted_at: row.get(6),
+            date_created: row.get(7),
+            date_modified: row.get(8),
+        }
+    }
+
+    /// Find an unexpired, unaccepted invitation by its (unhashed) token
+    pub fn get_open_by_token(conn: &Connection, token: &str) -> Option<Invitation> {
+        let qs = "select * from invitations where token_hash=$1 \
+                  and accepted_at is null and expires_at > now() for update";
+        query_or_none!(conn.query(qs, &[&auth::hash_token(token)]), Invitation)
+    }
+}
+
+
+/// Return the user's profile if it's the primary profile of a bidder
+fn primary_profile(conn: &Connection, user: &User) -> Result<Profile> {
+    match sql::select_profile_by_user(conn, &user.id) {
+        Some(prof) if prof.is_primary && prof.bidder_id.is_some() => Ok(prof),
+        _ => bail!("only a bidder's primary member can manage its household"),
+    }
+}
+
+
+/// Return the member profile, which must belong to the same bidder as `primary`
+fn member_profile(conn: &Connection, primary: &Profile, profile_id: i32) -> Result<Profile> {
+    match sql::get_profile(conn, profile_id) {
+        Some(prof) => {
+            if prof.bidder_id != primary.bidder_id {
+                bail!("profile is not a member of this household");
+            }
+            Ok(prof)
+        }
+        None => bail!("profile not found"),
+    }
+}
+
+
+/// Invite someone to join the primary user's bidder, emailing them a
+/// single-use token. Only the token's hash is stored.
+pub fn invite(conn: &Connection, mailer: &Mailer, user: &User, email: &str) -> Result<Invitation> {
+    let email = email.trim().to_lowercase();
+    if !email.contains('@') {
+        bail!("invalid email address");
+    }
+    let primary = primary_profile(conn, user)?;
+    let bidder_id = primary.bidder_id.unwrap();
+
+    let token = auth::new_token()?;
+    let token_hash = auth::hash_token(&token);
+    let expires_at = chrono::UTC::now() + chrono::Duration::hours(INVITATION_LIFE_HOURS);
+    let qs = "insert into invitations (bidder_id, inviter_profile_id, email, token_hash, expires_at) \
+              values ($1, $2, $3, $4, $5) returning id, date_created, date_modified";
+    let invitation: Result<Invitation> = try_insert_to_model!(
+        conn.query(qs, &[&bidder_id, &primary.id, &email, &token_hash, &expires_at]) ;
+        Invitation ;
+        id: 0, date_created: 1, date_modified: 2 ;
+        bidder_id: bidder_id, inviter_profile_id: primary.id, email: email,
+        token_hash: token_hash, expires_at: expires_at, accepted_at: None);
+    let invitation = invitation?;
+
+    let body = format!("{} has invited you to join their household for bidding.\n\n\
+                        Log in or create an account with this email address, then accept \
+                        the invitation here:\n\n{}/#/household/accept?token={}\n\n\
+                        This invitation expires on {}.",
+                       primary.name, mailer::public_url(), token,
+                       expires_at.format("%Y-%m-%d %H:%M UTC"));
+    mailer.send(&invitation.email, "You're invited to join a household", &body)
+        .chain_err(|| "Error sending invitation")?;
+    Ok(invitation)
+}
+
+
+/// Accept an invitation, attaching the user's profile to the inviting bidder
+/// and granting them the bidder role in its organization. The invitation
+/// must have been sent to the user's email address.
+pub fn accept(conn: &Connection, user: &User, token: &str) -> Result<Profile> {
+    let invitation = Invitation::get_open_by_token(conn, token).ok_or("invitation is invalid or has expired")?;
+    if invitation.email != user.email.to_lowercase() {
+        bail!("invitation was sent to a different email address");
+    }
+    let profile = sql::select_profile_by_user(conn, &user.id).ok_or("user has no profile")?;
+    if profile.bidder_id.is_some() {
+        bail!("already a member of a bidder");
+    }
+    conn.execute("update profiles set bidder_id=$1, is_primary=false where id=$2",
+                 &[&invitation.bidder_id, &profile.id])
+        .chain_err(|| "Error joining bidder")?;
+    conn.execute("update invitations set accepted_at=now() where id=$1", &[&invitation.id])
+        .chain_err(|| "Error accepting invitation")?;
+    let bidder = sql::get_bidder(conn, invitation.bidder_id).ok_or("bidder not found")?;
+    roles::grant(conn, user.id, Role::Bidder, Some(bidder.organization_id))?;
+    Ok(Profile { bidder_id: Some(invitation.bidder_id), is_primary: false, ..profile })
+}
+
+
+/// Remove a (non-primary) member from the primary user's bidder, revoking
+/// their bidder role in its organization
+pub fn remove_member(conn: &Connection, user: &User, profile_id: i32) -> Result<Profile> {
+    let primary = primary_profile(conn, user)?;
+    let member = member_profile(conn, &primary, profile_id)?;
+    if member.id == primary.id {
+        bail!("transfer primary status before leaving the household");
+    }
+    conn.execute("update profiles set bidder_id=null, is_primary=false where id=$1", &[&member.id])
+        .chain_err(|| "Error removing member")?;
+    if let Some(bidder) = member.bidder_id.and_then(|id| sql::get_bidder(conn, id)) {
+        roles::revoke(conn, member.user_id, Role::Bidder, Some(bidder.organization_id))?;
+    }
+    Ok(Profile { bidder_id: None, is_primary: false, ..member })
+}
+
+
+/// Hand primary status from the user to another member of their bidder.
+/// Should be run in `sql::transaction` so the bidder is never left without
+/// (or with two) primaries.
+pub fn transfer_primary(conn: &Connection, user: &User, profile_id: i32) -> Result<Profile> {
+    let primary = primary_profile(conn, user)?;
+    let member = member_profile(conn, &primary, profile_id)?;
+    if member.id == primary.id {
+        bail!("already the primary member");
+    }
+    conn.execute("update profiles set is_primary=false where id=$1", &[&primary.id])
+        .chain_err(|| "Error updating previous primary")?;
+    conn.execute("update profiles set is_primary=true where id=$1", &[&member.id])
+        .chain_err(|| "Error updating new primary")?;
+    Ok(Profile { is_primary: true, ..member })
+}
+
+
+/// All profiles belonging to a bidder, primary first
+pub fn members(conn: &Connection, bidder_id: i32) -> Vec<Profile> {
+    let qs = "select * from profiles where bidder_id=$1 order by is_primary desc, lower(name)";
+    query_coll!(conn.query(qs, &[&bidder_id]), Profile)
+}
@@ -0,0 +1,358 @@
+//! Ledger
+//!
+//! Double-entry bookkeeping for all money movement. Every sale, pledge,
+//! payment and refund posts a balanced `LedgerTransaction` made up of
+//! debit and credit `LedgerEntry`s. Posting should happen on the same
+//! connection, inside the same `sql::transaction`, as the operation
+//! that caused it.
+//!
+use chrono;
+use postgres::{self, Connection};
+
+use models::{Item, Bid};
+use errors::*;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcEncodable)]
+/// Ledger accounts
+pub enum Account {
+    BidderReceivable,
+    ItemRevenue,
+    Donations,
+    GatewayClearing,
+    Refunds,
+}
+impl Account {
+    pub fn all() -> Vec<Account> {
+        vec![Account::BidderReceivable, Account::ItemRevenue, Account::Donations,
+             Account::GatewayClearing, Account::Refunds]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Account::BidderReceivable => "bidder_receivable",
+            Account::ItemRevenue => "item_revenue",
+            Account::Donations => "donations",
+            Account::GatewayClearing => "gateway_clearing",
+            Account::Refunds => "refunds",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Account> {
+        Account::all().into_iter().find(|acc| acc.as_str() == s)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcEncodable)]
+/// What caused a ledger transaction
+pub enum TxKind {
+    Sale,
+    Pledge,
+    Payment,
+    Refund,
+}
+impl TxKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TxKind::Sale => "sale",
+            TxKind::Pledge => "pledge",
+            TxKind::Payment => "payment",
+            TxKind::Refund => "refund",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<TxKind> {
+        match s {
+            "sale" => Some(TxKind::Sale),
+            "pledge" => Some(TxKind::Pledge),
+            "payment" => Some(TxKind::Payment),
+            "refund" => Some(TxKind::Refund),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct LedgerTransaction {
+    pub id: i32,
+    pub organization_id: i32,
+    pub kind: String,
+    pub memo: String,
+    pub item_id: Option<i32>,
+    pub bid_id: Option<i32>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+
+
+impl LedgerTransaction {
+    /// All debit and credit entries posted under this transaction
+    pub fn entries(&self, conn: &Connection) -> Vec<LedgerEntry> {
+        let qs = "select * from ledger_entries where transaction_id=$1 order by id";
+        query_coll!(conn.query(qs, &[&self.id]), LedgerEntry)
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct LedgerEntry {
+    pub id: i32,
+    pub transaction_id: i32,
+    pub account: String,
+    pub bidder_id: Option<i32>,
+    pub debit: i64,
+    pub credit: i64,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl LedgerEntry {
+    pub fn from_row(row: postgres::rows::Row) -> LedgerEntry {
+        LedgerEntry {
+            id: row.get(0),
+            transaction_id: row.get(1),
+            account: row.get(2),
+            bidder_id: row.get(3),
+            debit: row.get(4),
+            credit: row.get(5),
+            date_created: row.get(6),
+            date_modified: row.get(7),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+/// A single side of a pending ledger transaction
+pub struct NewEntry {
+    pub account: Account,
+    pub bidder_id: Option<i32>,
+    pub debit: i64,
+    pub credit: i64,
+}
+
+
+#[derive(Debug)]
+/// Builder for a balanced set of ledger entries
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// NewLedgerTransaction::new(org.id, TxKind::Payment, "card payment")
+///     .debit(Account::GatewayClearing, None, 5000)
+///     .credit(Account::BidderReceivable, Some(bidder.id), 5000)
+///     .post(&conn)?;
+/// ```
+pub struct NewLedgerTransaction {
+    pub organization_id: i32,
+    pub kind: TxKind,
+    pub memo: String,
+    pub item_id: Option<i32>,
+    pub bid_id: Option<i32>,
+    pub entries: Vec<NewEntry>,
+}
+impl NewLedgerTransaction {
+    pub fn new(org_id: i32, kind: TxKind, memo: &str) -> NewLedgerTransaction {
+        NewLedgerTransaction {
+            organization_id: org_id, kind: kind, memo: memo.into(),
+            item_id: None, bid_id: None, entries: vec![],
+        }
+    }
+
+    pub fn item(mut self, item_id: i32) -> NewLedgerTransaction {
+        self.item_id = Some(item_id);
+        self
+    }
+
+    pub fn bid(mut self, bid_id: i32) -> NewLedgerTransaction {
+        self.bid_id = Some(bid_id);
+        self
+    }
+
+    pub fn debit(mut self, account: Account, bidder_id: Option<i32>, amount: i64) -> NewLedgerTransaction {
+        self.entries.push(NewEntry { account: account, bidder_id: bidder_id, debit: amount, credit: 0 });
+        self
+    }
+
+    pub fn credit(mut self, account: Account, bidder_id: Option<i32>, amount: i64) -> NewLedgerTransaction {
+        self.entries.push(NewEntry { account: account, bidder_id: bidder_id, debit: 0, credit: amount });
+        self
+    }
+
+    /// Total debits equal total credits and every entry moves a positive amount
+    pub fn is_balanced(&self) -> bool {
+        let debits = self.entries.iter().fold(Some(0i64), |acc, e| acc.and_then(|acc| acc.checked_add(e.debit)));
+        let credits = self.entries.iter().fold(Some(0i64), |acc, e| acc.and_then(|acc| acc.checked_add(e.credit)));
+        !self.entries.is_empty() && debits.is_some() && debits == credits &&
+            self.entries.iter().all(|e| (e.debit > 0) != (e.credit > 0) && e.debit >= 0 && e.credit >= 0)
+    }
+
+    /// Insert the transaction and all of its entries. Callers are expected
+    /// to run this inside the same `sql::transaction` as the change being
+    /// recorded so that the two commit or roll back together.
+    pub fn post(self, conn: &Connection) -> Result<LedgerTransaction> {
+        if !self.is_balanced() {
+            bail!(format!("Unbalanced {} ledger transaction", self.kind.as_str()));
+        }
+        let qs = "insert into ledger_transactions (organization_id, kind, memo, item_id, bid_id) \
+                  values ($1, $2, $3, $4, $5) returning id, date_created, date_modified";
+        let kind = self.kind.as_str().to_string();
+        let tx: Result<LedgerTransaction> = try_insert_to_model!(
+            conn.query(qs, &[&self.organization_id, &kind, &self.memo, &self.item_id, &self.bid_id]) ;
+            LedgerTransaction ;
+            id: 0, date_created: 1, date_modified: 2 ;
+            organization_id: self.organization_id, kind: kind,
+            memo: self.memo, item_id: self.item_id, bid_id: self.bid_id);
+        let tx = tx?;
+
+        let qs = "insert into ledger_entries (transaction_id, account, bidder_id, debit, credit) \
+                  values ($1, $2, $3, $4, $5)";
+        for entry in self.entries.iter() {
+            conn.execute(qs, &[&tx.id, &entry.account.as_str(), &entry.bidder_id,
+                               &entry.debit, &entry.credit])
+                .chain_err(|| "Error inserting ledger entry")?;
+        }
+        Ok(tx)
+    }
+}
+
+
+/// An item sold to its winning bid: the bidder owes the bid amount as
+/// item revenue.
+pub fn sale(org_id: i32, item_id: i32, title: &str, bid_id: i32, bidder_id: i32, amount: i64) -> NewLedgerTransaction {
+    NewLedgerTransaction::new(org_id, TxKind::Sale, &format!("sale: {}", title))
+        .item(item_id)
+        .bid(bid_id)
+        .debit(Account::BidderReceivable, Some(bidder_id), amount)
+        .credit(Account::ItemRevenue, None, amount)
+}
+
+pub fn post_sale(conn: &Connection, item: &Item, bid: &Bid) -> Result<LedgerTransaction> {
+    sale(item.organization_id, item.id, &item.title, bid.id, bid.bidder_id, bid.amount).post(conn)
+}
+
+
+/// A bid on a goal item: the bidder owes the pledged amount as a donation.
+pub fn pledge(org_id: i32, item_id: i32, title: &str, bid_id: i32, bidder_id: i32, amount: i64) -> NewLedgerTransaction {
+    NewLedgerTransaction::new(org_id, TxKind::Pledge, &format!("pledge: {}", title))
+        .item(item_id)
+        .bid(bid_id)
+        .debit(Account::BidderReceivable, Some(bidder_id), amount)
+        .credit(Account::Donations, None, amount)
+}
+
+pub fn post_pledge(conn: &Connection, item: &Item, bid: &Bid) -> Result<LedgerTransaction> {
+    pledge(item.organization_id, item.id, &item.title, bid.id, bid.bidder_id, bid.amount).post(conn)
+}
+
+
+/// Money received from a bidder through the payment gateway
+pub fn payment(org_id: i32, bidder_id: i32, amount: i64, memo: &str) -> NewLedgerTransaction {
+    NewLedgerTransaction::new(org_id, TxKind::Payment, memo)
+        .debit(Account::GatewayClearing, None, amount)
+        .credit(Account::BidderReceivable, Some(bidder_id), amount)
+}
+
+pub fn post_payment(conn: &Connection, org_id: i32, bidder_id: i32, amount: i64, memo: &str) -> Result<LedgerTransaction> {
+    payment(org_id, bidder_id, amount, memo).post(conn)
+}
+
+
+/// Money returned to a bidder through the payment gateway
+pub fn refund(org_id: i32, bidder_id: i32, amount: i64, memo: &str) -> NewLedgerTransaction {
+    NewLedgerTransaction::new(org_id, TxKind::Refund, memo)
+        .debit(Account::Refunds, Some(bidder_id), amount)
+        .credit(Account::GatewayClearing, None, amount)
+}
+
+pub fn post_refund(conn: &Connection, org_id: i32, bidder_id: i32, amount: i64, memo: &str) -> Result<LedgerTransaction> {
+    refund(org_id, bidder_id, amount, memo).post(conn)
+}
+
+
+#[derive(Debug, RustcEncodable)]
+/// Total debits and credits posted to an account
+pub struct AccountBalance {
+    pub account: String,
+    pub debit: i64,
+    pub credit: i64,
+}
+impl AccountBalance {
+    /// Debit-normal balance
+    pub fn balance(&self) -> i64 {
+        self.debit - self.credit
+    }
+}
+
+
+/// Sum of debits and credits for every account in an organization.
+/// Accounts without activity are reported with zero totals.
+pub fn trial_balance(conn: &Connection, org_id: i32) -> Result<Vec<AccountBalance>> {
+    let qs = "select e.account, coalesce(sum(e.debit), 0)::bigint, coalesce(sum(e.credit), 0)::bigint \
+              from ledger_entries e join ledger_transactions t on t.id = e.transaction_id \
+              where t.organization_id = $1 group by e.account";
+    let rows = conn.query(qs, &[&org_id]).chain_err(|| "Error querying trial balance")?;
+    let found = rows.iter().map(|row| {
+        AccountBalance { account: row.get(0), debit: row.get(1), credit: row.get(2) }
+    }).collect::<Vec<_>>();
+    Ok(Account::all().into_iter().map(|acc| {
+        match found.iter().find(|bal| bal.account == acc.as_str()) {
+            Some(bal) => AccountBalance { account: bal.account.clone(), debit: bal.debit, credit: bal.credit },
+            None => AccountBalance { account: acc.as_str().into(), debit: 0, credit: 0 },
+        }
+    }).collect())
+}
+
+
+#[derive(Debug, RustcEncodable)]
+/// One line of an account statement with the running balance after it
+pub struct StatementLine {
+    pub transaction_id: i32,
+    pub kind: String,
+    pub memo: String,
+    pub bidder_id: Option<i32>,
+    pub debit: i64,
+    pub credit: i64,
+    pub balance: i64,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+}
+
+
+/// Every entry posted to an account, oldest first, optionally
+/// limited to a single bidder's sub-ledger.
+pub fn account_statement(conn: &Connection, org_id: i32, account: Account,
+                         bidder_id: Option<i32>) -> Result<Vec<StatementLine>> {
+    let qs = "select t.id, t.kind, t.memo, e.bidder_id, e.debit, e.credit, e.date_created \
+              from ledger_entries e join ledger_transactions t on t.id = e.transaction_id \
+              where t.organization_id = $1 and e.account = $2 \
+              and ($3::integer is null or e.bidder_id = $3) \
+              order by e.date_created, e.id";
+    let rows = conn.query(qs, &[&org_id, &account.as_str(), &bidder_id])
+        .chain_err(|| "Error querying account statement")?;
+    let mut balance = 0i64;
+    Ok(rows.iter().map(|row| {
+        let debit: i64 = row.get(4);
+        let credit: i64 = row.get(5);
+        balance += debit - credit;
+        StatementLine {
+            transaction_id: row.get(0),
+            kind: row.get(1),
+            memo: row.get(2),
+            bidder_id: row.get(3),
+            debit: debit,
+            credit: credit,
+            balance: balance,
+            date_created: row.get(6),
+        }
+    }).collect())
+}
+
+
+/// Outstanding receivable balance for a single bidder
+pub fn bidder_balance(conn: &Connection, bidder_id: i32) -> Result<i64> {
+    let qs = "select coalesce(sum(debit) - sum(credit), 0)::bigint from ledger_entries \
+              where account = 'bidder_receivable' and bidder_id = $1";
+    let rows = conn.query(qs, &[&bidder_id]).chain_err(|| "Error querying bidder balance")?;
+    Ok(rows.iter().next().map(|row| row.get(0)).unwrap_or(0))
+}
@@ -35,15 +35,130 @@ extern crate staticfile;
 #[macro_use]
 extern crate error_chain;
 pub mod errors {
-    error_chain! { }
+    error_chain! {
+        errors {
+            UnknownCurrency(code: String) {
+                description("unknown currency")
+                display("unknown currency: '{}'", code)
+            }
+            CurrencyMismatch(one: String, two: String) {
+                description("currency mismatch")
+                display("currency mismatch: {} and {}", one, two)
+            }
+            MoneyOverflow {
+                description("monetary amount overflow")
+                display("monetary amount overflow")
+            }
+            InvalidMoney(input: String) {
+                description("invalid monetary amount")
+                display("invalid monetary amount: '{}'", input)
+            }
+            SpendingLimitExceeded(limit: i64, exposure: i64) {
+                description("spending limit exceeded")
+                display("spending limit exceeded: bids would total {} against a limit of {}", exposure, limit)
+            }
+            BidTooLow(minimum: i64) {
+                description("bid too low")
+                display("bid must be at least {}", minimum)
+            }
+            ItemClosed(item_id: i32) {
+                description("item closed to bidding")
+                display("item {} is closed to bidding", item_id)
+            }
+            InvalidRows(rows: Vec<::bidding::RowError>) {
+                description("invalid rows")
+                display("{} invalid rows", rows.len())
+            }
+            EmailNotVerified {
+                description("email not verified")
+                display("email address must be verified first")
+            }
+            ItemHasBids(item_id: i32) {
+                description("item has bids")
+                display("item {} already has bids", item_id)
+            }
+            BidderHasBids(bidder_id: i32) {
+                description("bidder has bids")
+                display("bidder {} has already placed bids", bidder_id)
+            }
+            BidPosted(bid_id: i32) {
+                description("bid posted to the ledger")
+                display("bid {} has been posted to the ledger", bid_id)
+            }
+            PermissionDenied(permission: String) {
+                description("permission denied")
+                display("permission denied: {} required", permission)
+            }
+            InvalidFields(fields: Vec<::validation::FieldError>) {
+                description("invalid fields")
+                display("invalid fields: {}", fields.iter().map(|f| format!("{} {}", f.field, f.msg)).collect::<Vec<_>>().join(", "))
+            }
+            TwoFactorRequired {
+                description("two-factor authentication required")
+                display("two-factor authentication must be enabled for this account")
+            }
+            TooManyAttempts(retry_after: i64) {
+                description("too many attempts")
+                display("too many attempts, try again in {} seconds", retry_after)
+            }
+        }
+    }
+
+    impl ErrorKind {
+        /// Stable, machine readable code for api error responses
+        pub fn code(&self) -> &'static str {
+            match *self {
+                ErrorKind::UnknownCurrency(..) => "unknown_currency",
+                ErrorKind::CurrencyMismatch(..) => "currency_mismatch",
+                ErrorKind::MoneyOverflow => "money_overflow",
+                ErrorKind::InvalidMoney(..) => "invalid_money",
+                ErrorKind::SpendingLimitExceeded(..) => "limit_exceeded",
+                ErrorKind::BidTooLow(..) => "bid_too_low",
+                ErrorKind::ItemClosed(..) => "item_closed",
+                ErrorKind::InvalidRows(..) => "invalid_rows",
+                ErrorKind::EmailNotVerified => "email_not_verified",
+                ErrorKind::ItemHasBids(..) => "item_has_bids",
+                ErrorKind::BidderHasBids(..) => "bidder_has_bids",
+                ErrorKind::BidPosted(..) => "bid_posted",
+                ErrorKind::PermissionDenied(..) => "forbidden",
+                ErrorKind::InvalidFields(..) => "invalid_fields",
+                ErrorKind::TwoFactorRequired => "two_factor_required",
+                ErrorKind::TooManyAttempts(..) => "too_many_attempts",
+                _ => "error",
+            }
+        }
+    }
 }
 
 #[macro_use]
 pub mod macros;
 
+pub mod money;
 pub mod models;
 pub mod sql;
+pub mod validation;
+pub mod ledger;
+pub mod receipts;
+pub mod payments;
+pub mod bidding;
+pub mod registration;
+pub mod signup;
+pub mod password_reset;
+pub mod password_policy;
+pub mod login_throttle;
+pub mod two_factor;
+pub mod audit;
+pub mod account;
+pub mod mailer;
+pub mod household;
+pub mod checkin;
+pub mod watchlist;
+pub mod display;
+pub mod activity;
+pub mod roles;
+pub mod admin;
 pub mod auth;
+pub mod tokens;
 pub mod sessions;
 pub mod service;
 pub mod handlers;
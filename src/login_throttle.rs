@@ -0,0 +1,217 @@
+//! Login Throttle
+//!
+//! Slows down password guessing. Every login attempt is recorded, and
+//! recent failures for an email or an ip address earn an exponentially
+//! growing wait before the next attempt, ending in a temporary lockout.
+//! Ip limits are looser since many users can share an address. A
+//! successful login clears its email's failures; admins can clear an
+//! email or ip from the cli.
+//!
+//! An attempt is recorded as a failure *before* it's checked, and only
+//! counts the attempts recorded ahead of it, so a burst of concurrent
+//! guesses is throttled the same as the guesses made one at a time.
+//! Attempts older than `RETAIN_DAYS` are pruned by a daemon thread.
+//!
+use std::thread;
+use std::time;
+use chrono::{self, Duration};
+use postgres::Connection;
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+
+use errors::*;
+
+
+/// Only failures this recent count towards throttling
+pub const WINDOW_MINUTES: i64 = 60;
+
+/// How long a lockout lasts after the last failure
+pub const LOCKOUT_MINUTES: i64 = 30;
+
+/// How long attempts are kept around for looking into
+pub const RETAIN_DAYS: i64 = 7;
+
+/// First backoff delay, doubled for each further failure
+const BASE_DELAY_SECS: i64 = 2;
+const MAX_DELAY_SECS: i64 = 15 * 60;
+
+
+struct Limits {
+    /// Failures allowed before any backoff
+    free: i64,
+    /// Failures that lock the key out
+    lockout: i64,
+}
+const EMAIL_LIMITS: Limits = Limits { free: 3, lockout: 10 };
+const IP_LIMITS: Limits = Limits { free: 10, lockout: 50 };
+
+
+/// What attempts are throttled by
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Email(String),
+    Ip(String),
+}
+impl Key {
+    fn column(&self) -> &'static str {
+        match *self {
+            Key::Email(_) => "email",
+            Key::Ip(_) => "ip",
+        }
+    }
+
+    fn value(&self) -> &str {
+        match *self {
+            Key::Email(ref email) => email,
+            Key::Ip(ref ip) => ip,
+        }
+    }
+
+    fn limits(&self) -> &'static Limits {
+        match *self {
+            Key::Email(_) => &EMAIL_LIMITS,
+            Key::Ip(_) => &IP_LIMITS,
+        }
+    }
+}
+
+
+/// Recent failures for a key and when it may try again
+#[derive(Debug)]
+pub struct Status {
+    pub key: Key,
+    pub failures: i64,
+    pub last_failure: Option<chrono::DateTime<chrono::UTC>>,
+    pub retry_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub locked: bool,
+}
+impl Status {
+    pub fn new(key: Key, failures: i64, last_failure: Option<chrono::DateTime<chrono::UTC>>) -> Status {
+        let (retry_at, locked) = match last_failure {
+            Some(last) if failures >= key.limits().lockout => {
+                (Some(last + Duration::minutes(LOCKOUT_MINUTES)), true)
+            }
+            Some(last) if failures >= key.limits().free => {
+                let doublings = ::std::cmp::min(failures - key.limits().free, 20) as u32;
+                let delay = ::std::cmp::min(BASE_DELAY_SECS << doublings, MAX_DELAY_SECS);
+                (Some(last + Duration::seconds(delay)), false)
+            }
+            _ => (None, false),
+        };
+        Status { key: key, failures: failures, last_failure: last_failure, retry_at: retry_at, locked: locked }
+    }
+
+    /// Seconds until the key may try again, if it has to wait
+    pub fn retry_after(&self) -> Option<i64> {
+        self.retry_at
+            .map(|at| (at - chrono::UTC::now()).num_seconds())
+            .filter(|secs| *secs > 0)
+    }
+}
+
+
+fn window_start() -> chrono::DateTime<chrono::UTC> {
+    chrono::UTC::now() - Duration::minutes(WINDOW_MINUTES)
+}
+
+
+/// Recent uncleared failures for a key, recorded before the given attempt
+fn status_before(conn: &Connection, key: Key, attempt_id: i32) -> Result<Status> {
+    let qs = format!("select count(*), max(date_created) from login_attempts \
+                      where {}=$1 and not succeeded and cleared_at is null and date_created > $2 and id < $3",
+                     key.column());
+    let rows = conn.query(&qs, &[&key.value(), &window_start(), &attempt_id])
+        .chain_err(|| "Error counting login attempts")?;
+    let row = rows.iter().next().ok_or("no attempt count")?;
+    let (failures, last_failure) = (row.get(0), row.get(1));
+    Ok(Status::new(key, failures, last_failure))
+}
+
+
+/// Emails and ip addresses that currently have to wait or are locked out
+pub fn throttled(conn: &Connection) -> Result<Vec<Status>> {
+    let mut statuses = vec![];
+    for &(column, limits) in [("email", &EMAIL_LIMITS), ("ip", &IP_LIMITS)].iter() {
+        let qs = format!("select {0}, count(*), max(date_created) from login_attempts \
+                          where not succeeded and cleared_at is null and date_created > $1 \
+                          group by {0} having count(*) >= $2 order by {0}", column);
+        let rows = conn.query(&qs, &[&window_start(), &limits.free]).chain_err(|| "Error listing login attempts")?;
+        for row in rows.iter() {
+            let value: String = row.get(0);
+            let key = if column == "email" { Key::Email(value) } else { Key::Ip(value) };
+            statuses.push(Status::new(key, row.get(1), row.get(2)));
+        }
+    }
+    Ok(statuses.into_iter().filter(|status| status.retry_after().is_some()).collect())
+}
+
+
+/// A login attempt, counted as a failure unless it's marked as succeeded
+#[derive(Debug)]
+pub struct Attempt {
+    id: i32,
+    email: String,
+}
+impl Attempt {
+    /// Mark the attempt successful, clearing the email's earlier failures
+    pub fn succeeded(self, conn: &Connection) -> Result<()> {
+        conn.execute("update login_attempts set succeeded=true where id=$1", &[&self.id])
+            .chain_err(|| "Error recording login attempt")?;
+        clear(conn, &Key::Email(self.email))?;
+        Ok(())
+    }
+}
+
+
+/// Record a login attempt for the email and ip, failing with
+/// `TooManyAttempts` (and forgetting the attempt) if either has
+/// to wait before trying again.
+pub fn begin(conn: &Connection, email: &str, ip: &str) -> Result<Attempt> {
+    let rows = conn.query("insert into login_attempts (email, ip, succeeded) values ($1, $2, false) returning id",
+                          &[&email, &ip])
+        .chain_err(|| "Error recording login attempt")?;
+    let id: i32 = rows.iter().next().ok_or("no attempt id")?.get(0);
+
+    let email_status = status_before(conn, Key::Email(email.into()), id)?;
+    let ip_status = status_before(conn, Key::Ip(ip.into()), id)?;
+    let wait = ::std::cmp::max(email_status.retry_after(), ip_status.retry_after());
+    if let Some(secs) = wait {
+        conn.execute("delete from login_attempts where id=$1", &[&id])
+            .chain_err(|| "Error removing throttled login attempt")?;
+        bail!(ErrorKind::TooManyAttempts(secs));
+    }
+    Ok(Attempt { id: id, email: email.into() })
+}
+
+
+/// Clear a key's failures, ending any wait or lockout. Returns how many were cleared.
+pub fn clear(conn: &Connection, key: &Key) -> Result<u64> {
+    let qs = format!("update login_attempts set cleared_at=now() \
+                      where {}=$1 and not succeeded and cleared_at is null", key.column());
+    conn.execute(&qs, &[&key.value()]).chain_err(|| "Error clearing login attempts")
+}
+
+
+/// Delete attempts older than `RETAIN_DAYS`. Returns how many were deleted.
+pub fn prune(conn: &Connection) -> Result<u64> {
+    let cutoff = chrono::UTC::now() - Duration::days(RETAIN_DAYS);
+    conn.execute("delete from login_attempts where date_created < $1", &[&cutoff])
+        .chain_err(|| "Error pruning login attempts")
+}
+
+
+/// Start a daemon thread to prune old login attempts every 'interval' seconds.
+pub fn start_daemon_pruner(db_pool: Pool<PostgresConnectionManager>, interval: u64) {
+    thread::spawn(move || {
+        loop {
+            match db_pool.get() {
+                Ok(conn) => match prune(&conn) {
+                    Ok(count) => println!(">> Pruned {} old login attempts", count),
+                    Err(err) => println!("Error pruning login attempts: {}", err),
+                },
+                Err(err) => println!("Error pruning login attempts: {}", err),
+            }
+            thread::sleep(time::Duration::from_secs(interval));
+        }
+    });
+}
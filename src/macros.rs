@@ -53,6 +53,73 @@ macro_rules! try_insert_to_model {
 }
 
 
+#[macro_export]
+/// Intended for updating a row and returning a Result with the updated
+/// model (with its new `date_modified`) or an error-chain error describing
+/// the failure. The query must `returning date_modified`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// impl Bidder {
+///     ...
+///     pub fn update(self, conn: &Connection) -> Result<Bidder> {
+///         let qs = "update bidders set id_name=$1 where id=$2 returning date_modified";
+///         try_update_model!(conn.query(qs, &[&self.id_name, &self.id]) ; Bidder ; self)
+///     }
+/// }
+/// ```
+macro_rules! try_update_model {
+    ($query:expr ; $model:ident ; $value:expr) => {
+        match $query {
+            Ok(rows) => {
+                match rows.iter().next() {
+                    Some(row) => Ok($model { date_modified: row.get(0), ..$value }),
+                    _ => bail!("not found")
+                }
+            }
+            Err(postgres::error::Error::Db(err)) => {
+                let message = err.message.to_string();
+                bail!(err.detail.unwrap_or("no details".to_string()) + " | " +
+                    message.as_str())
+            }
+            _ => bail!("Conversion or IO error".to_string())
+        }
+    }
+}
+
+
+#[macro_export]
+/// Intended for deleting a single row by id, returning a Result that fails
+/// if there was no such row or the row is still referenced by others.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// pub fn delete(self, conn: &Connection) -> Result<()> {
+///     try_delete!(conn.execute("delete from bidders where id=$1", &[&self.id]))
+/// }
+/// ```
+macro_rules! try_delete {
+    ($query:expr) => {
+        match $query {
+            Ok(0) => bail!("not found"),
+            Ok(_) => Ok(()),
+            Err(postgres::error::Error::Db(ref err))
+                if err.code == postgres::error::SqlState::ForeignKeyViolation => {
+                bail!("still in use: {}", err.detail.clone().unwrap_or(err.message.clone()))
+            }
+            Err(postgres::error::Error::Db(err)) => {
+                let message = err.message.to_string();
+                bail!(err.detail.unwrap_or("no details".to_string()) + " | " +
+                    message.as_str())
+            }
+            _ => bail!("Conversion or IO error".to_string())
+        }
+    }
+}
+
+
 #[macro_export]
 /// Intended for pulling out the first row of
 /// a query, returning a populated model-struct in an Option
@@ -0,0 +1,67 @@
+//! Mailer
+//!
+//! Pluggable outgoing mail
+//!
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use errors::*;
+
+
+/// Something that can deliver a plain text email
+pub trait Mailer: Send + Sync {
+    fn send(&self, to: &str, subject: &str, body: &str) -> Result<()>;
+}
+
+
+/// Stand-in mailer that prints messages instead of delivering them
+pub struct LogMailer;
+impl Mailer for LogMailer {
+    fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        println!(">> [mail] to: {}\n>> [mail] subject: {}\n{}\n", to, subject, body);
+        Ok(())
+    }
+}
+
+
+/// Stand-in mailer that appends messages to a file, so tests and
+/// local setups can pick links out of sent mail
+pub struct FileMailer {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+impl FileMailer {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileMailer {
+        FileMailer { path: path.into(), lock: Mutex::new(()) }
+    }
+}
+impl Mailer for FileMailer {
+    fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)
+            .chain_err(|| format!("Error opening mail file {}", self.path.display()))?;
+        write!(file, "To: {}\nSubject: {}\n\n{}\n\n", to, subject, body)
+            .chain_err(|| "Error writing mail file")?;
+        Ok(())
+    }
+}
+
+
+/// Select the mailer named by `MAILER`, defaulting to `log`.
+/// The `file` mailer writes to `MAILER_FILE`, defaulting to `mail.log`
+pub fn mailer_from_env() -> Result<Arc<Mailer>> {
+    match env::var("MAILER").unwrap_or("log".into()).as_str() {
+        "log" => Ok(Arc::new(LogMailer)),
+        "file" => Ok(Arc::new(FileMailer::new(env::var("MAILER_FILE").unwrap_or("mail.log".into())))),
+        other => bail!("unknown mailer: {}", other),
+    }
+}
+
+
+/// Base url used when building links sent in emails, from `PUBLIC_URL`
+pub fn public_url() -> String {
+    env::var("PUBLIC_URL").unwrap_or("http://localhost:3002".into()).trim_right_matches('/').to_string()
+}
@@ -1,6 +1,6 @@
 //! Custom Middleware
 //!
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::collections::HashSet;
 
 use iron::{Request, IronResult, IronError, Handler, Response, status};
@@ -9,7 +9,7 @@ use iron::headers::Authorization;
 
 use sessions::SessionStore;
 
-type SStore = Arc<Mutex<SessionStore>>;
+type SStore = Arc<SessionStore>;
 
 
 /// Simple info logger to display the incoming request method & url
@@ -33,6 +33,8 @@ impl BeforeMiddleware for InfoLog {
 /// Session middleware handler to look for an auth/session token
 /// in the request.headers typemap and either return an
 /// unauthorized response or call the provided handler.
+/// With the `jwt` session store this is where access tokens'
+/// signatures, expiry and revocation are checked.
 /// This handler is intended to be returned from SessionMiddleware (AroundMiddleware)
 struct SessionMiddlewareHandler<H: Handler> {
     store: SStore,
@@ -45,11 +47,9 @@ impl<H: Handler> Handler for SessionMiddlewareHandler<H> {
             let url_path = request.url.path();
             let url_root = url_path.iter().next().unwrap();
             if !url_root.is_empty() && !self.exempt_url_roots.contains(url_root) {
-                // note: store-lock needs to get dropped before the handler is called below
-                let mut store = self.store.lock().unwrap();
                 let valid = match request.headers.get::<Authorization<String>>() {
                     Some(token) => {
-                        store.check_delete(&token)
+                        self.store.check_delete(&token)
                     },
                     _ => false,
                 };
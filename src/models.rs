@@ -4,19 +4,58 @@
 use chrono;
 use uuid;
 use postgres::{self, Connection};
+use postgres::types::ToSql;
 use rustc_serialize::json::{Json};
 
 use auth as app_auth;
+use validation::Validator;
+use money::Currency;
 use errors::*;
 
 
+/// Columns and values of a partial update. Only fields that are `Some`
+/// are set, so unchanged columns are left alone in the database.
+struct Changeset<'a> {
+    table: &'static str,
+    columns: Vec<&'static str>,
+    values: Vec<&'a ToSql>,
+}
+impl<'a> Changeset<'a> {
+    fn new(table: &'static str) -> Changeset<'a> {
+        Changeset { table: table, columns: vec![], values: vec![] }
+    }
+
+    fn set<T: ToSql + 'a>(&mut self, column: &'static str, value: &'a Option<T>) {
+        if let Some(ref value) = *value {
+            self.columns.push(column);
+            self.values.push(value);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Run the update on row `id`, returning its new `date_modified`
+    fn execute<'c>(mut self, conn: &'c Connection, id: &'a i32) -> postgres::Result<postgres::rows::Rows<'c>> {
+        let sets = self.columns.iter().enumerate()
+            .map(|(i, column)| format!("{}=${}", column, i + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let qs = format!("update {} set {} where id=${} returning date_modified",
+                         self.table, sets, self.columns.len() + 1);
+        self.values.push(id);
+        conn.query(&qs, &self.values)
+    }
+}
+
+
 #[derive(Debug, RustcEncodable)]
 pub struct Auth {
     pub id: i32,
-    pub salt: Vec<u8>,
-    pub password: Vec<u8>,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub hash: String,
 }
 impl Auth {
     pub fn get(conn: &Connection, id: &i32) -> Option<Auth> {
@@ -27,35 +66,35 @@ impl Auth {
     fn from_row(row: postgres::rows::Row) -> Auth {
         Auth {
             id: row.get(0),
-            salt: row.get(1),
-            password: row.get(2),
-            date_created: row.get(3),
-            date_modified: row.get(4),
+            date_created: row.get(1),
+            date_modified: row.get(2),
+            hash: row.get(3),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct NewAuth {
-    pub salt: Vec<u8>,
-    pub password: Vec<u8>,
+    pub hash: String,
 }
 impl NewAuth {
-    pub fn new(password_string: &str) -> NewAuth {
-        let salt = app_auth::new_salt().expect("salt generation fail");
-        let password = app_auth::hash(password_string, &salt).expect("hash fail");
-        NewAuth {
-            salt: salt,
-            password: password,
-        }
+    /// Hash a new password, failing with `InvalidFields` if it
+    /// breaks the `password_policy`
+    pub fn new(password_string: &str) -> Result<NewAuth> {
+        let mut v = Validator::new();
+        v.password("password", password_string);
+        v.finish()?;
+        Ok(NewAuth {
+            hash: app_auth::hash_password(password_string)?,
+        })
     }
     pub fn create(self, conn: &Connection) -> Result<Auth> {
-        let qs = "insert into auth (salt, password) values ($1, $2) \
+        let qs = "insert into auth (hash) values ($1) \
                   returning id, date_created, date_modified";
-        try_insert_to_model!(conn.query(qs, &[&self.salt, &self.password]) ;
+        try_insert_to_model!(conn.query(qs, &[&self.hash]) ;
                              Auth ;
                              id: 0, date_created: 1, date_modified: 2 ;
-                             salt: self.salt, password: self.password)
+                             hash: self.hash)
     }
 }
 
@@ -68,13 +107,14 @@ pub struct User {
     pub auth_id: i32,
     pub email: String,
     pub uuid_: uuid::Uuid,
-    pub level_: i32,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub deleted_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub verified_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl User {
     pub fn get(conn: &Connection, id: &i32) -> Option<User> {
-        let qs = "select * from users where id=$1";
+        let qs = "select * from users where id=$1 and deleted_at is null";
         query_or_none!(conn.query(qs, &[&id]), User)
     }
 
@@ -84,11 +124,17 @@ impl User {
             auth_id: row.get(1),
             email: row.get(2),
             uuid_: row.get(3),
-            level_: row.get(4),
-            date_created: row.get(5),
-            date_modified: row.get(6),
+            date_created: row.get(4),
+            date_modified: row.get(5),
+            deleted_at: row.get(6),
+            verified_at: row.get(7),
         }
     }
+
+    /// Whether the user has confirmed their email address
+    pub fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
 }
 
 #[derive(Debug)]
@@ -96,26 +142,31 @@ pub struct NewUser {
     pub auth_id: i32,
     pub email: String,
     pub uuid_: uuid::Uuid,
-    pub level_: i32,
+    pub verified_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl NewUser {
-    pub fn new(email: &str, auth: &Auth, level_: i32) -> NewUser {
+    pub fn new(email: &str, auth: &Auth) -> NewUser {
         let uuid_ = uuid::Uuid::new_v4();
         NewUser {
             auth_id: auth.id,
             email: email.into(),
             uuid_: uuid_,
-            level_: level_
+            verified_at: None,
         }
     }
+    /// Mark the new user's email as already verified, for accounts
+    /// created by admins or in person
+    pub fn verified(self) -> NewUser {
+        NewUser { verified_at: Some(chrono::UTC::now()), ..self }
+    }
     pub fn create(self, conn: &Connection) -> Result<User> {
-        let qs = "insert into users (auth_id, email, uuid_, level_) values ($1, $2, $3, $4) \
+        let qs = "insert into users (auth_id, email, uuid_, verified_at) values ($1, $2, $3, $4) \
                   returning id, date_created, date_modified";
-        try_insert_to_model!(conn.query(qs, &[&self.auth_id, &self.email, &self.uuid_, &self.level_]) ;
+        try_insert_to_model!(conn.query(qs, &[&self.auth_id, &self.email, &self.uuid_, &self.verified_at]) ;
                              User ;
                              id: 0, date_created: 1, date_modified: 2 ;
                              auth_id: self.auth_id, email: self.email, uuid_: self.uuid_,
-                             level_: self.level_)
+                             deleted_at: None, verified_at: self.verified_at)
     }
 }
 
@@ -142,6 +193,11 @@ pub struct Organization {
     pub extra: Option<Json>,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub bidder_spending_limit: Option<i64>,
+    pub preauth_amount: Option<i64>,
+    pub next_paddle: i32,
+    pub display_token_hash: Option<Vec<u8>>,
+    pub deleted_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl Organization {
     pub fn from_row(row: postgres::rows::Row) -> Organization {
@@ -151,6 +207,64 @@ impl Organization {
             extra: row.get(2),
             date_created: row.get(3),
             date_modified: row.get(4),
+            bidder_spending_limit: row.get(5),
+            preauth_amount: row.get(6),
+            next_paddle: row.get(7),
+            display_token_hash: row.get(8),
+            deleted_at: row.get(9),
+        }
+    }
+
+    /// The organization's configured `extra.currency`, defaulting to USD
+    pub fn currency(&self) -> Result<Currency> {
+        Organization::currency_from_extra(&self.extra)
+    }
+
+    /// The currency configured by an organization `extra`, defaulting to USD
+    pub fn currency_from_extra(extra: &Option<Json>) -> Result<Currency> {
+        match extra.as_ref().and_then(|ex| ex.find("currency")).and_then(|c| c.as_string()) {
+            Some(code) => Currency::from_code(code),
+            None => Ok(Currency::default()),
+        }
+    }
+
+    pub fn update(self, conn: &Connection, changes: OrgChanges) -> Result<Organization> {
+        let id = self.id;
+        let rows = {
+            let mut set = Changeset::new("organizations");
+            set.set("name", &changes.name);
+            set.set("extra", &changes.extra);
+            set.set("bidder_spending_limit", &changes.bidder_spending_limit);
+            set.set("preauth_amount", &changes.preauth_amount);
+            if set.is_empty() { return Ok(self); }
+            set.execute(conn, &id)
+        };
+        try_update_model!(rows ; Organization ; changes.apply_to(self))
+    }
+
+    /// Soft delete the organization, see `sql::restore_organization`
+    pub fn delete(self, conn: &Connection) -> Result<()> {
+        let qs = "update organizations set deleted_at=NOW() where id=$1 and deleted_at is null";
+        try_delete!(conn.execute(qs, &[&self.id]))
+    }
+}
+
+/// Partial update of an `Organization`, `None` fields are left unchanged
+#[derive(Debug, Default)]
+pub struct OrgChanges {
+    pub name: Option<String>,
+    pub extra: Option<Option<Json>>,
+    pub bidder_spending_limit: Option<Option<i64>>,
+    pub preauth_amount: Option<Option<i64>>,
+}
+impl OrgChanges {
+    pub fn apply_to(self, org: Organization) -> Organization {
+        Organization {
+            name: self.name.unwrap_or(org.name),
+            extra: self.extra.unwrap_or(org.extra),
+            bidder_spending_limit: self.bidder_spending_limit.unwrap_or(org.bidder_spending_limit),
+            preauth_amount: self.preauth_amount.unwrap_or(org.preauth_amount),
+            ..org
         }
     }
 }
@@ -170,11 +284,13 @@ impl NewOrg {
     }
     pub fn create(self, conn: &Connection) -> Result<Organization> {
         let qs = "insert into organizations (name, extra) values ($1, $2) \
-                  returning id, date_created, date_modified";
+                  returning id, date_created, date_modified, next_paddle, display_token_hash";
         try_insert_to_model!(conn.query(qs, &[&self.name, &self.extra]) ;
                              Organization ;
-                             id: 0, date_created: 1, date_modified: 2 ;
-                             name: self.name, extra: self.extra)
+                             id: 0, date_created: 1, date_modified: 2,
+                             next_paddle: 3, display_token_hash: 4 ;
+                             name: self.name, extra: self.extra,
+                             bidder_spending_limit: None, preauth_amount: None, deleted_at: None)
     }
 }
 
@@ -188,6 +304,9 @@ pub struct Bidder {
     pub id_name: String,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub spending_limit: Option<i64>,
+    pub paddle: Option<i32>,
+    pub terms_accepted_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl Bidder {
     pub fn from_row(row: postgres::rows::Row) -> Bidder {
@@ -195,8 +314,75 @@ impl Bidder {
             id: row.get(0),
             organization_id: row.get(1),
             id_name: row.get(2),
-            date_created: row.get(2),
-            date_modified: row.get(3),
+            date_created: row.get(3),
+            date_modified: row.get(4),
+            spending_limit: row.get(5),
+            paddle: row.get(6),
+            terms_accepted_at: row.get(7),
+        }
+    }
+
+    /// The bidder's own spending limit, falling back to the organization-wide limit
+    pub fn effective_spending_limit(&self, org: &Organization) -> Option<i64> {
+        self.spending_limit.or(org.bidder_spending_limit)
+    }
+
+    /// Whether this bidder has placed any bids
+    pub fn has_bids(&self, conn: &Connection) -> Result<bool> {
+        let rows = conn.query("select exists (select 1 from bids where bidder_id=$1)", &[&self.id])
+            .chain_err(|| "Error checking bidder bids")?;
+        Ok(rows.get(0).get(0))
+    }
+
+    /// Update the bidder, refusing to move a bidder with bids to another
+    /// organization since their bids would then span organizations
+    pub fn update(self, conn: &Connection, changes: BidderChanges) -> Result<Bidder> {
+        if changes.moves_organization(&self) && self.has_bids(conn)? {
+            bail!(ErrorKind::BidderHasBids(self.id));
+        }
+        let id = self.id;
+        let rows = {
+            let mut set = Changeset::new("bidders");
+            set.set("organization_id", &changes.organization_id);
+            set.set("id_name", &changes.id_name);
+            set.set("spending_limit", &changes.spending_limit);
+            set.set("paddle", &changes.paddle);
+            if set.is_empty() { return Ok(self); }
+            set.execute(conn, &id)
+        };
+        try_update_model!(rows ; Bidder ; changes.apply_to(self))
+    }
+
+    /// Delete the bidder, refusing if it has placed any bids
+    pub fn delete(self, conn: &Connection) -> Result<()> {
+        if self.has_bids(conn)? {
+            bail!(ErrorKind::BidderHasBids(self.id));
+        }
+        try_delete!(conn.execute("delete from bidders where id=$1", &[&self.id]))
+    }
+}
+
+/// Partial update of a `Bidder`, `None` fields are left unchanged
+#[derive(Debug, Default)]
+pub struct BidderChanges {
+    pub organization_id: Option<i32>,
+    pub id_name: Option<String>,
+    pub spending_limit: Option<Option<i64>>,
+    pub paddle: Option<Option<i32>>,
+}
+impl BidderChanges {
+    /// Whether these changes move `bidder` to another organization
+    pub fn moves_organization(&self, bidder: &Bidder) -> bool {
+        self.organization_id.map(|org_id| org_id != bidder.organization_id).unwrap_or(false)
+    }
+
+    pub fn apply_to(self, bidder: Bidder) -> Bidder {
+        Bidder {
+            organization_id: self.organization_id.unwrap_or(bidder.organization_id),
+            id_name: self.id_name.unwrap_or(bidder.id_name),
+            spending_limit: self.spending_limit.unwrap_or(bidder.spending_limit),
+            paddle: self.paddle.unwrap_or(bidder.paddle),
+            ..bidder
         }
     }
 }
@@ -219,7 +405,8 @@ impl NewBidder {
         try_insert_to_model!(conn.query(qs, &[&self.organization_id, &self.id_name]) ;
                              Bidder ;
                              id: 0, date_created: 1, date_modified: 2 ;
-                             organization_id: self.organization_id, id_name: self.id_name)
+                             organization_id: self.organization_id, id_name: self.id_name,
+                             spending_limit: None, paddle: None, terms_accepted_at: None)
     }
 }
 
@@ -246,6 +433,41 @@ impl PaymentInfo {
             date_modified: row.get(5),
         }
     }
+
+    pub fn update(self, conn: &Connection, changes: PaymentInfoChanges) -> Result<PaymentInfo> {
+        let id = self.id;
+        let rows = {
+            let mut set = Changeset::new("payment_information");
+            set.set("cc_number", &changes.cc_number);
+            set.set("cc_pin", &changes.cc_pin);
+            set.set("cc_exp", &changes.cc_exp);
+            if set.is_empty() { return Ok(self); }
+            set.execute(conn, &id)
+        };
+        try_update_model!(rows ; PaymentInfo ; changes.apply_to(self))
+    }
+
+    pub fn delete(self, conn: &Connection) -> Result<()> {
+        try_delete!(conn.execute("delete from payment_information where id=$1", &[&self.id]))
+    }
+}
+
+/// Partial update of a `PaymentInfo`, `None` fields are left unchanged
+#[derive(Debug, Default)]
+pub struct PaymentInfoChanges {
+    pub cc_number: Option<String>,
+    pub cc_pin: Option<String>,
+    pub cc_exp: Option<chrono::NaiveDate>,
+}
+impl PaymentInfoChanges {
+    pub fn apply_to(self, info: PaymentInfo) -> PaymentInfo {
+        PaymentInfo {
+            cc_number: self.cc_number.unwrap_or(info.cc_number),
+            cc_pin: self.cc_pin.unwrap_or(info.cc_pin),
+            cc_exp: self.cc_exp.unwrap_or(info.cc_exp),
+            ..info
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -287,6 +509,8 @@ pub struct Profile {
     pub extra: Option<Json>,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub checked_in_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub checked_in_by: Option<i32>,
 }
 impl Profile {
     pub fn from_row(row: postgres::rows::Row) -> Profile {
@@ -301,6 +525,52 @@ impl Profile {
             extra: row.get(7),
             date_created: row.get(8),
             date_modified: row.get(9),
+            checked_in_at: row.get(10),
+            checked_in_by: row.get(11),
+        }
+    }
+
+    pub fn update(self, conn: &Connection, changes: ProfileChanges) -> Result<Profile> {
+        let id = self.id;
+        let rows = {
+            let mut set = Changeset::new("profiles");
+            set.set("bidder_id", &changes.bidder_id);
+            set.set("payment_info_id", &changes.payment_info_id);
+            set.set("is_primary", &changes.is_primary);
+            set.set("name", &changes.name);
+            set.set("phone", &changes.phone);
+            set.set("extra", &changes.extra);
+            if set.is_empty() { return Ok(self); }
+            set.execute(conn, &id)
+        };
+        try_update_model!(rows ; Profile ; changes.apply_to(self))
+    }
+
+    pub fn delete(self, conn: &Connection) -> Result<()> {
+        try_delete!(conn.execute("delete from profiles where id=$1", &[&self.id]))
+    }
+}
+
+/// Partial update of a `Profile`, `None` fields are left unchanged
+#[derive(Debug, Default)]
+pub struct ProfileChanges {
+    pub bidder_id: Option<Option<i32>>,
+    pub payment_info_id: Option<Option<i32>>,
+    pub is_primary: Option<bool>,
+    pub name: Option<String>,
+    pub phone: Option<Option<String>>,
+    pub extra: Option<Option<Json>>,
+}
+impl ProfileChanges {
+    pub fn apply_to(self, profile: Profile) -> Profile {
+        Profile {
+            bidder_id: self.bidder_id.unwrap_or(profile.bidder_id),
+            payment_info_id: self.payment_info_id.unwrap_or(profile.payment_info_id),
+            is_primary: self.is_primary.unwrap_or(profile.is_primary),
+            name: self.name.unwrap_or(profile.name),
+            phone: self.phone.unwrap_or(profile.phone),
+            extra: self.extra.unwrap_or(profile.extra),
+            ..profile
         }
     }
 }
@@ -338,7 +608,8 @@ impl NewProfile {
                              user_id: self.user_id, bidder_id: self.bidder_id,
                              payment_info_id: self.payment_info_id,
                              is_primary: self.is_primary, name: self.name,
-                             phone: self.phone, extra: self.extra)
+                             phone: self.phone, extra: self.extra,
+                             checked_in_at: None, checked_in_by: None)
     }
 }
 
@@ -358,6 +629,8 @@ pub struct Item {
     pub min_bid: i64,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub closes_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub deleted_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl Item {
     pub fn from_row(row: postgres::rows::Row) -> Item {
@@ -373,6 +646,90 @@ impl Item {
             min_bid: row.get(8),
             date_created: row.get(9),
             date_modified: row.get(10),
+            closes_at: row.get(11),
+            deleted_at: row.get(12),
+        }
+    }
+
+    /// Whether online bidding on this item has ended
+    pub fn is_closed(&self) -> bool {
+        self.owning_bidder_id.is_some() ||
+            self.closes_at.map(|closes| closes <= chrono::UTC::now()).unwrap_or(false)
+    }
+
+    /// Whether any bids have been placed on this item
+    pub fn has_bids(&self, conn: &Connection) -> Result<bool> {
+        let rows = conn.query("select exists (select 1 from bids where item_id=$1)", &[&self.id])
+            .chain_err(|| "Error checking item bids")?;
+        Ok(rows.get(0).get(0))
+    }
+
+    /// Update the item, refusing changes that would invalidate
+    /// existing bids (see `ItemChanges::is_destructive`)
+    pub fn update(self, conn: &Connection, changes: ItemChanges) -> Result<Item> {
+        if changes.is_destructive() && self.has_bids(conn)? {
+            bail!(ErrorKind::ItemHasBids(self.id));
+        }
+        let id = self.id;
+        let rows = {
+            let mut set = Changeset::new("items");
+            set.set("organization_id", &changes.organization_id);
+            set.set("is_goal", &changes.is_goal);
+            set.set("title", &changes.title);
+            set.set("description", &changes.description);
+            set.set("value", &changes.value);
+            set.set("starting", &changes.starting);
+            set.set("min_bid", &changes.min_bid);
+            set.set("closes_at", &changes.closes_at);
+            if set.is_empty() { return Ok(self); }
+            set.execute(conn, &id)
+        };
+        try_update_model!(rows ; Item ; changes.apply_to(self))
+    }
+
+    /// Soft delete the item, refusing if any bids have been placed on it.
+    /// See `sql::restore_item`
+    pub fn delete(self, conn: &Connection) -> Result<()> {
+        if self.has_bids(conn)? {
+            bail!(ErrorKind::ItemHasBids(self.id));
+        }
+        let qs = "update items set deleted_at=NOW() where id=$1 and deleted_at is null";
+        try_delete!(conn.execute(qs, &[&self.id]))
+    }
+}
+
+/// Partial update of an `Item`, `None` fields are left unchanged
+#[derive(Debug, Default)]
+pub struct ItemChanges {
+    pub organization_id: Option<i32>,
+    pub is_goal: Option<bool>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub value: Option<i64>,
+    pub starting: Option<i64>,
+    pub min_bid: Option<i64>,
+    pub closes_at: Option<Option<chrono::DateTime<chrono::UTC>>>,
+}
+impl ItemChanges {
+    /// Changes to what's being bid on, or to the rules existing bids were
+    /// placed under. Titles, descriptions, values and closing times are
+    /// always allowed. Items are sold through the ledger, not here.
+    pub fn is_destructive(&self) -> bool {
+        self.organization_id.is_some() || self.is_goal.is_some() ||
+            self.starting.is_some() || self.min_bid.is_some()
+    }
+
+    pub fn apply_to(self, item: Item) -> Item {
+        Item {
+            organization_id: self.organization_id.unwrap_or(item.organization_id),
+            is_goal: self.is_goal.unwrap_or(item.is_goal),
+            title: self.title.unwrap_or(item.title),
+            description: self.description.unwrap_or(item.description),
+            value: self.value.unwrap_or(item.value),
+            starting: self.starting.unwrap_or(item.starting),
+            min_bid: self.min_bid.unwrap_or(item.min_bid),
+            closes_at: self.closes_at.unwrap_or(item.closes_at),
+            ..item
         }
     }
 }
@@ -390,6 +747,7 @@ pub struct NewItem {
     pub value: i64,
     pub starting: i64,
     pub min_bid: i64,
+    pub closes_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl NewItem {
     pub fn new(org_id: i32, is_goal: bool, title: &str, desc: &str,
@@ -397,19 +755,21 @@ impl NewItem {
         NewItem {
             organization_id: org_id, is_goal: is_goal, title: title.into(),
             description: desc.into(), value: value, starting: starting,
-            min_bid: min_bid, owning_bidder_id: None,
+            min_bid: min_bid, owning_bidder_id: None, closes_at: None,
         }
     }
     pub fn create(self, conn: &Connection) -> Result<Item> {
-        let qs = "insert into items (organization_id, is_goal, title, description, value, starting, min_bid, owning_bidder_id) \
-                  values ($1, $2, $3, $4, $5, $6, $7, $8) returning id, date_created, date_modified";
+        let qs = "insert into items (organization_id, is_goal, title, description, value, starting, min_bid, owning_bidder_id, closes_at) \
+                  values ($1, $2, $3, $4, $5, $6, $7, $8, $9) returning id, date_created, date_modified";
         try_insert_to_model!(conn.query(qs, &[&self.organization_id, &self.is_goal, &self.title,
-                                              &self.description, &self.value, &self.starting, &self.min_bid, &self.owning_bidder_id]) ;
+                                              &self.description, &self.value, &self.starting, &self.min_bid, &self.owning_bidder_id,
+                                              &self.closes_at]) ;
                              Item ;
                              id: 0, date_created: 1, date_modified: 2 ;
                              organization_id: self.organization_id, is_goal:self.is_goal, title: self.title,
                              description: self.description, value: self.value, starting: self.starting,
-                             min_bid: self.min_bid, owning_bidder_id: self.owning_bidder_id)
+                             min_bid: self.min_bid, owning_bidder_id: self.owning_bidder_id, closes_at: self.closes_at,
+                             deleted_at: None)
     }
 }
 
@@ -425,6 +785,9 @@ pub struct Bid {
     pub amount: i64,
     pub date_created: chrono::DateTime<chrono::UTC>,
     pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub source: String,
+    pub entered_by: Option<i32>,
+    pub written_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl Bid {
     pub fn from_row(row: postgres::rows::Row) -> Bid {
@@ -435,28 +798,108 @@ impl Bid {
             amount: row.get(3),
             date_created: row.get(4),
             date_modified: row.get(5),
+            source: row.get(6),
+            entered_by: row.get(7),
+            written_at: row.get(8),
+        }
+    }
+
+    /// Whether a sale or pledge has been posted to the ledger for this bid
+    pub fn is_posted(&self, conn: &Connection) -> Result<bool> {
+        let rows = conn.query("select exists (select 1 from ledger_transactions where bid_id=$1)", &[&self.id])
+            .chain_err(|| "Error checking bid postings")?;
+        Ok(rows.get(0).get(0))
+    }
+
+    /// Update the bid, refusing to change the bidder or amount of a bid
+    /// already posted to the ledger (every goal pledge, and awarded sales)
+    pub fn update(self, conn: &Connection, changes: BidChanges) -> Result<Bid> {
+        if changes.is_destructive() && self.is_posted(conn)? {
+            bail!(ErrorKind::BidPosted(self.id));
+        }
+        let id = self.id;
+        let rows = {
+            let mut set = Changeset::new("bids");
+            set.set("bidder_id", &changes.bidder_id);
+            set.set("amount", &changes.amount);
+            set.set("written_at", &changes.written_at);
+            if set.is_empty() { return Ok(self); }
+            set.execute(conn, &id)
+        };
+        try_update_model!(rows ; Bid ; changes.apply_to(self))
+    }
+
+    /// Delete the bid, refusing if it's been posted to the ledger
+    pub fn delete(self, conn: &Connection) -> Result<()> {
+        if self.is_posted(conn)? {
+            bail!(ErrorKind::BidPosted(self.id));
+        }
+        try_delete!(conn.execute("delete from bids where id=$1", &[&self.id]))
+    }
+}
+
+/// Partial update of a `Bid`, `None` fields are left unchanged.
+/// Meant for correcting transcription mistakes in manual bids.
+#[derive(Debug, Default)]
+pub struct BidChanges {
+    pub bidder_id: Option<i32>,
+    pub amount: Option<i64>,
+    pub written_at: Option<Option<chrono::DateTime<chrono::UTC>>>,
+}
+impl BidChanges {
+    /// Changes to who owes what, which the ledger has already recorded
+    /// for posted bids. Correcting when a bid was written is always allowed.
+    pub fn is_destructive(&self) -> bool {
+        self.bidder_id.is_some() || self.amount.is_some()
+    }
+
+    pub fn apply_to(self, bid: Bid) -> Bid {
+        Bid {
+            bidder_id: self.bidder_id.unwrap_or(bid.bidder_id),
+            amount: self.amount.unwrap_or(bid.amount),
+            written_at: self.written_at.unwrap_or(bid.written_at),
+            ..bid
         }
     }
 }
 
+/// `Bid::source` for bids placed by bidders themselves
+pub const BID_SOURCE_ONLINE: &'static str = "online";
+/// `Bid::source` for bids transcribed from paper bid sheets
+pub const BID_SOURCE_MANUAL: &'static str = "manual";
+
 #[derive(Debug)]
 pub struct NewBid {
     pub bidder_id: i32,
     pub item_id: i32,
     pub amount: i64,
+    pub source: String,
+    pub entered_by: Option<i32>,
+    pub written_at: Option<chrono::DateTime<chrono::UTC>>,
 }
 impl NewBid {
     pub fn new(bidder_id: i32, item_id: i32, amount: i64) -> NewBid {
         NewBid {
             bidder_id: bidder_id, item_id: item_id, amount: amount,
+            source: BID_SOURCE_ONLINE.into(), entered_by: None, written_at: None,
+        }
+    }
+    /// A bid transcribed from a paper bid sheet by the `entered_by` user
+    pub fn manual(bidder_id: i32, item_id: i32, amount: i64, entered_by: Option<i32>,
+                  written_at: chrono::DateTime<chrono::UTC>) -> NewBid {
+        NewBid {
+            source: BID_SOURCE_MANUAL.into(), entered_by: entered_by, written_at: Some(written_at),
+            ..NewBid::new(bidder_id, item_id, amount)
         }
     }
     pub fn create(self, conn: &Connection) -> Result<Bid> {
-        let qs = "insert into bids (bidder_id, item_id, amount) values ($1, $2, $3) \
-                  returning id, date_created, date_modified";
-        try_insert_to_model!(conn.query(qs, &[&self.bidder_id, &self.item_id, &self.amount]) ;
+        let qs = "insert into bids (bidder_id, item_id, amount, source, entered_by, written_at) \
+                  values ($1, $2, $3, $4, $5, $6) returning id, date_created, date_modified";
+        try_insert_to_model!(conn.query(qs, &[&self.bidder_id, &self.item_id, &self.amount,
+                                              &self.source, &self.entered_by, &self.written_at]) ;
                              Bid ;
                              id: 0, date_created: 1, date_modified: 2 ;
-                             bidder_id: self.bidder_id, item_id: self.item_id, amount: self.amount)
+                             bidder_id: self.bidder_id, item_id: self.item_id, amount: self.amount,
+                             source: self.source, entered_by: self.entered_by, written_at: self.written_at)
     }
 }
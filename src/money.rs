@@ -0,0 +1,173 @@
+//! Money
+//!
+//! Monetary amounts in minor units (cents) tagged with an ISO-4217 currency.
+//! Arithmetic is checked: mixing currencies or overflowing an `i64`
+//! returns an error instead of producing a bogus amount.
+//!
+use std::fmt;
+use rustc_serialize::{Encodable, Encoder};
+
+use errors::*;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// ISO-4217 currency with display symbol and number of minor-unit digits
+pub struct Currency {
+    pub code: &'static str,
+    pub symbol: &'static str,
+    pub exponent: u32,
+}
+
+pub const USD: Currency = Currency { code: "USD", symbol: "$", exponent: 2 };
+pub const CAD: Currency = Currency { code: "CAD", symbol: "$", exponent: 2 };
+pub const AUD: Currency = Currency { code: "AUD", symbol: "$", exponent: 2 };
+pub const EUR: Currency = Currency { code: "EUR", symbol: "€", exponent: 2 };
+pub const GBP: Currency = Currency { code: "GBP", symbol: "£", exponent: 2 };
+pub const JPY: Currency = Currency { code: "JPY", symbol: "¥", exponent: 0 };
+
+const CURRENCIES: [Currency; 6] = [USD, CAD, AUD, EUR, GBP, JPY];
+
+impl Currency {
+    /// Look up a supported currency by its (case-insensitive) ISO code
+    pub fn from_code(code: &str) -> Result<Currency> {
+        let code = code.trim().to_uppercase();
+        match CURRENCIES.iter().find(|c| c.code == code) {
+            Some(c) => Ok(*c),
+            None => bail!(ErrorKind::UnknownCurrency(code)),
+        }
+    }
+
+    fn scale(&self) -> i64 {
+        10i64.pow(self.exponent)
+    }
+}
+impl Default for Currency {
+    fn default() -> Currency {
+        USD
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An amount of minor currency units
+pub struct Money {
+    pub amount: i64,
+    pub currency: Currency,
+}
+impl Money {
+    pub fn new(amount: i64, currency: Currency) -> Money {
+        Money { amount: amount, currency: currency }
+    }
+
+    pub fn zero(currency: Currency) -> Money {
+        Money::new(0, currency)
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<()> {
+        if self.currency != other.currency {
+            bail!(ErrorKind::CurrencyMismatch(self.currency.code.into(), other.currency.code.into()));
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money> {
+        self.same_currency(other)?;
+        match self.amount.checked_add(other.amount) {
+            Some(amount) => Ok(Money::new(amount, self.currency)),
+            None => bail!(ErrorKind::MoneyOverflow),
+        }
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money> {
+        self.same_currency(other)?;
+        match self.amount.checked_sub(other.amount) {
+            Some(amount) => Ok(Money::new(amount, self.currency)),
+            None => bail!(ErrorKind::MoneyOverflow),
+        }
+    }
+
+    pub fn checked_mul(&self, factor: i64) -> Result<Money> {
+        match self.amount.checked_mul(factor) {
+            Some(amount) => Ok(Money::new(amount, self.currency)),
+            None => bail!(ErrorKind::MoneyOverflow),
+        }
+    }
+
+    /// Sum a collection of amounts, all of which must be in `currency`
+    pub fn sum<'a, I: IntoIterator<Item=&'a Money>>(currency: Currency, amounts: I) -> Result<Money> {
+        amounts.into_iter().fold(Ok(Money::zero(currency)), |acc, m| acc.and_then(|acc| acc.checked_add(m)))
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.amount < 0
+    }
+
+    /// Parse user input such as `"$1,250.00"`, `"1250"` or `"-3.5"` into
+    /// minor units of `currency`. Fractional digits beyond the currency's
+    /// precision are rejected rather than rounded.
+    pub fn parse(input: &str, currency: Currency) -> Result<Money> {
+        let invalid = || ErrorKind::InvalidMoney(input.to_string());
+        let mut s = input.trim();
+        let negative = s.starts_with('-');
+        if negative { s = s[1..].trim_left(); }
+        if s.starts_with(currency.symbol) { s = &s[currency.symbol.len()..]; }
+        if s.to_uppercase().ends_with(currency.code) { s = s[..s.len() - currency.code.len()].trim_right(); }
+
+        let mut parts = s.splitn(2, '.');
+        let whole = parts.next().unwrap_or("");
+        let frac = parts.next().unwrap_or("");
+        // thousands separators are optional, but must group by threes when present
+        let mut groups = whole.split(',');
+        let lead = groups.next().unwrap_or("");
+        if whole.contains(',') && (lead.is_empty() || lead.len() > 3 || !groups.all(|g| g.len() == 3)) {
+            bail!(invalid());
+        }
+        let whole = whole.replace(',', "");
+        let whole = whole.as_str();
+        if (whole.is_empty() && frac.is_empty()) ||
+            !whole.chars().all(|c| c.is_digit(10)) ||
+            !frac.chars().all(|c| c.is_digit(10)) ||
+            frac.len() > currency.exponent as usize {
+            bail!(invalid());
+        }
+
+        let whole = if whole.is_empty() { 0 } else { whole.parse::<i64>().chain_err(&invalid)? };
+        let frac = if frac.is_empty() { 0 } else {
+            frac.parse::<i64>().chain_err(&invalid)? * 10i64.pow(currency.exponent - frac.len() as u32)
+        };
+        let amount = whole.checked_mul(currency.scale())
+            .and_then(|a| a.checked_add(frac))
+            .ok_or(ErrorKind::MoneyOverflow)?;
+        Ok(Money::new(if negative { -amount } else { amount }, currency))
+    }
+}
+impl fmt::Display for Money {
+    /// Format as e.g. `$1,250.00` or `-$3.50`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scale = self.currency.scale() as u64;
+        let abs = self.amount.wrapping_abs() as u64;
+        let whole = (abs / scale).to_string();
+        let mut grouped = String::new();
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 { grouped.push(','); }
+            grouped.push(c);
+        }
+        let sign = if self.is_negative() { "-" } else { "" };
+        if self.currency.exponent == 0 {
+            write!(f, "{}{}{}", sign, self.currency.symbol, grouped)
+        } else {
+            write!(f, "{}{}{}.{:0width$}", sign, self.currency.symbol, grouped,
+                   abs % scale, width = self.currency.exponent as usize)
+        }
+    }
+}
+impl Encodable for Money {
+    /// Encodes as `{"amount": 125000, "currency": "USD", "display": "$1,250.00"}`
+    fn encode<S: Encoder>(&self, s: &mut S) -> ::std::result::Result<(), S::Error> {
+        s.emit_struct("Money", 3, |s| {
+            s.emit_struct_field("amount", 0, |s| self.amount.encode(s))?;
+            s.emit_struct_field("currency", 1, |s| self.currency.code.encode(s))?;
+            s.emit_struct_field("display", 2, |s| self.to_string().encode(s))
+        })
+    }
+}
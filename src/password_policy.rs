@@ -0,0 +1,69 @@
+//! Password Policy
+//!
+//! Rules new passwords have to follow: a minimum length, a generous
+//! maximum (hashing time grows with length) and not being a common or
+//! breached password. The denylist has one password per line (`#` starts
+//! a comment): `resources/password-denylist.txt` is built in, and a file
+//! named by `PASSWORD_DENYLIST` is read once and added to it, e.g. a
+//! larger breach corpus. Existing passwords aren't re-checked.
+//!
+use std::env;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+
+pub const MIN_CHARS: usize = 8;
+pub const MAX_BYTES: usize = 1024;
+
+
+lazy_static! {
+    static ref DENYLIST: HashSet<String> = load_denylist();
+}
+
+
+const BUILTIN_DENYLIST: &'static str = include_str!("../resources/password-denylist.txt");
+
+
+/// Lowercased passwords from denylist lines
+fn denylist_entries<I: Iterator<Item=String>>(lines: I) -> Vec<String> {
+    lines.map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+
+/// The built-in denylist plus `PASSWORD_DENYLIST`, if set. A missing
+/// extra file is reported but leaves the built-in list in place rather
+/// than stopping every signup.
+fn load_denylist() -> HashSet<String> {
+    let mut denylist: HashSet<String> = denylist_entries(BUILTIN_DENYLIST.lines().map(String::from))
+        .into_iter().collect();
+    if let Ok(path) = env::var("PASSWORD_DENYLIST") {
+        match File::open(&path) {
+            Ok(file) => denylist.extend(denylist_entries(BufReader::new(file).lines().filter_map(|line| line.ok()))),
+            Err(err) => println!("Password denylist '{}' not loaded: {}", path, err),
+        }
+    }
+    denylist
+}
+
+
+/// Whether a password is on the denylist, ignoring case
+pub fn is_denied(password: &str) -> bool {
+    DENYLIST.contains(&password.to_lowercase())
+}
+
+
+/// Return why a password isn't allowed, if it isn't
+pub fn check(password: &str) -> Option<String> {
+    if password.chars().count() < MIN_CHARS {
+        Some(format!("must be at least {} characters", MIN_CHARS))
+    } else if password.len() > MAX_BYTES {
+        Some(format!("must be at most {} bytes", MAX_BYTES))
+    } else if is_denied(password) {
+        Some("is too common, please choose another".into())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,105 @@
+//! Password Reset
+//!
+//! Forgotten passwords are recovered through an emailed single-use link.
+//! Only the reset token's hash is stored, and requesting a new link
+//! expires any earlier ones.
+//!
+use chrono;
+use postgres::{self, Connection};
+
+use models::User;
+use mailer::{self, Mailer};
+use validation::Validator;
+use audit::{self, Action};
+use account;
+use auth;
+use sql;
+use errors::*;
+
+
+/// Reset links expire after this many minutes
+pub const RESET_LIFE_MINUTES: i64 = 60;
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct PasswordReset {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: Vec<u8>,
+    pub expires_at: chrono::DateTime<chrono::UTC>,
+    pub used_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl PasswordReset {
+    pub fn from_row(row: postgres::rows::Row) -> PasswordReset {
+        PasswordReset {
+            id: row.get(0),
+            user_id: row.get(1),
+            token_hash: row.get(2),
+            expires_at: row.get(3),
+            used_at: row.get(4),
+            date_created: row.get(5),
+            date_modified: row.get(6),
+        }
+    }
+
+    /// Find an unexpired, unused reset by its (unhashed) token
+    pub fn get_open_by_token(conn: &Connection, token: &str) -> Option<PasswordReset> {
+        let qs = "select * from password_resets where token_hash=$1 \
+                  and used_at is null and expires_at > now() for update";
+        query_or_none!(conn.query(qs, &[&auth::hash_token(token)]), PasswordReset)
+    }
+}
+
+
+/// Email a reset link to the user with this address. Unknown addresses
+/// are ignored so callers can't learn which emails are registered.
+pub fn request_reset(conn: &Connection, mailer: &Mailer, email: &str) -> Result<()> {
+    let user = match sql::select_user_by_email(conn, &email.trim().to_lowercase()) {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    conn.execute("update password_resets set expires_at=now() \
+                  where user_id=$1 and used_at is null and expires_at > now()", &[&user.id])
+        .chain_err(|| "Error expiring earlier resets")?;
+
+    let token = auth::new_token()?;
+    let token_hash = auth::hash_token(&token);
+    let expires_at = chrono::UTC::now() + chrono::Duration::minutes(RESET_LIFE_MINUTES);
+    let qs = "insert into password_resets (user_id, token_hash, expires_at) \
+              values ($1, $2, $3) returning id, date_created, date_modified";
+    let reset: Result<PasswordReset> = try_insert_to_model!(
+        conn.query(qs, &[&user.id, &token_hash, &expires_at]) ;
+        PasswordReset ;
+        id: 0, date_created: 1, date_modified: 2 ;
+        user_id: user.id, token_hash: token_hash, expires_at: expires_at, used_at: None);
+    reset?;
+
+    let body = format!("Someone asked to reset the password for your account. If it \
+                        wasn't you, you can ignore this email.\n\n\
+                        Choose a new password here:\n\n{}/#/password/reset?token={}\n\n\
+                        This link expires at {}.",
+                       mailer::public_url(), token, expires_at.format("%Y-%m-%d %H:%M UTC"));
+    mailer.send(&user.email, "Reset your password", &body)
+        .chain_err(|| "Error sending reset email")
+}
+
+
+/// Set a new password (with a fresh salt) for the user owning a reset
+/// token, using up the token. Callers should end the user's sessions.
+/// Should be run in `sql::transaction`.
+pub fn reset(conn: &Connection, token: &str, password: &str) -> Result<User> {
+    let mut v = Validator::new();
+    v.password("password", password);
+    v.finish()?;
+
+    let reset = PasswordReset::get_open_by_token(conn, token)
+        .ok_or("reset link is invalid or has expired")?;
+    let user = sql::get_user(conn, reset.user_id).ok_or("user not found")?;
+    account::set_password(conn, &user, password)?;
+    conn.execute("update password_resets set used_at=now() where id=$1", &[&reset.id])
+        .chain_err(|| "Error using reset")?;
+    audit::record(conn, user.id, Action::PasswordReset, None)?;
+    Ok(user)
+}
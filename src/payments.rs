@@ -0,0 +1,143 @@
+//! Payments
+//!
+//! Payment gateway abstraction & card pre-authorization holds
+//!
+use std::env;
+use std::sync::Arc;
+use chrono;
+use uuid::Uuid;
+use postgres::{self, Connection};
+
+use models::{Bidder, PaymentInfo};
+use money::Money;
+use errors::*;
+
+
+/// A card processor able to place, capture and release authorization holds
+pub trait PaymentGateway: Send + Sync {
+    /// Name recorded alongside any holds placed through this gateway
+    fn name(&self) -> &'static str;
+
+    /// Place a hold for `amount` on the card, returning the gateway's reference
+    fn authorize(&self, payment: &PaymentInfo, amount: &Money) -> Result<String>;
+
+    /// Collect `amount` against a previously authorized hold
+    fn capture(&self, reference: &str, amount: &Money) -> Result<()>;
+
+    /// Drop a previously authorized hold
+    fn release(&self, reference: &str) -> Result<()>;
+}
+
+
+/// Stand-in gateway that approves any unexpired card and only logs activity.
+/// Used until a real processor is configured, and in tests.
+pub struct LogGateway;
+impl PaymentGateway for LogGateway {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn authorize(&self, payment: &PaymentInfo, amount: &Money) -> Result<String> {
+        if payment.cc_exp < chrono::UTC::today().naive_utc() {
+            bail!("card expired");
+        }
+        let reference = Uuid::new_v4().to_string();
+        println!(">> [log-gateway] authorized {} on payment-info {}: {}", amount, payment.id, reference);
+        Ok(reference)
+    }
+
+    fn capture(&self, reference: &str, amount: &Money) -> Result<()> {
+        println!(">> [log-gateway] captured {} on {}", amount, reference);
+        Ok(())
+    }
+
+    fn release(&self, reference: &str) -> Result<()> {
+        println!(">> [log-gateway] released {}", reference);
+        Ok(())
+    }
+}
+
+
+/// Select the gateway named by `PAYMENT_GATEWAY`, defaulting to `log`
+pub fn gateway_from_env() -> Result<Arc<PaymentGateway>> {
+    match env::var("PAYMENT_GATEWAY").unwrap_or("log".into()).as_str() {
+        "log" => Ok(Arc::new(LogGateway)),
+        other => bail!("unknown payment gateway: {}", other),
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct PaymentHold {
+    pub id: i32,
+    pub bidder_id: i32,
+    pub payment_info_id: i32,
+    pub amount: i64,
+    pub gateway: String,
+    pub gateway_ref: String,
+    pub status: String,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl PaymentHold {
+    pub fn from_row(row: postgres::rows::Row) -> PaymentHold {
+        PaymentHold {
+            id: row.get(0),
+            bidder_id: row.get(1),
+            payment_info_id: row.get(2),
+            amount: row.get(3),
+            gateway: row.get(4),
+            gateway_ref: row.get(5),
+            status: row.get(6),
+            date_created: row.get(7),
+            date_modified: row.get(8),
+        }
+    }
+
+    /// The bidder's outstanding (authorized, not yet captured or released) hold
+    pub fn active_for_bidder(conn: &Connection, bidder_id: i32) -> Option<PaymentHold> {
+        let qs = "select * from payment_holds where bidder_id=$1 and status='authorized' \
+                  order by date_created desc limit 1";
+        query_or_none!(conn.query(qs, &[&bidder_id]), PaymentHold)
+    }
+}
+
+
+/// Place a pre-authorization hold on a bidder's card and record it
+pub fn preauthorize(conn: &Connection, gateway: &PaymentGateway, bidder: &Bidder,
+                    payment: &PaymentInfo, amount: &Money) -> Result<PaymentHold> {
+    if amount.amount <= 0 {
+        bail!("pre-authorization amount must be positive");
+    }
+    let reference = gateway.authorize(payment, amount).chain_err(|| "Card pre-authorization declined")?;
+    let hold = record_hold(conn, gateway, bidder, payment, amount, &reference);
+    if hold.is_err() {
+        // don't leave a hold on the card that nothing records
+        if let Err(err) = gateway.release(&reference) {
+            println!("Error releasing unrecorded hold {}: {}", reference, err);
+        }
+    }
+    hold
+}
+
+
+fn record_hold(conn: &Connection, gateway: &PaymentGateway, bidder: &Bidder,
+               payment: &PaymentInfo, amount: &Money, reference: &str) -> Result<PaymentHold> {
+    let qs = "insert into payment_holds (bidder_id, payment_info_id, amount, gateway, gateway_ref) \
+              values ($1, $2, $3, $4, $5) returning id, status, date_created, date_modified";
+    let gateway_name = gateway.name().to_string();
+    try_insert_to_model!(conn.query(qs, &[&bidder.id, &payment.id, &amount.amount, &gateway_name, &reference]) ;
+                         PaymentHold ;
+                         id: 0, status: 1, date_created: 2, date_modified: 3 ;
+                         bidder_id: bidder.id, payment_info_id: payment.id, amount: amount.amount,
+                         gateway: gateway_name, gateway_ref: reference.to_string())
+}
+
+
+/// Release an outstanding hold through the gateway and mark it released
+pub fn release_hold(conn: &Connection, gateway: &PaymentGateway, hold: &PaymentHold) -> Result<()> {
+    gateway.release(&hold.gateway_ref)?;
+    conn.execute("update payment_holds set status='released' where id=$1", &[&hold.id])
+        .chain_err(|| "Error updating payment hold")?;
+    Ok(())
+}
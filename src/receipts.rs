@@ -0,0 +1,258 @@
+//! Receipts
+//!
+//! Year-end tax-deductibility receipts. The deductible portion of a won
+//! item is the price paid less its fair market value (`Item::value`);
+//! goal pledges receive nothing in return and are fully deductible.
+//! Only money actually received counts: the bidder's payments, less
+//! refunds, are applied to their sales and pledges oldest first, and each
+//! receipt covers what was applied during its year.
+//!
+use std::fmt::Write;
+use chrono;
+use postgres::Connection;
+
+use models::{Organization, Bidder};
+use money::{Money, Currency};
+use sql;
+use errors::*;
+
+
+#[derive(Debug, RustcEncodable)]
+/// Organization details printed on a receipt, taken from `Organization::extra`
+pub struct OrgDetails {
+    pub name: String,
+    pub address: Option<String>,
+    pub tax_id: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub note: Option<String>,
+}
+impl OrgDetails {
+    pub fn from_org(org: &Organization) -> OrgDetails {
+        let field = |key: &str| {
+            org.extra.as_ref()
+                .and_then(|ex| ex.find(key))
+                .and_then(|val| val.as_string())
+                .map(|val| val.to_string())
+        };
+        OrgDetails {
+            name: org.name.clone(),
+            address: field("address"),
+            tax_id: field("tax_id"),
+            email: field("email"),
+            phone: field("phone"),
+            note: field("receipt_note"),
+        }
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct ReceiptLine {
+    pub kind: String,
+    pub description: String,
+    pub date: chrono::DateTime<chrono::UTC>,
+    pub paid: Money,
+    pub fair_market_value: Money,
+    pub deductible: Money,
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct Receipt {
+    pub organization: OrgDetails,
+    pub bidder_id: i32,
+    pub bidder_name: String,
+    pub year: i32,
+    pub lines: Vec<ReceiptLine>,
+    pub total_paid: Money,
+    pub total_deductible: Money,
+}
+impl Receipt {
+    /// Collect what a bidder paid towards their sales and pledges during `year`
+    pub fn build(conn: &Connection, bidder: &Bidder, year: i32) -> Result<Receipt> {
+        let org = sql::get_organization(conn, bidder.organization_id)
+            .ok_or("organization not found")?;
+        let currency = org.currency()?;
+        let bidder_name = sql::select_primary_profile(conn, bidder.id)
+            .map(|prof| prof.name)
+            .unwrap_or(bidder.id_name.clone());
+
+        let qs = "select t.kind, i.title, coalesce(i.value, 0), e.debit, t.date_created \
+                  from ledger_transactions t \
+                  join ledger_entries e on e.transaction_id = t.id and e.account = 'bidder_receivable' \
+                  join items i on i.id = t.item_id \
+                  where e.bidder_id = $1 and t.kind in ('sale', 'pledge') \
+                  and extract(year from t.date_created)::integer <= $2 \
+                  order by t.date_created, t.id";
+        let rows = conn.query(qs, &[&bidder.id, &year]).chain_err(|| "Error querying receipt lines")?;
+        let (paid_before, paid_through) = net_payments(conn, bidder.id, year)?;
+
+        let mut lines = vec![];
+        let mut owed_before = 0i64;
+        for row in rows.iter() {
+            let kind: String = row.get(0);
+            let owed: i64 = row.get(3);
+            let fmv: i64 = if kind == "pledge" { 0 } else { row.get(2) };
+            let share = paid_in_year(paid_before, paid_through, owed_before, owed, fmv);
+            owed_before = owed_before.checked_add(owed).ok_or(ErrorKind::MoneyOverflow)?;
+            let (paid, deductible) = match share {
+                Some(share) => share,
+                None => continue,
+            };
+            lines.push(ReceiptLine {
+                kind: kind,
+                description: row.get(1),
+                date: row.get(4),
+                paid: Money::new(paid, currency),
+                fair_market_value: Money::new(fmv, currency),
+                deductible: Money::new(deductible, currency),
+            });
+        }
+
+        Ok(Receipt {
+            organization: OrgDetails::from_org(&org),
+            bidder_id: bidder.id,
+            bidder_name: bidder_name,
+            year: year,
+            total_paid: Money::sum(currency, lines.iter().map(|l| &l.paid))?,
+            total_deductible: Money::sum(currency, lines.iter().map(|l| &l.deductible))?,
+            lines: lines,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.total_paid.currency
+    }
+
+    /// Plain text rendering, suitable for email bodies
+    pub fn to_text(&self) -> String {
+        let org = &self.organization;
+        let mut out = String::new();
+        writeln!(out, "{}", org.name).unwrap();
+        for line in [&org.address, &org.email, &org.phone].iter().filter_map(|l| l.as_ref()) {
+            writeln!(out, "{}", line).unwrap();
+        }
+        if let Some(ref tax_id) = org.tax_id {
+            writeln!(out, "Tax ID: {}", tax_id).unwrap();
+        }
+        writeln!(out, "\n{} Donation Receipt", self.year).unwrap();
+        writeln!(out, "Donor: {} (bidder #{})\n", self.bidder_name, self.bidder_id).unwrap();
+        writeln!(out, "{:<12} {:<30} {:>14} {:>14} {:>14}",
+                 "date", "description", "paid", "fair value", "deductible").unwrap();
+        for line in self.lines.iter() {
+            writeln!(out, "{:<12} {:<30} {:>14} {:>14} {:>14}",
+                     line.date.format("%Y-%m-%d").to_string(), line.description,
+                     line.paid.to_string(), line.fair_market_value.to_string(),
+                     line.deductible.to_string()).unwrap();
+        }
+        writeln!(out, "\n{:<43} {:>14} {:>14} {:>14}", "total",
+                 self.total_paid.to_string(), "", self.total_deductible.to_string()).unwrap();
+        if let Some(ref note) = org.note {
+            writeln!(out, "\n{}", note).unwrap();
+        }
+        out
+    }
+
+    /// Standalone HTML rendering, suitable for printing
+    pub fn to_html(&self) -> String {
+        let org = &self.organization;
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">");
+        writeln!(out, "<title>{} {} Donation Receipt</title>", escape_html(&org.name), self.year).unwrap();
+        out.push_str("<style>body{font-family:sans-serif;max-width:48em;margin:2em auto}\
+                      table{width:100%;border-collapse:collapse}\
+                      td,th{padding:.3em;border-bottom:1px solid #ccc}\
+                      .amt{text-align:right}</style></head><body>\n");
+        writeln!(out, "<h1>{}</h1>", escape_html(&org.name)).unwrap();
+        for line in [&org.address, &org.email, &org.phone].iter().filter_map(|l| l.as_ref()) {
+            writeln!(out, "<div>{}</div>", escape_html(line)).unwrap();
+        }
+        if let Some(ref tax_id) = org.tax_id {
+            writeln!(out, "<div>Tax ID: {}</div>", escape_html(tax_id)).unwrap();
+        }
+        writeln!(out, "<h2>{} Donation Receipt</h2>", self.year).unwrap();
+        writeln!(out, "<p>Donor: {} (bidder #{})</p>", escape_html(&self.bidder_name), self.bidder_id).unwrap();
+        out.push_str("<table><tr><th>Date</th><th>Description</th><th class=\"amt\">Paid</th>\
+                      <th class=\"amt\">Fair value</th><th class=\"amt\">Deductible</th></tr>\n");
+        for line in self.lines.iter() {
+            writeln!(out, "<tr><td>{}</td><td>{}</td><td class=\"amt\">{}</td>\
+                           <td class=\"amt\">{}</td><td class=\"amt\">{}</td></tr>",
+                     line.date.format("%Y-%m-%d"), escape_html(&line.description),
+                     line.paid, line.fair_market_value, line.deductible).unwrap();
+        }
+        writeln!(out, "<tr><th colspan=\"2\">Total</th><th class=\"amt\">{}</th><th></th>\
+                       <th class=\"amt\">{}</th></tr></table>",
+                 self.total_paid, self.total_deductible).unwrap();
+        if let Some(ref note) = org.note {
+            writeln!(out, "<p>{}</p>", escape_html(note)).unwrap();
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+}
+
+
+/// Portion of an amount `owed`, after `owed_before` of earlier amounts, that
+/// `paid` in total covers
+pub fn applied(paid: i64, owed_before: i64, owed: i64) -> i64 {
+    paid.saturating_sub(owed_before).max(0).min(owed)
+}
+
+
+/// How much of an amount `owed` (worth `fmv`) was paid during a year, and
+/// how much of that is deductible, given the bidder's net payments before
+/// and through the end of the year. `None` if none of it was paid that year.
+pub fn paid_in_year(paid_before: i64, paid_through: i64, owed_before: i64, owed: i64, fmv: i64)
+    -> Option<(i64, i64)>
+{
+    let applied_before = applied(paid_before, owed_before, owed);
+    let applied_through = applied(paid_through, owed_before, owed);
+    // refunds can take back more than was paid this year; that
+    // reduces the earlier year's receipt rather than this one
+    if applied_through <= applied_before {
+        return None;
+    }
+    let deductible = (applied_through - fmv).max(0) - (applied_before - fmv).max(0);
+    Some((applied_through - applied_before, deductible))
+}
+
+
+/// A bidder's payments less refunds, totaled before `year` and through the
+/// end of `year`
+fn net_payments(conn: &Connection, bidder_id: i32, year: i32) -> Result<(i64, i64)> {
+    let qs = "select coalesce(sum(case when m.year < $2 then m.amount else 0 end), 0)::bigint, \
+                     coalesce(sum(m.amount), 0)::bigint from \
+              (select e.credit as amount, extract(year from t.date_created)::integer as year \
+               from ledger_transactions t \
+               join ledger_entries e on e.transaction_id = t.id and e.account = 'bidder_receivable' \
+               where t.kind = 'payment' and e.bidder_id = $1 \
+               union all \
+               select -e.debit, extract(year from t.date_created)::integer \
+               from ledger_transactions t \
+               join ledger_entries e on e.transaction_id = t.id and e.account = 'refunds' \
+               where t.kind = 'refund' and e.bidder_id = $1) m \
+              where m.year <= $2";
+    let rows = conn.query(qs, &[&bidder_id, &year]).chain_err(|| "Error totaling payments")?;
+    let row = rows.iter().next().ok_or("no payment totals")?;
+    Ok((row.get(0), row.get(1)))
+}
+
+
+pub fn escape_html(s: &str) -> String {
+    s.chars().fold(String::with_capacity(s.len()), |mut acc, c| {
+        match c {
+            '&' => acc.push_str("&amp;"),
+            '<' => acc.push_str("&lt;"),
+            '>' => acc.push_str("&gt;"),
+            '"' => acc.push_str("&quot;"),
+            '\'' => acc.push_str("&#39;"),
+            _ => acc.push(c),
+        }
+        acc
+    })
+}
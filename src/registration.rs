@@ -0,0 +1,149 @@
+//! Registration
+//!
+//! Event registration for bidders & paddle number assignment.
+//! Paddle numbers are handed out sequentially per organization from
+//! `organizations.next_paddle`, which is incremented atomically.
+//!
+use postgres::Connection;
+
+use models::{self, User, Bidder, PaymentInfo};
+use money::Money;
+use payments::{self, PaymentGateway, PaymentHold};
+use roles::{self, Role};
+use sql;
+use errors::*;
+
+
+/// Take the next paddle number for an organization
+pub fn next_paddle(conn: &Connection, org_id: i32) -> Result<i32> {
+    let qs = "update organizations set next_paddle = next_paddle + 1 where id=$1 \
+              returning next_paddle - 1";
+    let rows = conn.query(qs, &[&org_id]).chain_err(|| "Error taking next paddle number")?;
+    match rows.iter().next() {
+        Some(row) => Ok(row.get(0)),
+        None => bail!("organization not found"),
+    }
+}
+
+
+/// Give a bidder the next paddle number for its organization,
+/// leaving bidders that already have one untouched
+pub fn assign_paddle(conn: &Connection, bidder: Bidder) -> Result<Bidder> {
+    if bidder.paddle.is_some() {
+        return Ok(bidder);
+    }
+    let paddle = next_paddle(conn, bidder.organization_id)?;
+    conn.execute("update bidders set paddle=$1 where id=$2", &[&paddle, &bidder.id])
+        .chain_err(|| "Error assigning paddle")?;
+    Ok(Bidder { paddle: Some(paddle), ..bidder })
+}
+
+
+/// Assign paddle numbers to every bidder in an organization that doesn't
+/// have one yet, in `id_name` order. Returns the newly numbered bidders.
+pub fn assign_paddles(conn: &Connection, org_id: i32) -> Result<Vec<Bidder>> {
+    let qs = "select * from bidders where organization_id=$1 and paddle is null \
+              order by lower(id_name), id for update";
+    let unassigned = query_coll!(conn.query(qs, &[&org_id]), Bidder);
+    unassigned.into_iter().map(|bidder| assign_paddle(conn, bidder)).collect()
+}
+
+
+#[derive(Debug)]
+/// Card details supplied at registration
+pub struct CardDetails {
+    pub number: String,
+    pub pin: String,
+    pub exp: ::chrono::NaiveDate,
+}
+
+
+#[derive(Debug)]
+/// A logged-in user's request to register for an organization's event
+pub struct Registration {
+    pub organization_id: i32,
+    pub id_name: Option<String>,
+    pub accept_terms: bool,
+    pub card: Option<CardDetails>,
+}
+
+
+#[derive(Debug)]
+pub struct Registered {
+    pub bidder: Bidder,
+    /// Card and amount to pre-authorize when the organization requires a hold
+    pub hold_due: Option<(PaymentInfo, Money)>,
+}
+
+
+/// Register a user's profile as a bidder with an organization.
+///
+/// If the profile already belongs to a bidder in the organization (e.g. one
+/// pre-created by an admin, or a household member's) that bidder is joined,
+/// otherwise a new bidder is created with the profile as its primary.
+/// Either way the bidder ends up with a paddle number and accepted terms,
+/// and the user is granted the bidder role in the organization.
+/// If a card is supplied it's attached to the profile, and returned in
+/// `hold_due` when the organization requires a hold. Should be run in
+/// `sql::transaction`, followed by `place_hold` once it has committed.
+pub fn register(conn: &Connection, user: &User, reg: Registration) -> Result<Registered> {
+    if !reg.accept_terms {
+        bail!("terms must be accepted to register");
+    }
+    let org = sql::get_organization(conn, reg.organization_id).ok_or("organization not found")?;
+    let profile = sql::select_profile_by_user(conn, &user.id).ok_or("user has no profile")?;
+
+    let existing = profile.bidder_id.and_then(|id| sql::get_bidder(conn, id));
+    let bidder = match existing {
+        Some(ref bidder) if bidder.organization_id != org.id => {
+            bail!("already registered with another organization");
+        }
+        Some(bidder) => bidder,
+        None => {
+            let id_name = reg.id_name.clone().unwrap_or(profile.name.clone());
+            let bidder = models::NewBidder::new(org.id, &id_name).create(conn)
+                .chain_err(|| "Error creating bidder")?;
+            conn.execute("update profiles set bidder_id=$1, is_primary=true where id=$2",
+                         &[&bidder.id, &profile.id])
+                .chain_err(|| "Error attaching profile to bidder")?;
+            bidder
+        }
+    };
+    let bidder = assign_paddle(conn, bidder)?;
+    roles::grant(conn, user.id, Role::Bidder, Some(org.id))?;
+    let qs = "update bidders set terms_accepted_at=coalesce(terms_accepted_at, now()) \
+              where id=$1 returning terms_accepted_at";
+    let rows = conn.query(qs, &[&bidder.id]).chain_err(|| "Error accepting terms")?;
+    let accepted = rows.iter().next().map(|row| row.get(0));
+    let bidder = Bidder { terms_accepted_at: accepted, ..bidder };
+
+    let hold_due = match reg.card {
+        Some(card) => {
+            let payment = models::NewPaymentInfo::new(&card.number, &card.pin, &card.exp).create(conn)
+                .chain_err(|| "Error saving payment information")?;
+            conn.execute("update profiles set payment_info_id=$1 where id=$2", &[&payment.id, &profile.id])
+                .chain_err(|| "Error attaching payment information")?;
+            match org.preauth_amount {
+                Some(amount) => Some((payment, Money::new(amount, org.currency()?))),
+                None => None,
+            }
+        }
+        None => None,
+    };
+    Ok(Registered { bidder: bidder, hold_due: hold_due })
+}
+
+
+/// Pre-authorize the card of a committed registration, if a hold is due.
+/// The gateway isn't transactional, so this runs after `register`'s
+/// transaction rather than in it: a registration that rolls back never
+/// leaves a hold on the card. A declined card leaves the bidder registered
+/// without a hold; registering again with another card retries it.
+pub fn place_hold(conn: &Connection, gateway: &PaymentGateway, registered: &Registered) -> Result<Option<PaymentHold>> {
+    match registered.hold_due {
+        Some((ref payment, ref amount)) => {
+            payments::preauthorize(conn, gateway, &registered.bidder, payment, amount).map(Some)
+        }
+        None => Ok(None),
+    }
+}
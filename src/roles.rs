@@ -0,0 +1,173 @@
+//! Roles
+//!
+//! Role-based access control. Users are granted roles either globally or
+//! within one organization; each role carries a fixed set of permissions.
+//! Handlers check permissions with `require` (or the `handlers::Require`
+//! wrapper), never against roles directly. Roles are granted and revoked
+//! from the server cli.
+//!
+use chrono;
+use postgres::{self, Connection};
+
+use models::User;
+use errors::*;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcEncodable)]
+pub enum Role {
+    SuperAdmin,
+    OrgAdmin,
+    Volunteer,
+    Bidder,
+}
+impl Role {
+    pub fn all() -> Vec<Role> {
+        vec![Role::SuperAdmin, Role::OrgAdmin, Role::Volunteer, Role::Bidder]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Role::SuperAdmin => "super_admin",
+            Role::OrgAdmin => "org_admin",
+            Role::Volunteer => "volunteer",
+            Role::Bidder => "bidder",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Role> {
+        Role::all().into_iter().find(|role| role.as_str() == s)
+    }
+
+    /// Permissions carried by the role
+    pub fn permissions(&self) -> Vec<Permission> {
+        match *self {
+            Role::SuperAdmin => Permission::all(),
+            Role::OrgAdmin => vec![Permission::ManageOrganization, Permission::CheckIn, Permission::EnterBids],
+            Role::Volunteer => vec![Permission::CheckIn, Permission::EnterBids],
+            Role::Bidder => vec![Permission::PlaceBids],
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcEncodable)]
+pub enum Permission {
+    /// Create organizations and act across all of them
+    ManageOrganizations,
+    /// Edit an organization's settings, items and bidders
+    ManageOrganization,
+    /// Use the check-in desk
+    CheckIn,
+    /// Enter bids on behalf of bidders, e.g. from paper bid sheets
+    EnterBids,
+    /// Bid as a registered bidder
+    PlaceBids,
+}
+impl Permission {
+    pub fn all() -> Vec<Permission> {
+        vec![Permission::ManageOrganizations, Permission::ManageOrganization,
+             Permission::CheckIn, Permission::EnterBids, Permission::PlaceBids]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Permission::ManageOrganizations => "manage_organizations",
+            Permission::ManageOrganization => "manage_organization",
+            Permission::CheckIn => "check_in",
+            Permission::EnterBids => "enter_bids",
+            Permission::PlaceBids => "place_bids",
+        }
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+/// A role granted to a user. `organization_id` is `None` for global grants.
+pub struct UserRole {
+    pub id: i32,
+    pub user_id: i32,
+    pub role: String,
+    pub organization_id: Option<i32>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl UserRole {
+    pub fn from_row(row: postgres::rows::Row) -> UserRole {
+        UserRole {
+            id: row.get(0),
+            user_id: row.get(1),
+            role: row.get(2),
+            organization_id: row.get(3),
+            date_created: row.get(4),
+            date_modified: row.get(5),
+        }
+    }
+
+    pub fn role(&self) -> Option<Role> {
+        Role::from_str(&self.role)
+    }
+
+    /// Whether the grant applies within `org_id`. Global grants apply everywhere;
+    /// with no `org_id` only global grants apply.
+    pub fn applies_to(&self, org_id: Option<i32>) -> bool {
+        match (self.organization_id, org_id) {
+            (None, _) => true,
+            (Some(granted), Some(org_id)) => granted == org_id,
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn grants(&self, permission: Permission) -> bool {
+        self.role().map(|role| role.permissions().contains(&permission)).unwrap_or(false)
+    }
+}
+
+
+/// All of a user's role grants
+pub fn user_roles(conn: &Connection, user_id: i32) -> Vec<UserRole> {
+    let qs = "select * from user_roles where user_id=$1 order by organization_id nulls first, role";
+    query_coll!(conn.query(qs, &[&user_id]), UserRole)
+}
+
+
+/// Whether the user holds `permission` within `org_id`, or globally when `org_id` is `None`
+pub fn has_permission(conn: &Connection, user: &User, permission: Permission, org_id: Option<i32>) -> bool {
+    user_roles(conn, user.id).iter().any(|r| r.grants(permission) && r.applies_to(org_id))
+}
+
+
+/// Whether the user holds `permission` anywhere, globally or in any organization
+pub fn has_permission_anywhere(conn: &Connection, user: &User, permission: Permission) -> bool {
+    user_roles(conn, user.id).iter().any(|r| r.grants(permission))
+}
+
+
+/// Fail with `PermissionDenied` unless the user holds `permission` within `org_id`
+pub fn require(conn: &Connection, user: &User, permission: Permission, org_id: Option<i32>) -> Result<()> {
+    if !has_permission(conn, user, permission, org_id) {
+        bail!(ErrorKind::PermissionDenied(permission.as_str().to_string()));
+    }
+    Ok(())
+}
+
+
+/// Grant a role, globally when `org_id` is `None`. Granting a role the
+/// user already holds is a no-op.
+pub fn grant(conn: &Connection, user_id: i32, role: Role, org_id: Option<i32>) -> Result<()> {
+    if role == Role::SuperAdmin && org_id.is_some() {
+        bail!("super_admin can only be granted globally");
+    }
+    let qs = "insert into user_roles (user_id, role, organization_id) values ($1, $2, $3) \
+              on conflict do nothing";
+    conn.execute(qs, &[&user_id, &role.as_str(), &org_id]).chain_err(|| "Error granting role")?;
+    Ok(())
+}
+
+
+/// Revoke a role grant. Returns whether anything was revoked.
+pub fn revoke(conn: &Connection, user_id: i32, role: Role, org_id: Option<i32>) -> Result<bool> {
+    let qs = "delete from user_roles where user_id=$1 and role=$2 \
+              and coalesce(organization_id, 0) = coalesce($3, 0)";
+    let deleted = conn.execute(qs, &[&user_id, &role.as_str(), &org_id]).chain_err(|| "Error revoking role")?;
+    Ok(deleted > 0)
+}
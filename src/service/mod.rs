@@ -4,7 +4,7 @@
 //!
 mod routes;
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use std::path::Path;
 use mount::Mount;
@@ -24,6 +24,9 @@ use postgres::{Connection, TlsMode as PgTlsMode};
 use handlers::{Handlers};
 use middleware::{InfoLog, SessionMiddleware};
 use sessions::{self, SessionStore};
+use payments;
+use mailer;
+use login_throttle;
 
 
 /// Create a new postgres database connection
@@ -50,10 +53,12 @@ pub fn start(host: &str, quiet: bool) {
     // setup db connection pool
     let db_pool = establish_pool_connection();
     println!(">> Connected to db!");
+    login_throttle::start_daemon_pruner(db_pool.clone(), 24 * 60 * 60);
 
     // setup session store access, exempt url roots, and store-cleaning daemon
-    let session_store = Arc::new(Mutex::new(SessionStore::new(20 * 60)));
-    let exempt_url_roots = hashset!("login", "hello");
+    let session_store = SessionStore::from_env(20 * 60).expect("Failed to setup session store");
+    let session_store = Arc::new(session_store);
+    let exempt_url_roots = hashset!("login", "hello", "display", "signup", "verify", "password");
     let session_middleware = SessionMiddleware::new(session_store.clone(), exempt_url_roots);
     sessions::start_daemon_sweeper(session_store.clone(), 30 * 60);
     println!(">> Session store created");
@@ -62,8 +67,13 @@ pub fn start(host: &str, quiet: bool) {
     env_logger::init().unwrap();
     let (log_before, log_after) = Logger::new(None);
 
+    // setup payment gateway
+    let gateway = payments::gateway_from_env().expect("Failed to setup payment gateway");
+    println!(">> Using '{}' payment gateway", gateway.name());
+    let mailer = mailer::mailer_from_env().expect("Failed to setup mailer");
+
     // initialize handler manager with external resources
-    let handlers = Handlers::new(db_pool, session_store);
+    let handlers = Handlers::new(db_pool, session_store, gateway, mailer);
 
     // Setup endpoints
     let mut router = Router::new();
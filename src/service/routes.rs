@@ -9,11 +9,53 @@ pub fn mount(router: &mut Router, handlers: Handlers) {
 
     router.get("/hello", handlers.hello, "hello");
     router.get("/info", handlers.info, "info");
+    router.get("/me/activity", handlers.activity, "activity");
 
     router.post("/login", handlers.login, "login");
+    router.post("/login/two-factor", handlers.login_two_factor, "login_two_factor");
+    router.post("/login/refresh", handlers.login_refresh, "login_refresh");
     router.post("/logout", handlers.logout, "logout");
 
     router.get("/items", handlers.items, "items");
+    router.post("/bids", handlers.place_bid, "place_bid");
+    router.post("/register", handlers.register, "register");
+    router.post("/signup", handlers.signup, "signup");
+    router.post("/verify", handlers.verify, "verify");
+    router.post("/me/verify", handlers.verify_resend, "verify_resend");
+    router.post("/password/forgot", handlers.password_forgot, "password_forgot");
+    router.post("/password/reset", handlers.password_reset, "password_reset");
+    router.post("/me/password", handlers.change_password, "change_password");
+    router.post("/me/email", handlers.change_email, "change_email");
+    router.post("/me/two-factor", handlers.two_factor_enroll, "two_factor_enroll");
+    router.post("/me/two-factor/confirm", handlers.two_factor_confirm, "two_factor_confirm");
+    router.post("/me/two-factor/recovery-codes", handlers.two_factor_recovery_codes, "two_factor_recovery_codes");
+    router.post("/me/two-factor/disable", handlers.two_factor_disable, "two_factor_disable");
+
+    router.get("/household", handlers.household, "household");
+    router.post("/household/invite", handlers.household_invite, "household_invite");
+    router.post("/household/accept", handlers.household_accept, "household_accept");
+    router.post("/household/remove", handlers.household_remove, "household_remove");
+    router.post("/household/transfer", handlers.household_transfer, "household_transfer");
+
+    router.post("/checkin/search", handlers.checkin_search, "checkin_search");
+    router.post("/checkin", handlers.checkin, "checkin");
+    router.post("/checkin/walkin", handlers.checkin_walk_in, "checkin_walk_in");
+
+    router.post("/admin/bids/bulk", handlers.bulk_bids, "bulk_bids");
+    router.any("/admin/organizations", handlers.admin_orgs, "admin_orgs");
+    router.any("/admin/organizations/:id", handlers.admin_org, "admin_org");
+    router.any("/admin/items", handlers.admin_items, "admin_items");
+    router.any("/admin/items/:id", handlers.admin_item, "admin_item");
+    router.any("/admin/bidders", handlers.admin_bidders, "admin_bidders");
+    router.any("/admin/bidders/:id", handlers.admin_bidder, "admin_bidder");
+    router.any("/admin/profiles", handlers.admin_profiles, "admin_profiles");
+    router.any("/admin/profiles/:id", handlers.admin_profile, "admin_profile");
+
+    router.get("/watchlist", handlers.watchlist, "watchlist");
+    router.post("/watchlist/add", handlers.watchlist_add, "watchlist_add");
+    router.post("/watchlist/remove", handlers.watchlist_remove, "watchlist_remove");
+
+    router.get("/display/:org_id", handlers.display, "display");
     //router.get("/users", handlers.users, "users");
     //router.post("/msg", handlers.post_msg , "post_msg");
     //router.get("/msg", handlers.get_msg, "get_msg");
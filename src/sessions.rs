@@ -3,44 +3,36 @@
 //! Session & SessionStore impls
 //! SessionKey for insertion in iron's request.extensions typemap
 //!
+//! Sessions are kept by a `SessionBackend`, chosen with `SESSION_STORE`:
+//! `memory` (the default) loses every session on restart, while `postgres`
+//! keeps them across restarts and deploys, storing only token hashes.
+//! `jwt` keeps no sessions at all: tokens are signed and carry the user
+//! (see `tokens`), so several servers can share the load.
+//! Backends handle their own locking, so the store is shared between
+//! handlers without a lock around it and one slow lookup doesn't hold
+//! up every other request.
+//!
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
+use std::env;
 use std::thread;
 use std::time;
 
-use uuid::Uuid;
-use chrono;
-use jwt::{encode, Header};
+use chrono::{self, TimeZone};
 use postgres::Connection;
-use json::JsonValue;
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+use json::{self, JsonValue};
 use iron::Request;
 use iron::headers::Authorization;
 use iron::typemap;
 
 use models::User;
-use auth::new_salt;
+use auth;
 use sql;
-
-
-#[derive(RustcEncodable, RustcDecodable)]
-/// Token json web token generation
-struct Claim {
-    salt: Vec<u8>,
-}
-lazy_static! {
-    // generate a new session-secret each time the server starts up
-    // since the session-store gets wiped out anyway
-    static ref SESSION_SECRET: String = Uuid::new_v4().to_string();
-}
-/// generate a new jwt token for auth/session tokens
-fn generate_token() -> Result<String, String> {
-    let salt = new_salt().unwrap();
-    let claim = Claim { salt: salt };
-    match encode(Header::default(), &claim, SESSION_SECRET.as_bytes()) {
-        Ok(t) => Ok(t),
-        Err(_) => Err("failed to generate a token".to_string()),
-    }
-}
+use service;
+use tokens::{self, Signer};
+use errors::*;
 
 
 /// SessionKey type to be inserted & retrieved from iron's request typemap.
@@ -53,7 +45,7 @@ impl typemap::Key for SessionKey {
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// User Session token and information
 pub struct Session {
     pub token: String,
@@ -63,7 +55,7 @@ pub struct Session {
 }
 impl Session {
     pub fn new() -> Session {
-        let token = generate_token().expect("token fail");
+        let token = auth::new_token().expect("token fail");
         Session {
             token: token,
             user_id: None,
@@ -85,85 +77,356 @@ impl Session {
 }
 
 
+/// Tokens handed to the client for a new session
 #[derive(Debug)]
+pub struct Issued {
+    pub token: String,
+    /// For renewing `token` once it expires, when the backend issues them
+    pub refresh_token: Option<String>,
+}
+
+
+/// Where sessions are kept. Sessions expire once they've gone unused
+/// for the store's `life` and are never returned after that.
+pub trait SessionBackend: Send + Sync {
+    /// Store a new session, returning the tokens the client should use
+    fn insert(&self, sess: Session, life: chrono::Duration) -> Result<Issued>;
+
+    /// Trade a refresh token for new tokens, if the backend issues them
+    fn refresh(&self, _refresh_token: &str) -> Result<Issued> {
+        bail!("refresh tokens aren't used by this session store")
+    }
+
+    /// Return the unexpired session for a token
+    fn get(&self, token: &str, life: chrono::Duration) -> Result<Option<Session>>;
+
+    /// Mark the token's session as just used, returning it if it hasn't expired
+    fn touch(&self, token: &str, life: chrono::Duration) -> Result<Option<Session>>;
+
+    /// Delete the token's session, returning whether there was one
+    fn remove(&self, token: &str) -> Result<bool>;
+
+    /// Delete a user's sessions, except the one for `keep`, returning how many were removed
+    fn remove_by_user(&self, user_id: i32, keep: Option<&str>) -> Result<usize>;
+
+    /// Delete every expired session, returning how many were removed
+    fn remove_expired(&self, life: chrono::Duration) -> Result<usize>;
+
+    /// Number of stored sessions, including any expired ones not yet removed
+    fn len(&self) -> Result<usize>;
+}
+
+
+/// Sessions kept in memory, lost when the server stops
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    store: Mutex<HashMap<String, Session>>,
+}
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend { store: Mutex::new(HashMap::new()) }
+    }
+}
+impl SessionBackend for MemoryBackend {
+    fn insert(&self, sess: Session, _life: chrono::Duration) -> Result<Issued> {
+        let issued = Issued { token: sess.token.clone(), refresh_token: None };
+        self.store.lock().unwrap().insert(sess.token.clone(), sess);
+        Ok(issued)
+    }
+
+    fn get(&self, token: &str, life: chrono::Duration) -> Result<Option<Session>> {
+        Ok(self.store.lock().unwrap().get(token).filter(|sess| !sess.expired(life)).cloned())
+    }
+
+    fn touch(&self, token: &str, life: chrono::Duration) -> Result<Option<Session>> {
+        let mut store = self.store.lock().unwrap();
+        let expired = match store.get_mut(token) {
+            Some(sess) => {
+                if !sess.expired(life) {
+                    sess.touch();
+                    return Ok(Some(sess.clone()));
+                }
+                true
+            }
+            None => false,
+        };
+        if expired {
+            store.remove(token);
+        }
+        Ok(None)
+    }
+
+    fn remove(&self, token: &str) -> Result<bool> {
+        Ok(self.store.lock().unwrap().remove(token).is_some())
+    }
+
+    fn remove_by_user(&self, user_id: i32, keep: Option<&str>) -> Result<usize> {
+        let mut store = self.store.lock().unwrap();
+        let before = store.len();
+        store.retain(|token, sess| sess.user_id != Some(user_id) || Some(token.as_str()) == keep);
+        Ok(before - store.len())
+    }
+
+    fn remove_expired(&self, life: chrono::Duration) -> Result<usize> {
+        let mut store = self.store.lock().unwrap();
+        let before = store.len();
+        store.retain(|_, sess| !sess.expired(life));
+        Ok(before - store.len())
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self.store.lock().unwrap().len())
+    }
+}
+
+
+/// Sessions kept in the `sessions` table, surviving restarts. Tokens are
+/// stored hashed (see `auth::hash_token`). Uses its own connection pool
+/// so session lookups never wait on connections held by handlers.
+pub struct PgBackend {
+    pool: Pool<PostgresConnectionManager>,
+}
+impl PgBackend {
+    pub fn new(pool: Pool<PostgresConnectionManager>) -> PgBackend {
+        PgBackend { pool: pool }
+    }
+
+    fn with_conn<T, F>(&self, f: F) -> Result<T>
+        where F: FnOnce(&Connection) -> Result<T>
+    {
+        let conn = self.pool.get().chain_err(|| "Error getting session connection")?;
+        f(&conn)
+    }
+
+    fn session_from_row(token: &str, row: ::postgres::rows::Row) -> Session {
+        let data: String = row.get(1);
+        Session {
+            token: token.to_string(),
+            user_id: row.get(0),
+            data: json::parse(&data).unwrap_or(object!{}),
+            stamp: row.get(2),
+        }
+    }
+}
+impl SessionBackend for PgBackend {
+    fn insert(&self, sess: Session, life: chrono::Duration) -> Result<Issued> {
+        self.with_conn(|conn| {
+            conn.execute("insert into sessions (token_hash, user_id, data, expires_at) values ($1, $2, $3, $4)",
+                         &[&auth::hash_token(&sess.token), &sess.user_id, &sess.data.dump(), &(sess.stamp + life)])
+                .chain_err(|| "Error storing session")?;
+            Ok(Issued { token: sess.token.clone(), refresh_token: None })
+        })
+    }
+
+    fn get(&self, token: &str, _life: chrono::Duration) -> Result<Option<Session>> {
+        self.with_conn(|conn| {
+            let qs = "select user_id, data, date_modified from sessions \
+                      where token_hash=$1 and expires_at > now()";
+            let rows = conn.query(qs, &[&auth::hash_token(token)]).chain_err(|| "Error getting session")?;
+            let sess = rows.iter().next().map(|row| PgBackend::session_from_row(token, row));
+            Ok(sess)
+        })
+    }
+
+    fn touch(&self, token: &str, life: chrono::Duration) -> Result<Option<Session>> {
+        self.with_conn(|conn| {
+            let qs = "update sessions set expires_at=$2 where token_hash=$1 and expires_at > now() \
+                      returning user_id, data, date_modified";
+            let expires_at = chrono::UTC::now() + life;
+            let rows = conn.query(qs, &[&auth::hash_token(token), &expires_at]).chain_err(|| "Error touching session")?;
+            let sess = rows.iter().next().map(|row| PgBackend::session_from_row(token, row));
+            Ok(sess)
+        })
+    }
+
+    fn remove(&self, token: &str) -> Result<bool> {
+        self.with_conn(|conn| {
+            let deleted = conn.execute("delete from sessions where token_hash=$1", &[&auth::hash_token(token)])
+                .chain_err(|| "Error deleting session")?;
+            Ok(deleted > 0)
+        })
+    }
+
+    fn remove_by_user(&self, user_id: i32, keep: Option<&str>) -> Result<usize> {
+        self.with_conn(|conn| {
+            let keep = keep.map(auth::hash_token);
+            let deleted = conn.execute("delete from sessions where user_id=$1 \
+                                        and ($2::bytea is null or token_hash <> $2)", &[&user_id, &keep])
+                .chain_err(|| "Error deleting sessions")?;
+            Ok(deleted as usize)
+        })
+    }
+
+    fn remove_expired(&self, _life: chrono::Duration) -> Result<usize> {
+        self.with_conn(|conn| {
+            let deleted = conn.execute("delete from sessions where expires_at <= now()", &[])
+                .chain_err(|| "Error deleting expired sessions")?;
+            Ok(deleted as usize)
+        })
+    }
+
+    fn len(&self) -> Result<usize> {
+        self.with_conn(|conn| {
+            let rows = conn.query("select count(*) from sessions", &[]).chain_err(|| "Error counting sessions")?;
+            let count: i64 = rows.get(0).get(0);
+            Ok(count as usize)
+        })
+    }
+}
+
+
+/// Signed sessions: the token is a JWT paired with a refresh token kept in
+/// the database (see `tokens`). Tokens aren't touched, so sessions last
+/// `tokens::ACCESS_LIFE_MINUTES` regardless of the store's life. Each check
+/// also makes sure the refresh token hasn't been revoked, so removing a
+/// session ends it straight away.
+pub struct JwtBackend {
+    pool: Pool<PostgresConnectionManager>,
+    signer: Signer,
+}
+impl JwtBackend {
+    pub fn new(pool: Pool<PostgresConnectionManager>, signer: Signer) -> JwtBackend {
+        JwtBackend { pool: pool, signer: signer }
+    }
+
+    fn with_conn<T, F>(&self, f: F) -> Result<T>
+        where F: FnOnce(&Connection) -> Result<T>
+    {
+        let conn = self.pool.get().chain_err(|| "Error getting session connection")?;
+        f(&conn)
+    }
+
+    fn issued(pair: tokens::TokenPair) -> Issued {
+        Issued { token: pair.token, refresh_token: Some(pair.refresh_token) }
+    }
+}
+impl SessionBackend for JwtBackend {
+    fn insert(&self, sess: Session, _life: chrono::Duration) -> Result<Issued> {
+        let user_id = sess.user_id.ok_or("jwt sessions need a user")?;
+        let signer = &self.signer;
+        self.with_conn(|conn| tokens::issue(conn, signer, user_id)).map(JwtBackend::issued)
+    }
+
+    fn refresh(&self, refresh_token: &str) -> Result<Issued> {
+        let signer = &self.signer;
+        self.with_conn(|conn| sql::transaction(conn, |conn| tokens::refresh(conn, signer, refresh_token)))
+            .map(JwtBackend::issued)
+    }
+
+    fn get(&self, token: &str, _life: chrono::Duration) -> Result<Option<Session>> {
+        let claims = match self.signer.verify(token) {
+            Ok(claims) => claims,
+            Err(_) => return Ok(None),
+        };
+        if !self.with_conn(|conn| tokens::is_open(conn, claims.sid))? {
+            return Ok(None);
+        }
+        Ok(Some(Session {
+            token: token.to_string(),
+            user_id: Some(claims.sub),
+            data: object!{},
+            stamp: chrono::UTC.timestamp(claims.iat, 0),
+        }))
+    }
+
+    fn touch(&self, token: &str, life: chrono::Duration) -> Result<Option<Session>> {
+        self.get(token, life)
+    }
+
+    fn remove(&self, token: &str) -> Result<bool> {
+        let sid = match self.signer.decode(token) {
+            Ok(claims) => claims.sid,
+            Err(_) => return Ok(false),
+        };
+        self.with_conn(|conn| tokens::revoke(conn, sid))
+    }
+
+    fn remove_by_user(&self, user_id: i32, keep: Option<&str>) -> Result<usize> {
+        let keep_sid = keep.and_then(|token| self.signer.decode(token).ok()).map(|claims| claims.sid);
+        self.with_conn(|conn| tokens::revoke_by_user(conn, user_id, keep_sid)).map(|n| n as usize)
+    }
+
+    fn remove_expired(&self, _life: chrono::Duration) -> Result<usize> {
+        self.with_conn(|conn| tokens::delete_stale(conn)).map(|n| n as usize)
+    }
+
+    fn len(&self) -> Result<usize> {
+        self.with_conn(|conn| tokens::count_open(conn)).map(|n| n as usize)
+    }
+}
+
+
 /// User session store & manager
 pub struct SessionStore {
-    pub store: HashMap<String, Session>,
+    backend: Box<SessionBackend>,
     pub life_span: chrono::Duration,
 }
 impl SessionStore {
-    /// Start a new session-store with a specified life-span
-    pub fn new(life: i64) -> SessionStore {
+    /// Start a new session-store with a specified life-span (in seconds)
+    pub fn new(backend: Box<SessionBackend>, life: i64) -> SessionStore {
         SessionStore {
-            store: HashMap::new(),
+            backend: backend,
             life_span: chrono::Duration::seconds(life),
         }
     }
 
-    /// Add a session to the session-store, return its token
-    pub fn add(&mut self, sess: Session) -> String {
-        let token = sess.token.clone();
-        self.store.insert(token.clone(), sess);
-        token
+    /// Select the backend named by `SESSION_STORE`, defaulting to `memory`.
+    /// The `jwt` store signs tokens with `JWT_SECRET`, shared by every server.
+    pub fn from_env(life: i64) -> Result<SessionStore> {
+        let backend: Box<SessionBackend> = match env::var("SESSION_STORE").unwrap_or("memory".into()).as_str() {
+            "memory" => Box::new(MemoryBackend::new()),
+            "postgres" => Box::new(PgBackend::new(service::establish_pool_connection())),
+            "jwt" => Box::new(JwtBackend::new(service::establish_pool_connection(), Signer::from_env()?)),
+            other => bail!("unknown session store: {}", other),
+        };
+        Ok(SessionStore::new(backend, life))
     }
 
-    /// Return the number of session entries
-    pub fn len(&self) -> usize {
-        self.store.len()
+    /// Add a session to the session-store, return the tokens for the client
+    pub fn add(&self, sess: Session) -> Result<Issued> {
+        self.backend.insert(sess, self.life_span)
     }
 
-    /// Returns true if the session's life-span is within
-    /// the life_span specified for the session-store
-    pub fn token_expired(&self, token: &String) -> bool {
-        match self.store.get(token) {
-            Some(sess) => sess.expired(self.life_span),
-            None => false,
-        }
+    /// Trade a refresh token for new tokens (`jwt` store only)
+    pub fn refresh(&self, refresh_token: &str) -> Result<Issued> {
+        self.backend.refresh(refresh_token)
     }
 
-    /// Check the session-store for a given token. If it exists,
-    /// check its timestamp against the store's specified life_span
-    /// and delete it if it's expired, otherwise touch it to update
-    /// the sessions timestamp.
-    pub fn check_delete(&mut self, token: &String) -> bool {
-        if self.token_expired(token) {
-            self.store.remove(token);
-            false
-        } else {
-            match self.store.get_mut(token) {
-                Some(mut sess) => {
-                    sess.touch();
-                    true
-                },
-                _ => false,
-            }
-        }
+    /// Return the number of session entries
+    pub fn len(&self) -> usize {
+        self.backend.len().unwrap_or(0)
     }
 
-    /// Returns a reference to the session corresponding to the given token
-    pub fn get(&self, token: &str) -> Option<&Session> {
-        self.store.get(token)
+    /// Check the session-store for a given token. If it exists and hasn't
+    /// outlived the store's life_span, touch it to update the session's
+    /// timestamp. Expired sessions are deleted.
+    pub fn check_delete(&self, token: &str) -> bool {
+        match self.backend.touch(token, self.life_span) {
+            Ok(sess) => sess.is_some(),
+            Err(err) => {
+                println!("Error checking session: {}", err);
+                false
+            }
+        }
     }
 
-    /// Returns a mutable reference to the session corresponding to the token.
-    pub fn get_mut(&mut self, token: &str) -> Option<&mut Session> {
-        self.store.get_mut(token)
+    /// Returns the session corresponding to the given token
+    pub fn get(&self, token: &str) -> Option<Session> {
+        self.backend.get(token, self.life_span).unwrap_or_else(|err| {
+            println!("Error getting session: {}", err);
+            None
+        })
     }
 
-    /// Return a mutable reference to the session linked to the request
-    /// Authorization token
-    pub fn get_mut_from_request(&mut self, request: &Request) -> Option<&mut Session> {
-        match request.headers.get::<Authorization<String>>() {
-            Some(token) => self.get_mut(token),
-            None => None,
-        }
+    /// Return the session linked to the request Authorization token
+    pub fn get_from_request(&self, request: &Request) -> Option<Session> {
+        request.headers.get::<Authorization<String>>()
+            .and_then(|token| self.get(token))
     }
 
-    /// Return the user.uuid associated with this request session
+    /// Return the user.id associated with this request session
     pub fn get_user_id_from_request(&self, request: &Request) -> Option<i32> {
-        request.headers.get::<Authorization<String>>()
-            .and_then(move |token| self.get(token))
-            .map(|session| session.user_id.unwrap())
+        self.get_from_request(request).and_then(|session| session.user_id)
     }
 
     pub fn get_user_from_request(&self, conn: &Connection, request: &Request) -> Option<User> {
@@ -172,50 +435,62 @@ impl SessionStore {
     }
 
     /// Delete from the SessionStore the Session associated with the given
-    /// Request's Auth-token
-    pub fn delete_by_request(&mut self, request: &Request) -> Option<Session> {
+    /// Request's Auth-token, returning whether there was one
+    pub fn delete_by_request(&self, request: &Request) -> bool {
         match request.headers.get::<Authorization<String>>() {
-            Some(&Authorization(ref token)) => self.store.remove(token),
-            _ => None,
+            Some(&Authorization(ref token)) => self.backend.remove(token).unwrap_or_else(|err| {
+                println!("Error deleting session: {}", err);
+                false
+            }),
+            _ => false,
         }
     }
 
+    /// Delete every session belonging to a user, returning how many were removed
+    pub fn delete_by_user(&self, user_id: i32) -> usize {
+        self.backend.remove_by_user(user_id, None).unwrap_or_else(|err| {
+            println!("Error deleting sessions: {}", err);
+            0
+        })
+    }
+
+    /// Delete every session belonging to a user except the one linked to the
+    /// request's Authorization token, returning how many were removed
+    pub fn delete_others_by_request(&self, user_id: i32, request: &Request) -> usize {
+        let keep = request.headers.get::<Authorization<String>>().map(|token| token.0.clone());
+        self.backend.remove_by_user(user_id, keep.as_ref().map(|token| token.as_str())).unwrap_or_else(|err| {
+            println!("Error deleting sessions: {}", err);
+            0
+        })
+    }
+
     /// Touch the given session (by token) to update its timestamp
     /// Returns an Error if the token does not exist in the store.
-    pub fn touch(&mut self, token: &String) -> Result<(), ()> {
-        match self.store.get_mut(token) {
-            Some(mut sess) => {
-                sess.touch();
-                Ok(())
-            }
-            None => Err(()),
+    pub fn touch(&self, token: &str) -> ::std::result::Result<(), ()> {
+        match self.backend.touch(token, self.life_span) {
+            Ok(Some(_)) => Ok(()),
+            _ => Err(()),
         }
     }
+
+    /// Delete every expired session, returning how many were removed
+    pub fn sweep(&self) -> usize {
+        self.backend.remove_expired(self.life_span).unwrap_or_else(|err| {
+            println!("Error sweeping sessions: {}", err);
+            0
+        })
+    }
 }
 
 
 /// Start a daemon thread to clean out stale sessions left in
 /// the session-store every 'interval' seconds.
-pub fn start_daemon_sweeper(session_store: Arc<Mutex<SessionStore>>, interval: u64) {
+pub fn start_daemon_sweeper(session_store: Arc<SessionStore>, interval: u64) {
     // startup session daemon
     thread::spawn(move || {
         loop {
-            {
-                let mut s_store = session_store.lock().unwrap();
-                let stale = s_store.store.iter().fold(vec![], |mut acc, (k, v)| {
-                    let now_ish = chrono::UTC::now();
-                    if now_ish - v.stamp > s_store.life_span {
-                        acc.push(k.clone());
-                    }
-                    acc
-                });
-                let mut count = 0;
-                for k in stale.iter() {
-                    s_store.store.remove(k);
-                    count += 1;
-                }
-                println!(">> Cleaned out {} stale sessions", count);
-            }
+            let count = session_store.sweep();
+            println!(">> Cleaned out {} stale sessions", count);
             thread::sleep(time::Duration::from_secs(interval));
         }
     });
@@ -0,0 +1,179 @@
+//! Signup
+//!
+//! Self-service account creation. New accounts can log in straight away
+//! but can't bid until the emailed verification link is followed.
+//! Verifications also confirm changes of address (see `account`), in
+//! which case `new_email` replaces the user's email once followed.
+//! Only the verification token's hash is stored.
+//!
+use chrono;
+use postgres::{self, Connection};
+
+use models::{self, User};
+use mailer::{self, Mailer};
+use validation::Validator;
+use audit::{self, Action};
+use auth;
+use sql;
+use errors::*;
+
+
+/// Verification links expire after this many hours
+pub const VERIFICATION_LIFE_HOURS: i64 = 48;
+
+const MAX_NAME_LEN: usize = 255;
+
+
+#[derive(Debug)]
+pub struct Signup {
+    pub email: String,
+    pub password: String,
+    pub name: String,
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct EmailVerification {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: Vec<u8>,
+    pub expires_at: chrono::DateTime<chrono::UTC>,
+    pub verified_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+    pub new_email: Option<String>,
+}
+impl EmailVerification {
+    pub fn from_row(row: postgres::rows::Row) -> EmailVerification {
+        EmailVerification {
+            id: row.get(0),
+            user_id: row.get(1),
+            token_hash: row.get(2),
+            expires_at: row.get(3),
+            verified_at: row.get(4),
+            date_created: row.get(5),
+            date_modified: row.get(6),
+            new_email: row.get(7),
+        }
+    }
+
+    /// Find an unexpired, unused verification by its (unhashed) token
+    pub fn get_open_by_token(conn: &Connection, token: &str) -> Option<EmailVerification> {
+        let qs = "select * from email_verifications where token_hash=$1 \
+                  and verified_at is null and expires_at > now() for update";
+        query_or_none!(conn.query(qs, &[&auth::hash_token(token)]), EmailVerification)
+    }
+}
+
+
+/// Create an unverified user with a profile and email them a verification
+/// link. Should be run in `sql::transaction` so nothing is left behind if
+/// the email can't be sent.
+///
+/// An address that's already registered gets an email about its existing
+/// account instead, and `None` is returned. Callers should respond the same
+/// either way so signup can't be used to discover who has an account.
+pub fn signup(conn: &Connection, mailer: &Mailer, signup: Signup) -> Result<Option<User>> {
+    let mut v = Validator::new();
+    let email = signup.email.trim().to_lowercase();
+    if !email.contains('@') {
+        v.error("email", "is not a valid email address");
+    }
+    v.password("password", &signup.password);
+    let name = v.text("name", &signup.name, MAX_NAME_LEN);
+    v.finish()?;
+
+    // hashed either way so a registered address doesn't answer faster
+    let new_auth = models::NewAuth::new(&signup.password)?;
+    if let Some(existing) = sql::select_user_by_email(conn, &email) {
+        send_existing_account(conn, mailer, &existing)?;
+        return Ok(None);
+    }
+    let new_auth = new_auth.create(conn).chain_err(|| "Error creating auth")?;
+    let user = models::NewUser::new(&email, &new_auth).create(conn).chain_err(|| "Error creating user")?;
+    models::NewProfile::new(user.id, None, None, false, &name.unwrap(), None, None)
+        .create(conn).chain_err(|| "Error creating profile")?;
+    send_verification(conn, mailer, &user)?;
+    Ok(Some(user))
+}
+
+
+/// Tell the owner of an already registered address that someone tried to
+/// sign up with it. Unverified accounts get a fresh verification link.
+fn send_existing_account(conn: &Connection, mailer: &Mailer, user: &User) -> Result<()> {
+    if !user.is_verified() {
+        send_verification(conn, mailer, user)?;
+        return Ok(());
+    }
+    let body = format!("Someone tried to sign up with this email address, which already has an \
+                        account. You can log in at:\n\n\
+                        {}/#/login\n\n\
+                        If this wasn't you, you can ignore this email.",
+                       mailer::public_url());
+    mailer.send(&user.email, "You already have an account", &body)
+        .chain_err(|| "Error sending existing account email")
+}
+
+
+/// Store a new verification for the user, confirming `new_email` if given
+/// or their current address otherwise. Returns it with its (unhashed) token.
+pub fn new_verification(conn: &Connection, user_id: i32, new_email: Option<String>)
+                        -> Result<(EmailVerification, String)> {
+    let token = auth::new_token()?;
+    let token_hash = auth::hash_token(&token);
+    let expires_at = chrono::UTC::now() + chrono::Duration::hours(VERIFICATION_LIFE_HOURS);
+    let qs = "insert into email_verifications (user_id, token_hash, expires_at, new_email) \
+              values ($1, $2, $3, $4) returning id, date_created, date_modified";
+    let verification: Result<EmailVerification> = try_insert_to_model!(
+        conn.query(qs, &[&user_id, &token_hash, &expires_at, &new_email]) ;
+        EmailVerification ;
+        id: 0, date_created: 1, date_modified: 2 ;
+        user_id: user_id, token_hash: token_hash, expires_at: expires_at, verified_at: None,
+        new_email: new_email);
+    Ok((verification?, token))
+}
+
+
+/// Email the user a new single-use verification link
+pub fn send_verification(conn: &Connection, mailer: &Mailer, user: &User) -> Result<EmailVerification> {
+    if user.is_verified() {
+        bail!("email is already verified");
+    }
+    let (verification, token) = new_verification(conn, user.id, None)?;
+    let body = format!("Please confirm your email address to start bidding:\n\n\
+                        {}/#/verify?token={}\n\n\
+                        This link expires on {}.",
+                       mailer::public_url(), token, verification.expires_at.format("%Y-%m-%d %H:%M UTC"));
+    mailer.send(&user.email, "Confirm your email address", &body)
+        .chain_err(|| "Error sending verification email")?;
+    Ok(verification)
+}
+
+
+/// Mark the user owning a verification token as verified, switching them
+/// to the verification's `new_email` if it confirms a change of address.
+/// Should be run in `sql::transaction`.
+pub fn verify(conn: &Connection, token: &str) -> Result<User> {
+    let verification = EmailVerification::get_open_by_token(conn, token)
+        .ok_or("verification link is invalid or has expired")?;
+    conn.execute("update email_verifications set verified_at=now() where id=$1", &[&verification.id])
+        .chain_err(|| "Error using verification")?;
+    let user = sql::get_user(conn, verification.user_id).ok_or("user not found")?;
+
+    match verification.new_email {
+        Some(new_email) => {
+            if sql::select_user_by_email(conn, &new_email).is_some() {
+                bail!("email is already registered");
+            }
+            let qs = "update users set email=$1, verified_at=now() where id=$2 returning *";
+            let changed = query_or_none!(conn.query(qs, &[&new_email, &user.id]), User).ok_or("user not found")?;
+            let detail = audit::detail(&[("from", user.email.as_str()), ("to", changed.email.as_str())]);
+            audit::record(conn, user.id, Action::EmailChanged, Some(detail))?;
+            Ok(changed)
+        }
+        None => {
+            let qs = "update users set verified_at=coalesce(verified_at, now()) where id=$1 returning *";
+            query_or_none!(conn.query(qs, &[&user.id]), User).ok_or_else(|| "user not found".into())
+        }
+    }
+}
@@ -2,48 +2,223 @@ use postgres::Connection;
 use uuid::Uuid;
 
 use models::*;
+use tokens;
 use errors::*;
 
 
+/// Run `f` in a transaction on the given connection, committing if it
+/// returns `Ok` and rolling back if it returns an error or panics.
+///
+/// Called while a transaction is already open, `f` runs in a savepoint
+/// instead, so its work can be undone without abandoning the outer
+/// transaction.
+pub fn transaction<T, F>(conn: &Connection, f: F) -> Result<T>
+    where F: FnOnce(&Connection) -> Result<T>
+{
+    if !conn.is_active() {
+        return savepoint(conn, f);
+    }
+    let trans = conn.transaction().chain_err(|| "Error starting transaction")?;
+    // dropping `trans` on the error path rolls it back
+    let val = f(trans.connection())?;
+    trans.commit().chain_err(|| "Error committing transaction")?;
+    Ok(val)
+}
+
+/// Rolls back to its savepoint when dropped unless released first
+struct Savepoint<'a> {
+    conn: &'a Connection,
+    released: bool,
+}
+
+impl<'a> Drop for Savepoint<'a> {
+    fn drop(&mut self) {
+        if !self.released {
+            let _ = self.conn.batch_execute("ROLLBACK TO SAVEPOINT nested; RELEASE SAVEPOINT nested");
+        }
+    }
+}
+
+/// `transaction` nested inside an open transaction. Savepoints of the same
+/// name stack, so each level releases or rolls back only its own.
+fn savepoint<T, F>(conn: &Connection, f: F) -> Result<T>
+    where F: FnOnce(&Connection) -> Result<T>
+{
+    conn.batch_execute("SAVEPOINT nested").chain_err(|| "Error starting savepoint")?;
+    let mut sp = Savepoint { conn: conn, released: false };
+    let val = f(conn)?;
+    conn.batch_execute("RELEASE SAVEPOINT nested").chain_err(|| "Error releasing savepoint")?;
+    sp.released = true;
+    Ok(val)
+}
+
+
 pub fn select_user_by_email(conn: &Connection, email: &str) -> Option<User> {
-    let qs = "select * from users where email=$1";
+    let qs = "select * from users where email=$1 and deleted_at is null";
     query_or_none!(conn.query(qs, &[&email]), User)
 }
 
 pub fn get_user(conn: &Connection, id: i32) -> Option<User> {
-    let qs = "select * from users where id=$1";
+    let qs = "select * from users where id=$1 and deleted_at is null";
     query_or_none!(conn.query(qs, &[&id]), User)
 }
 
 pub fn select_user_by_uuid(conn: &Connection, uuid_: &Uuid) -> Option<User> {
-    let qs = "select * from users where uuid_=$1 limit 1";
+    let qs = "select * from users where uuid_=$1 and deleted_at is null limit 1";
     query_or_none!(conn.query(qs, &[&uuid_]), User)
 }
 
-/// Want to delete the auth record and have the users record cascade delete
+/// Soft delete a user, hiding them from every query here until restored.
+/// Their auth, profile and bid history are kept. Sessions in the `postgres`
+/// session store are deleted and `jwt` logins revoked; in-memory ones stop
+/// authenticating, since handlers can no longer load the user.
 pub fn delete_user_by_id(conn: &Connection, id: i32) -> Result<()> {
-    let qs = "with user_sel as (select auth_id from users where id=$1) \
-              delete from auth using user_sel \
-              where user_sel.auth_id=auth.id";
-    conn.execute(qs, &[&id]).chain_err(|| "Error deleting user/auth")?;
+    let qs = "update users set deleted_at=NOW() where id=$1 and deleted_at is null";
+    let n = conn.execute(qs, &[&id]).chain_err(|| "Error deleting user")?;
+    if n == 0 {
+        bail!("user {} not found", id);
+    }
+    conn.execute("delete from sessions where user_id=$1", &[&id]).chain_err(|| "Error deleting user sessions")?;
+    tokens::revoke_by_user(conn, id, None)?;
     Ok(())
 }
 
+pub fn select_deleted_users(conn: &Connection) -> Vec<User> {
+    let qs = "select * from users where deleted_at is not null order by deleted_at desc";
+    query_coll!(conn.query(qs, &[]), User)
+}
+
+pub fn restore_user(conn: &Connection, id: i32) -> Result<User> {
+    let qs = "update users set deleted_at=null where id=$1 and deleted_at is not null returning *";
+    query_or_none!(conn.query(qs, &[&id]), User)
+        .ok_or_else(|| format!("no deleted user {}", id).into())
+}
+
 
 pub fn select_profile_by_user(conn: &Connection, user_id: &i32) -> Option<Profile> {
     let qs = "select * from profiles where user_id=$1 limit 1";
     query_or_none!(conn.query(qs, &[&user_id]), Profile)
 }
 
+pub fn get_profile(conn: &Connection, id: i32) -> Option<Profile> {
+    let qs = "select * from profiles where id=$1";
+    query_or_none!(conn.query(qs, &[&id]), Profile)
+}
+
 pub fn select_profile_by_name(conn: &Connection, name: &str) -> Option<Profile> {
     let qs = "select * from profiles where name=$1 limit 1";
     query_or_none!(conn.query(qs, &[&name]), Profile)
 }
 
+pub fn select_profiles_by_org(conn: &Connection, org_id: i32) -> Vec<Profile> {
+    let qs = "select p.* from profiles p join bidders b on b.id=p.bidder_id \
+              where b.organization_id=$1 order by p.id";
+    query_coll!(conn.query(qs, &[&org_id]), Profile)
+}
+
+pub fn select_primary_profile(conn: &Connection, bidder_id: i32) -> Option<Profile> {
+    let qs = "select * from profiles where bidder_id=$1 and is_primary limit 1";
+    query_or_none!(conn.query(qs, &[&bidder_id]), Profile)
+}
+
+
+pub fn get_bidder(conn: &Connection, id: i32) -> Option<Bidder> {
+    let qs = "select * from bidders where id=$1";
+    query_or_none!(conn.query(qs, &[&id]), Bidder)
+}
+
+pub fn select_bidder_by_paddle(conn: &Connection, org_id: i32, paddle: i32) -> Option<Bidder> {
+    let qs = "select * from bidders where organization_id=$1 and paddle=$2";
+    query_or_none!(conn.query(qs, &[&org_id, &paddle]), Bidder)
+}
+
+pub fn select_bidders_by_org(conn: &Connection, org_id: i32) -> Vec<Bidder> {
+    let qs = "select * from bidders where organization_id=$1 order by id";
+    query_coll!(conn.query(qs, &[&org_id]), Bidder)
+}
+
+
+pub fn get_payment_info(conn: &Connection, id: i32) -> Option<PaymentInfo> {
+    let qs = "select * from payment_information where id=$1";
+    query_or_none!(conn.query(qs, &[&id]), PaymentInfo)
+}
+
+/// Users whose profiles have a payment info attached
+pub fn select_payment_info_user_ids(conn: &Connection, id: i32) -> Vec<i32> {
+    let qs = "select distinct user_id from profiles where payment_info_id=$1";
+    let rows = conn.query(qs, &[&id]).unwrap();
+    rows.iter().map(|row| row.get(0)).collect()
+}
+
+/// Select a payment info only if it is attached to one of the bidder's profiles
+pub fn select_payment_info_for_bidder(conn: &Connection, bidder_id: i32, id: i32) -> Option<PaymentInfo> {
+    let qs = "select pi.* from payment_information pi \
+              where pi.id=$2 and exists \
+              (select 1 from profiles p where p.bidder_id=$1 and p.payment_info_id=pi.id)";
+    query_or_none!(conn.query(qs, &[&bidder_id, &id]), PaymentInfo)
+}
+
 
 pub fn filter_items_for_user(conn: &Connection, user_id: i32) -> Vec<Item> {
-    let qs = "select * from items where organization_id in \
+    let qs = "select * from items where deleted_at is null and organization_id in \
               (select organization_id from bidders where id in \
-               (select bidder_id from profiles where user_id=$1))";
+               (select bidder_id from profiles where user_id=$1)) \
+              and organization_id not in (select id from organizations where deleted_at is not null)";
     query_coll!(conn.query(qs, &[&user_id]), Item)
 }
+
+
+pub fn get_organization(conn: &Connection, id: i32) -> Option<Organization> {
+    let qs = "select * from organizations where id=$1 and deleted_at is null";
+    query_or_none!(conn.query(qs, &[&id]), Organization)
+}
+
+pub fn select_organizations(conn: &Connection) -> Vec<Organization> {
+    let qs = "select * from organizations where deleted_at is null order by id";
+    query_coll!(conn.query(qs, &[]), Organization)
+}
+
+pub fn select_deleted_organizations(conn: &Connection) -> Vec<Organization> {
+    let qs = "select * from organizations where deleted_at is not null order by deleted_at desc";
+    query_coll!(conn.query(qs, &[]), Organization)
+}
+
+pub fn restore_organization(conn: &Connection, id: i32) -> Result<Organization> {
+    let qs = "update organizations set deleted_at=null where id=$1 and deleted_at is not null returning *";
+    query_or_none!(conn.query(qs, &[&id]), Organization)
+        .ok_or_else(|| format!("no deleted organization {}", id).into())
+}
+
+pub fn get_item(conn: &Connection, id: i32) -> Option<Item> {
+    let qs = "select * from items where id=$1 and deleted_at is null";
+    query_or_none!(conn.query(qs, &[&id]), Item)
+}
+
+/// Select an item, locking its row until the end of the current transaction
+pub fn lock_item(conn: &Connection, id: i32) -> Option<Item> {
+    let qs = "select * from items where id=$1 and deleted_at is null for update";
+    query_or_none!(conn.query(qs, &[&id]), Item)
+}
+
+pub fn select_items_by_org(conn: &Connection, org_id: i32) -> Vec<Item> {
+    let qs = "select * from items where organization_id=$1 and deleted_at is null order by id";
+    query_coll!(conn.query(qs, &[&org_id]), Item)
+}
+
+pub fn select_deleted_items(conn: &Connection) -> Vec<Item> {
+    let qs = "select * from items where deleted_at is not null order by deleted_at desc";
+    query_coll!(conn.query(qs, &[]), Item)
+}
+
+pub fn restore_item(conn: &Connection, id: i32) -> Result<Item> {
+    let qs = "update items set deleted_at=null where id=$1 and deleted_at is not null returning *";
+    query_or_none!(conn.query(qs, &[&id]), Item)
+        .ok_or_else(|| format!("no deleted item {}", id).into())
+}
+
+/// Select the highest (latest on ties) bid placed on an item
+pub fn select_high_bid(conn: &Connection, item_id: i32) -> Option<Bid> {
+    let qs = "select * from bids where item_id=$1 \
+              order by amount desc, date_created desc limit 1";
+    query_or_none!(conn.query(qs, &[&item_id]), Bid)
+}
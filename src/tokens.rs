@@ -0,0 +1,215 @@
+//! Tokens
+//!
+//! Signed access tokens and server-side refresh tokens for the `jwt`
+//! session store. Access tokens are HS256 JWTs carrying the user's id,
+//! roles and expiry, so any server sharing `JWT_SECRET` accepts them.
+//! They're short-lived; clients trade their refresh token for a new pair
+//! when they expire. Refresh tokens are stored hashed, replaced on every
+//! use and revoked on logout and credential changes, which also ends the
+//! access tokens issued with them (see `is_open`).
+//!
+use std::env;
+
+use chrono;
+use postgres::{self, Connection};
+use jwt::{self, Algorithm, Header};
+
+use roles;
+use auth;
+use sql;
+use errors::*;
+
+
+/// Access tokens expire after this many minutes
+pub const ACCESS_LIFE_MINUTES: i64 = 15;
+
+/// Refresh tokens expire after this many days without being used
+pub const REFRESH_LIFE_DAYS: i64 = 30;
+
+const MIN_SECRET_BYTES: usize = 32;
+
+
+/// Access token claims
+#[derive(Debug, RustcEncodable, RustcDecodable)]
+pub struct Claims {
+    /// User id
+    pub sub: i32,
+    /// Refresh token id, identifying the login the token belongs to
+    pub sid: i32,
+    pub roles: Vec<String>,
+    /// Issued and expiry times, in seconds since the epoch
+    pub iat: i64,
+    pub exp: i64,
+}
+
+
+/// Signs and checks access tokens with the shared `JWT_SECRET`
+pub struct Signer {
+    secret: Vec<u8>,
+}
+impl Signer {
+    pub fn new(secret: &[u8]) -> Result<Signer> {
+        if secret.len() < MIN_SECRET_BYTES {
+            bail!("jwt secret must be at least {} bytes", MIN_SECRET_BYTES);
+        }
+        Ok(Signer { secret: secret.to_vec() })
+    }
+
+    pub fn from_env() -> Result<Signer> {
+        let secret = env::var("JWT_SECRET").chain_err(|| "JWT_SECRET must be set")?;
+        Signer::new(secret.as_bytes())
+    }
+
+    pub fn sign(&self, claims: &Claims) -> Result<String> {
+        jwt::encode(Header::new(Algorithm::HS256), claims, &self.secret)
+            .map_err(|_| "Error signing token".into())
+    }
+
+    /// Claims of a token with a valid signature, expired or not
+    pub fn decode(&self, token: &str) -> Result<Claims> {
+        jwt::decode::<Claims>(token, &self.secret, Algorithm::HS256)
+            .map(|data| data.claims)
+            .map_err(|_| "invalid token".into())
+    }
+
+    /// Claims of a token with a valid signature that hasn't expired
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let claims = self.decode(token)?;
+        if claims.exp <= chrono::UTC::now().timestamp() {
+            bail!("token has expired");
+        }
+        Ok(claims)
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: Vec<u8>,
+    pub expires_at: chrono::DateTime<chrono::UTC>,
+    pub revoked_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl RefreshToken {
+    pub fn from_row(row: postgres::rows::Row) -> RefreshToken {
+        RefreshToken {
+            id: row.get(0),
+            user_id: row.get(1),
+            token_hash: row.get(2),
+            expires_at: row.get(3),
+            revoked_at: row.get(4),
+            date_created: row.get(5),
+            date_modified: row.get(6),
+        }
+    }
+
+    /// Find an unexpired, unrevoked refresh token by its (unhashed) token
+    pub fn get_open_by_token(conn: &Connection, token: &str) -> Option<RefreshToken> {
+        let qs = "select * from refresh_tokens where token_hash=$1 \
+                  and revoked_at is null and expires_at > now() for update";
+        query_or_none!(conn.query(qs, &[&auth::hash_token(token)]), RefreshToken)
+    }
+}
+
+
+/// An access token and the refresh token to renew it with
+#[derive(Debug, RustcEncodable)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+}
+
+
+/// Sign a new access token for a user's login
+fn access_token(conn: &Connection, signer: &Signer, user_id: i32, sid: i32) -> Result<String> {
+    let now = chrono::UTC::now();
+    let claims = Claims {
+        sub: user_id,
+        sid: sid,
+        roles: roles::user_roles(conn, user_id).into_iter().map(|r| r.role).collect(),
+        iat: now.timestamp(),
+        exp: (now + chrono::Duration::minutes(ACCESS_LIFE_MINUTES)).timestamp(),
+    };
+    signer.sign(&claims)
+}
+
+
+/// Start a login for a user, returning its first pair of tokens
+pub fn issue(conn: &Connection, signer: &Signer, user_id: i32) -> Result<TokenPair> {
+    let refresh_token = auth::new_token()?;
+    let expires_at = chrono::UTC::now() + chrono::Duration::days(REFRESH_LIFE_DAYS);
+    let qs = "insert into refresh_tokens (user_id, token_hash, expires_at) values ($1, $2, $3) returning id";
+    let rows = conn.query(qs, &[&user_id, &auth::hash_token(&refresh_token), &expires_at])
+        .chain_err(|| "Error storing refresh token")?;
+    let sid: i32 = rows.iter().next().map(|row| row.get(0)).ok_or("Error storing refresh token")?;
+    Ok(TokenPair {
+        token: access_token(conn, signer, user_id, sid)?,
+        refresh_token: refresh_token,
+        expires_in: ACCESS_LIFE_MINUTES * 60,
+    })
+}
+
+
+/// Trade a refresh token for a new pair, replacing it so it can't be used
+/// again. Should be run in `sql::transaction`.
+pub fn refresh(conn: &Connection, signer: &Signer, refresh_token: &str) -> Result<TokenPair> {
+    let current = RefreshToken::get_open_by_token(conn, refresh_token)
+        .ok_or("refresh token is invalid or has expired")?;
+    sql::get_user(conn, current.user_id).ok_or("user not found")?;
+    let next = auth::new_token()?;
+    let expires_at = chrono::UTC::now() + chrono::Duration::days(REFRESH_LIFE_DAYS);
+    conn.execute("update refresh_tokens set token_hash=$1, expires_at=$2 where id=$3",
+                 &[&auth::hash_token(&next), &expires_at, &current.id])
+        .chain_err(|| "Error replacing refresh token")?;
+    Ok(TokenPair {
+        token: access_token(conn, signer, current.user_id, current.id)?,
+        refresh_token: next,
+        expires_in: ACCESS_LIFE_MINUTES * 60,
+    })
+}
+
+
+/// Whether a login's refresh token is still unrevoked. Checked along with
+/// access tokens so logging out or resetting a password ends them at once.
+pub fn is_open(conn: &Connection, sid: i32) -> Result<bool> {
+    let rows = conn.query("select 1 from refresh_tokens where id=$1 and revoked_at is null", &[&sid])
+        .chain_err(|| "Error checking refresh token")?;
+    Ok(!rows.is_empty())
+}
+
+
+/// Revoke a login's refresh token, returning whether it was open
+pub fn revoke(conn: &Connection, sid: i32) -> Result<bool> {
+    let revoked = conn.execute("update refresh_tokens set revoked_at=now() where id=$1 and revoked_at is null", &[&sid])
+        .chain_err(|| "Error revoking refresh token")?;
+    Ok(revoked > 0)
+}
+
+
+/// Revoke a user's refresh tokens, except `keep_sid`'s, returning how many were revoked
+pub fn revoke_by_user(conn: &Connection, user_id: i32, keep_sid: Option<i32>) -> Result<u64> {
+    let qs = "update refresh_tokens set revoked_at=now() \
+              where user_id=$1 and revoked_at is null and ($2::integer is null or id <> $2)";
+    conn.execute(qs, &[&user_id, &keep_sid]).chain_err(|| "Error revoking refresh tokens")
+}
+
+
+/// Delete expired and revoked refresh tokens, returning how many were deleted
+pub fn delete_stale(conn: &Connection) -> Result<u64> {
+    conn.execute("delete from refresh_tokens where expires_at <= now() or revoked_at is not null", &[])
+        .chain_err(|| "Error deleting stale refresh tokens")
+}
+
+
+/// Number of open logins
+pub fn count_open(conn: &Connection) -> Result<i64> {
+    let rows = conn.query("select count(*) from refresh_tokens where revoked_at is null and expires_at > now()", &[])
+        .chain_err(|| "Error counting refresh tokens")?;
+    let count = rows.get(0).get(0);
+    Ok(count)
+}
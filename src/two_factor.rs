@@ -0,0 +1,393 @@
+//! Two Factor
+//!
+//! Optional TOTP (RFC 6238) second factor, required for admins. Users
+//! enroll by adding the provisioning uri (usually shown as a QR code) to
+//! an authenticator app and confirming a first code, which also issues
+//! single-use recovery codes. Only the recovery codes' hashes are stored;
+//! TOTP secrets have to be kept to compute codes.
+//!
+//! Enrolled users' logins stop after the password with a short-lived
+//! pending token, exchanged for a session along with a code (see
+//! `start_login` and `finish_login`).
+//!
+use chrono;
+use postgres::{self, Connection};
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha1::Sha1;
+use rand::{Rng, OsRng};
+
+use models::User;
+use roles::{self, Permission};
+use audit::{self, Action};
+use auth;
+use sql;
+use errors::*;
+
+
+/// Seconds each code is valid for
+pub const STEP_SECS: i64 = 30;
+/// Digits in each code
+pub const DIGITS: usize = 6;
+/// Codes from this many steps either side of now are accepted, for clock drift
+const SKEW_STEPS: i64 = 1;
+const SECRET_SIZE: usize = 20;
+const ISSUER: &'static str = "bidrs";
+
+/// Recovery codes issued at a time
+pub const RECOVERY_CODES: usize = 10;
+
+/// Pending logins expire after this many minutes
+pub const PENDING_LIFE_MINUTES: i64 = 5;
+/// Codes a pending login can try before it's used up
+pub const PENDING_MAX_ATTEMPTS: i32 = 5;
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct TotpSecret {
+    pub id: i32,
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+    pub confirmed_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub last_used_step: Option<i64>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl TotpSecret {
+    pub fn from_row(row: postgres::rows::Row) -> TotpSecret {
+        TotpSecret {
+            id: row.get(0),
+            user_id: row.get(1),
+            secret: row.get(2),
+            confirmed_at: row.get(3),
+            last_used_step: row.get(4),
+            date_created: row.get(5),
+            date_modified: row.get(6),
+        }
+    }
+
+    pub fn get_by_user(conn: &Connection, user_id: i32) -> Option<TotpSecret> {
+        let qs = "select * from totp_secrets where user_id=$1 for update";
+        query_or_none!(conn.query(qs, &[&user_id]), TotpSecret)
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+
+#[derive(Debug, RustcEncodable)]
+pub struct PendingLogin {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: Vec<u8>,
+    pub expires_at: chrono::DateTime<chrono::UTC>,
+    pub attempts: i32,
+    pub used_at: Option<chrono::DateTime<chrono::UTC>>,
+    pub date_created: chrono::DateTime<chrono::UTC>,
+    pub date_modified: chrono::DateTime<chrono::UTC>,
+}
+impl PendingLogin {
+    pub fn from_row(row: postgres::rows::Row) -> PendingLogin {
+        PendingLogin {
+            id: row.get(0),
+            user_id: row.get(1),
+            token_hash: row.get(2),
+            expires_at: row.get(3),
+            attempts: row.get(4),
+            used_at: row.get(5),
+            date_created: row.get(6),
+            date_modified: row.get(7),
+        }
+    }
+}
+
+
+/// A started enrollment: the base32 secret for manual entry and the
+/// `otpauth://` uri to show as a QR code
+#[derive(Debug, RustcEncodable)]
+pub struct Enrollment {
+    pub secret: String,
+    pub uri: String,
+}
+
+
+/// RFC 4648 base32 without padding, as authenticator apps expect secrets
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(ALPHABET[((buffer >> (bits - 5)) & 31) as usize] as char);
+            bits -= 5;
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+
+/// Percent-encode everything but unreserved characters
+fn uri_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+
+/// The code for a time step, see RFC 4226 (HOTP) and RFC 6238
+pub fn totp(secret: &[u8], step: i64) -> String {
+    let mut counter = [0u8; 8];
+    for i in 0..8 {
+        counter[i] = (step >> (56 - 8 * i)) as u8;
+    }
+    let mut mac = Hmac::new(Sha1::new(), secret);
+    mac.input(&counter);
+    let result = mac.result();
+    let hash = result.code();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+    format!("{:0width$}", binary % 10u32.pow(DIGITS as u32), width = DIGITS)
+}
+
+
+fn current_step() -> i64 {
+    chrono::UTC::now().timestamp() / STEP_SECS
+}
+
+
+/// `otpauth://` uri for authenticator apps, usually shown as a QR code
+pub fn provisioning_uri(email: &str, secret: &[u8]) -> String {
+    format!("otpauth://totp/{issuer}:{label}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+            issuer = uri_encode(ISSUER), label = uri_encode(email), secret = base32(secret),
+            digits = DIGITS, period = STEP_SECS)
+}
+
+
+/// Strip the spaces and dashes people type into codes
+fn normalize_code(code: &str) -> String {
+    code.chars().filter(|c| !c.is_whitespace() && *c != '-').collect::<String>().to_lowercase()
+}
+
+
+/// Whether the user has confirmed a second factor
+pub fn is_enabled(conn: &Connection, user_id: i32) -> bool {
+    let qs = "select 1 from totp_secrets where user_id=$1 and confirmed_at is not null";
+    conn.query(qs, &[&user_id]).map(|rows| !rows.is_empty()).unwrap_or(false)
+}
+
+
+/// Whether the user's roles require a second factor (admins do)
+pub fn is_required(conn: &Connection, user: &User) -> bool {
+    roles::has_permission_anywhere(conn, user, Permission::ManageOrganization)
+}
+
+
+/// Start (or restart) enrolling the user with a new secret. It isn't used
+/// for logins until `confirm`ed. Should be run in `sql::transaction`.
+pub fn enroll(conn: &Connection, user: &User) -> Result<Enrollment> {
+    if is_enabled(conn, user.id) {
+        bail!("two-factor authentication is already enabled");
+    }
+    let mut secret = vec![0u8; SECRET_SIZE];
+    match OsRng::new() {
+        Ok(mut rng) => rng.fill_bytes(&mut secret),
+        Err(_) => bail!("rng error"),
+    };
+    conn.execute("delete from totp_secrets where user_id=$1", &[&user.id])
+        .chain_err(|| "Error removing earlier enrollment")?;
+    conn.execute("insert into totp_secrets (user_id, secret) values ($1, $2)", &[&user.id, &secret])
+        .chain_err(|| "Error storing secret")?;
+    Ok(Enrollment { secret: base32(&secret), uri: provisioning_uri(&user.email, &secret) })
+}
+
+
+/// Check a TOTP code, refusing codes from steps already used
+fn check_totp(conn: &Connection, totp_secret: &TotpSecret, code: &str) -> Result<bool> {
+    let now = current_step();
+    for step in (now - SKEW_STEPS)..(now + SKEW_STEPS + 1) {
+        if totp_secret.last_used_step.map(|last| step <= last).unwrap_or(false) {
+            continue;
+        }
+        if auth::const_eq(totp(&totp_secret.secret, step).as_bytes(), code.as_bytes()) {
+            // conditional, so two requests racing with the same code can't both use it
+            let qs = "update totp_secrets set last_used_step=$1 \
+                      where id=$2 and (last_used_step is null or last_used_step < $1)";
+            let used = conn.execute(qs, &[&step, &totp_secret.id]).chain_err(|| "Error using code")?;
+            return Ok(used > 0);
+        }
+    }
+    Ok(false)
+}
+
+
+/// Finish enrolling with a first code from the user's app. Returns
+/// a fresh set of recovery codes. Should be run in `sql::transaction`.
+pub fn confirm(conn: &Connection, user: &User, code: &str) -> Result<Vec<String>> {
+    let totp_secret = TotpSecret::get_by_user(conn, user.id).ok_or("two-factor enrollment not started")?;
+    if totp_secret.is_confirmed() {
+        bail!("two-factor authentication is already enabled");
+    }
+    if !check_totp(conn, &totp_secret, &normalize_code(code))? {
+        bail!("invalid two-factor code");
+    }
+    conn.execute("update totp_secrets set confirmed_at=now() where id=$1", &[&totp_secret.id])
+        .chain_err(|| "Error confirming two-factor")?;
+    audit::record(conn, user.id, Action::TwoFactorEnabled, None)?;
+    new_recovery_codes(conn, user.id)
+}
+
+
+/// Replace the user's recovery codes, returning the new ones
+fn new_recovery_codes(conn: &Connection, user_id: i32) -> Result<Vec<String>> {
+    conn.execute("delete from recovery_codes where user_id=$1", &[&user_id])
+        .chain_err(|| "Error removing recovery codes")?;
+    let mut codes = vec![];
+    for _ in 0..RECOVERY_CODES {
+        let token = auth::new_token()?;
+        let code = format!("{}-{}-{}-{}", &token[0..4], &token[4..8], &token[8..12], &token[12..16]);
+        conn.execute("insert into recovery_codes (user_id, code_hash) values ($1, $2)",
+                     &[&user_id, &auth::hash_token(&normalize_code(&code))])
+            .chain_err(|| "Error storing recovery code")?;
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+
+/// Use up a recovery code
+fn use_recovery_code(conn: &Connection, user_id: i32, code: &str) -> Result<bool> {
+    let qs = "update recovery_codes set used_at=now() \
+              where user_id=$1 and code_hash=$2 and used_at is null";
+    let used = conn.execute(qs, &[&user_id, &auth::hash_token(code)]).chain_err(|| "Error using recovery code")?;
+    if used > 0 {
+        let remaining = format!("{}", unused_recovery_codes(conn, user_id));
+        audit::record(conn, user_id, Action::RecoveryCodeUsed, Some(audit::detail(&[("remaining", remaining.as_str())])))?;
+    }
+    Ok(used > 0)
+}
+
+
+/// How many of the user's recovery codes haven't been used
+pub fn unused_recovery_codes(conn: &Connection, user_id: i32) -> i64 {
+    let qs = "select count(*) from recovery_codes where user_id=$1 and used_at is null";
+    conn.query(qs, &[&user_id]).ok()
+        .and_then(|rows| rows.iter().next().map(|row| row.get(0)))
+        .unwrap_or(0)
+}
+
+
+/// Check a second factor: a current TOTP code or an unused recovery code
+pub fn verify(conn: &Connection, user: &User, code: &str) -> Result<bool> {
+    let totp_secret = TotpSecret::get_by_user(conn, user.id)
+        .filter(|secret| secret.is_confirmed())
+        .ok_or("two-factor authentication is not enabled")?;
+    let code = normalize_code(code);
+    if code.len() == DIGITS && code.chars().all(|c| c.is_digit(10)) {
+        check_totp(conn, &totp_secret, &code)
+    } else {
+        use_recovery_code(conn, user.id, &code)
+    }
+}
+
+
+/// Replace the user's recovery codes, after checking a second factor.
+/// Should be run in `sql::transaction`.
+pub fn regenerate_recovery_codes(conn: &Connection, user: &User, code: &str) -> Result<Vec<String>> {
+    if !verify(conn, user, code)? {
+        bail!("invalid two-factor code");
+    }
+    let codes = new_recovery_codes(conn, user.id)?;
+    audit::record(conn, user.id, Action::RecoveryCodesReplaced, None)?;
+    Ok(codes)
+}
+
+
+/// Remove the user's second factor
+fn remove(conn: &Connection, user_id: i32) -> Result<()> {
+    conn.execute("delete from totp_secrets where user_id=$1", &[&user_id])
+        .chain_err(|| "Error removing secret")?;
+    conn.execute("delete from recovery_codes where user_id=$1", &[&user_id])
+        .chain_err(|| "Error removing recovery codes")?;
+    Ok(())
+}
+
+
+/// Turn off the user's second factor, after checking it. Admins can't.
+/// Should be run in `sql::transaction`.
+pub fn disable(conn: &Connection, user: &User, code: &str) -> Result<()> {
+    if is_required(conn, user) {
+        bail!("two-factor authentication is required for admins");
+    }
+    if !verify(conn, user, code)? {
+        bail!("invalid two-factor code");
+    }
+    remove(conn, user.id)?;
+    audit::record(conn, user.id, Action::TwoFactorDisabled, None)?;
+    Ok(())
+}
+
+
+/// Remove a user's second factor for them, e.g. when they've lost their
+/// device and recovery codes. Should be run in `sql::transaction`.
+pub fn reset(conn: &Connection, user: &User) -> Result<()> {
+    remove(conn, user.id)?;
+    audit::record(conn, user.id, Action::TwoFactorDisabled, Some(audit::detail(&[("by", "admin")])))?;
+    Ok(())
+}
+
+
+/// Hold a login whose password checked out until a second factor is
+/// given. Returns it with its (unhashed) token.
+pub fn start_login(conn: &Connection, user: &User) -> Result<(PendingLogin, String)> {
+    let token = auth::new_token()?;
+    let token_hash = auth::hash_token(&token);
+    let expires_at = chrono::UTC::now() + chrono::Duration::minutes(PENDING_LIFE_MINUTES);
+    let qs = "insert into pending_logins (user_id, token_hash, expires_at) \
+              values ($1, $2, $3) returning id, date_created, date_modified";
+    let pending: Result<PendingLogin> = try_insert_to_model!(
+        conn.query(qs, &[&user.id, &token_hash, &expires_at]) ;
+        PendingLogin ;
+        id: 0, date_created: 1, date_modified: 2 ;
+        user_id: user.id, token_hash: token_hash, expires_at: expires_at, attempts: 0, used_at: None);
+    Ok((pending?, token))
+}
+
+
+/// The user of an open pending login, without using up an attempt
+pub fn pending_user(conn: &Connection, token: &str) -> Option<User> {
+    let qs = "select user_id from pending_logins \
+              where token_hash=$1 and used_at is null and expires_at > now() and attempts < $2";
+    conn.query(qs, &[&auth::hash_token(token), &PENDING_MAX_ATTEMPTS]).ok()
+        .and_then(|rows| rows.iter().next().map(|row| row.get::<_, i32>(0)))
+        .and_then(|user_id| sql::get_user(conn, user_id))
+}
+
+
+/// Finish a pending login with a second factor, returning its user.
+/// Each try counts against the login's attempts even when it fails, so
+/// this shouldn't be run in a transaction.
+pub fn finish_login(conn: &Connection, token: &str, code: &str) -> Result<User> {
+    let qs = "update pending_logins set attempts=attempts+1 \
+              where token_hash=$1 and used_at is null and expires_at > now() and attempts < $2 \
+              returning *";
+    let pending = query_or_none!(conn.query(qs, &[&auth::hash_token(token), &PENDING_MAX_ATTEMPTS]), PendingLogin)
+        .ok_or("login has expired, please log in again")?;
+    let user = sql::get_user(conn, pending.user_id).ok_or("user not found")?;
+    if !verify(conn, &user, code)? {
+        bail!("invalid two-factor code");
+    }
+    conn.execute("update pending_logins set used_at=now() where id=$1", &[&pending.id])
+        .chain_err(|| "Error using pending login")?;
+    Ok(user)
+}
@@ -0,0 +1,114 @@
+//! Validation
+//!
+//! Collects field-level errors while checking api input so every problem
+//! is reported at once, rather than failing on the first bad field.
+//!
+use chrono;
+
+use money::{Money, Currency};
+use bidding;
+use password_policy;
+use errors::*;
+
+
+#[derive(Debug, Clone, RustcEncodable)]
+pub struct FieldError {
+    pub field: String,
+    pub msg: String,
+}
+
+
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+impl Validator {
+    pub fn new() -> Validator {
+        Validator { errors: vec![] }
+    }
+
+    pub fn error(&mut self, field: &str, msg: &str) {
+        self.errors.push(FieldError { field: field.into(), msg: msg.into() });
+    }
+
+    /// Record an error for a required field that's missing
+    pub fn required<T>(&mut self, field: &str, value: Option<T>) -> Option<T> {
+        if value.is_none() {
+            self.error(field, "is required");
+        }
+        value
+    }
+
+    /// Trimmed, non-empty text of at most `max_len` characters
+    pub fn text(&mut self, field: &str, value: &str, max_len: usize) -> Option<String> {
+        let value = value.trim();
+        if value.is_empty() {
+            self.error(field, "must not be blank");
+            None
+        } else if value.chars().count() > max_len {
+            self.error(field, &format!("must be at most {} characters", max_len));
+            None
+        } else {
+            Some(value.to_string())
+        }
+    }
+
+    /// A new password allowed by the `password_policy`
+    pub fn password(&mut self, field: &str, value: &str) -> bool {
+        if let Some(msg) = password_policy::check(value) {
+            self.error(field, &msg);
+            return false;
+        }
+        true
+    }
+
+    /// A non-negative amount of money, e.g. "$1,250.00"
+    pub fn money(&mut self, field: &str, value: &str, currency: Currency) -> Option<i64> {
+        match Money::parse(value, currency) {
+            Ok(ref money) if money.is_negative() => {
+                self.error(field, "must not be negative");
+                None
+            }
+            Ok(money) => Some(money.amount),
+            Err(err) => {
+                self.error(field, &err.to_string());
+                None
+            }
+        }
+    }
+
+    /// An amount of money that can be cleared with a blank string.
+    /// Returns `None` when invalid, `Some(None)` when cleared.
+    pub fn optional_money(&mut self, field: &str, value: &str, currency: Currency) -> Option<Option<i64>> {
+        if value.trim().is_empty() {
+            return Some(None);
+        }
+        self.money(field, value, currency).map(Some)
+    }
+
+    /// A time that can be cleared with a blank string, see `bidding::parse_written_at`
+    pub fn optional_time(&mut self, field: &str, value: &str) -> Option<Option<chrono::DateTime<chrono::UTC>>> {
+        if value.trim().is_empty() {
+            return Some(None);
+        }
+        match bidding::parse_written_at(value) {
+            Ok(time) => Some(Some(time)),
+            Err(err) => {
+                self.error(field, &err.to_string());
+                None
+            }
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Fail with `InvalidFields` if any errors were recorded
+    pub fn finish(self) -> Result<()> {
+        if !self.errors.is_empty() {
+            bail!(ErrorKind::InvalidFields(self.errors));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Watchlist
+//!
+//! Items starred by a profile, and the "outbid" and "closing soon"
+//! emails sent to the profiles watching them.
+//!
+use chrono;
+use postgres::Connection;
+
+use models::{Bid, Item, Profile};
+use money::Money;
+use mailer::{self, Mailer};
+use sql;
+use errors::*;
+
+
+#[derive(Debug)]
+/// A watched item with its current high bid. `is_winning` is set when the
+/// high bid belongs to the watching profile's bidder.
+pub struct WatchedItem {
+    pub item: Item,
+    pub high_bid: Option<Bid>,
+    pub is_winning: bool,
+}
+
+
+/// Start watching an item. The item must belong to the organization
+/// the profile is bidding with. Watching an item twice is a no-op.
+pub fn add(conn: &Connection, profile: &Profile, item_id: i32) -> Result<()> {
+    let bidder = profile.bidder_id.and_then(|id| sql::get_bidder(conn, id))
+        .ok_or("register as a bidder before watching items")?;
+    let item = sql::get_item(conn, item_id).ok_or("item not found")?;
+    if item.organization_id != bidder.organization_id {
+        bail!("item not found");
+    }
+    let qs = "insert into watchlist (profile_id, item_id) values ($1, $2) \
+              on conflict (profile_id, item_id) do nothing";
+    conn.execute(qs, &[&profile.id, &item.id]).chain_err(|| "Error adding item to watchlist")?;
+    Ok(())
+}
+
+
+/// Stop watching an item
+pub fn remove(conn: &Connection, profile: &Profile, item_id: i32) -> Result<()> {
+    conn.execute("delete from watchlist where profile_id=$1 and item_id=$2", &[&profile.id, &item_id])
+        .chain_err(|| "Error removing item from watchlist")?;
+    Ok(())
+}
+
+
+/// The profile's watched items, soonest closing first
+pub fn watched(conn: &Connection, profile: &Profile) -> Vec<WatchedItem> {
+    let qs = "select i.* from watchlist w join items i on i.id = w.item_id \
+              where w.profile_id=$1 and i.deleted_at is null order by i.closes_at nulls last, i.id";
+    let items = query_coll!(conn.query(qs, &[&profile.id]), Item);
+    items.into_iter().map(|item| {
+        let high_bid = sql::select_high_bid(conn, item.id);
+        let is_winning = !item.is_goal && profile.bidder_id.is_some() &&
+            high_bid.as_ref().map(|bid| Some(bid.bidder_id) == profile.bidder_id).unwrap_or(false);
+        WatchedItem { item: item, high_bid: high_bid, is_winning: is_winning }
+    }).collect()
+}
+
+
+/// Email the profiles watching `bid`'s item whose bidder held the high bid
+/// before it was placed. Returns the number of emails sent.
+pub fn notify_outbid(conn: &Connection, mailer: &Mailer, bid: &Bid) -> Result<usize> {
+    let item = sql::get_item(conn, bid.item_id).ok_or("item not found")?;
+    if item.is_goal {
+        return Ok(0);
+    }
+    let qs = "select * from bids where item_id=$1 and id<>$2 \
+              order by amount desc, date_created desc limit 1";
+    let previous = match query_or_none!(conn.query(qs, &[&item.id, &bid.id]), Bid) {
+        Some(ref prev) if prev.bidder_id != bid.bidder_id && prev.amount < bid.amount => prev.bidder_id,
+        _ => return Ok(0),
+    };
+    let currency = sql::get_organization(conn, item.organization_id)
+        .ok_or("organization not found")?
+        .currency()?;
+
+    let qs = "select u.email, p.name from watchlist w \
+              join profiles p on p.id = w.profile_id join users u on u.id = p.user_id \
+              where w.item_id=$1 and p.bidder_id=$2";
+    let watchers = conn.query(qs, &[&item.id, &previous]).chain_err(|| "Error selecting watchers")?;
+    let mut sent = 0;
+    for row in watchers.iter() {
+        let email: String = row.get(0);
+        let name: String = row.get(1);
+        let body = format!("Hi {},\n\nYou've been outbid on \"{}\". The high bid is now {}.\n\n\
+                            Bid again here:\n\n{}/#/items/{}",
+                           name, item.title, Money::new(bid.amount, currency), mailer::public_url(), item.id);
+        mailer.send(&email, &format!("You've been outbid on {}", item.title), &body)
+            .chain_err(|| "Error sending outbid notification")?;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+
+/// Email watchers of open items closing within `within` that haven't been
+/// told yet. Each watcher is notified at most once per item; failed sends are
+/// reported and retried on the next run. Returns the number of emails sent.
+pub fn notify_closing(conn: &Connection, mailer: &Mailer, within: chrono::Duration) -> Result<usize> {
+    let cutoff = chrono::UTC::now() + within;
+    let qs = "select w.id, u.email, p.name, i.id, i.title, i.closes_at from watchlist w \
+              join items i on i.id = w.item_id \
+              join profiles p on p.id = w.profile_id join users u on u.id = p.user_id \
+              where w.closing_notified_at is null and i.owning_bidder_id is null and i.deleted_at is null \
+              and i.closes_at > now() and i.closes_at <= $1";
+    let rows = conn.query(qs, &[&cutoff]).chain_err(|| "Error selecting closing items")?;
+    let mut sent = 0;
+    for row in rows.iter() {
+        let watch_id: i32 = row.get(0);
+        let email: String = row.get(1);
+        let name: String = row.get(2);
+        let item_id: i32 = row.get(3);
+        let title: String = row.get(4);
+        let closes_at: chrono::DateTime<chrono::UTC> = row.get(5);
+        let body = format!("Hi {},\n\n\"{}\" on your watchlist closes at {}.\n\n\
+                            Place your final bids here:\n\n{}/#/items/{}",
+                           name, title, closes_at.format("%H:%M UTC"), mailer::public_url(), item_id);
+        if let Err(err) = mailer.send(&email, &format!("Closing soon: {}", title), &body) {
+            println!("Error sending closing notification to {}: {}", email, err);
+            continue;
+        }
+        conn.execute("update watchlist set closing_notified_at=now() where id=$1", &[&watch_id])
+            .chain_err(|| "Error recording closing notification")?;
+        sent += 1;
+    }
+    Ok(sent)
+}
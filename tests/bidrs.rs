@@ -1,5 +1,697 @@
+extern crate bidrs;
+extern crate chrono;
+
+use bidrs::ledger::{self, Account, NewLedgerTransaction, TxKind};
+use bidrs::money::{self, Money, Currency};
+use bidrs::errors::{Error, ErrorKind};
+use bidrs::receipts::{self, OrgDetails, Receipt, ReceiptLine};
+use bidrs::bidding::{self, PaperBid, RowError};
+use bidrs::roles::{Role, Permission, UserRole};
+use bidrs::admin::{OrgInput, ItemInput};
+use bidrs::models::{Bidder, BidderChanges, ItemChanges, BidChanges};
+use bidrs::password_policy;
+use bidrs::login_throttle::{self, Key, Status};
+use bidrs::auth;
+use bidrs::two_factor;
+use bidrs::sessions::{Session, SessionBackend, MemoryBackend};
+use bidrs::tokens::{Claims, Signer};
+use chrono::{Duration, TimeZone, UTC};
+use std::time::Instant;
+
 
 #[test]
 fn working() {
     assert_eq!(0, 0);
 }
+
+
+/// (account, bidder_id, debit, credit) for each entry, in posting order
+fn entry_shape(tx: &NewLedgerTransaction) -> Vec<(Account, Option<i32>, i64, i64)> {
+    tx.entries.iter().map(|e| (e.account, e.bidder_id, e.debit, e.credit)).collect()
+}
+
+#[test]
+fn ledger_transactions_must_balance() {
+    let tx = NewLedgerTransaction::new(1, TxKind::Payment, "card payment")
+        .debit(Account::GatewayClearing, None, 5000)
+        .credit(Account::BidderReceivable, Some(7), 5000);
+    assert!(tx.is_balanced());
+
+    let tx = NewLedgerTransaction::new(1, TxKind::Payment, "card payment")
+        .debit(Account::GatewayClearing, None, 5000)
+        .credit(Account::BidderReceivable, Some(7), 4999);
+    assert!(!tx.is_balanced());
+
+    let tx = NewLedgerTransaction::new(1, TxKind::Payment, "card payment")
+        .debit(Account::GatewayClearing, None, 5000);
+    assert!(!tx.is_balanced());
+
+    assert!(!NewLedgerTransaction::new(1, TxKind::Payment, "nothing").is_balanced());
+}
+
+#[test]
+fn ledger_entries_must_move_a_positive_amount() {
+    let tx = NewLedgerTransaction::new(1, TxKind::Payment, "zero")
+        .debit(Account::GatewayClearing, None, 0)
+        .credit(Account::BidderReceivable, Some(7), 0);
+    assert!(!tx.is_balanced());
+
+    let tx = NewLedgerTransaction::new(1, TxKind::Payment, "negative")
+        .debit(Account::GatewayClearing, None, -100)
+        .credit(Account::BidderReceivable, Some(7), -100);
+    assert!(!tx.is_balanced());
+
+    let tx = NewLedgerTransaction::new(1, TxKind::Payment, "overflow")
+        .debit(Account::GatewayClearing, None, i64::max_value())
+        .debit(Account::GatewayClearing, None, 1)
+        .credit(Account::BidderReceivable, Some(7), i64::max_value())
+        .credit(Account::BidderReceivable, Some(7), 1);
+    assert!(!tx.is_balanced());
+}
+
+#[test]
+fn sales_and_pledges_charge_the_winning_bidder() {
+    let tx = ledger::sale(1, 10, "Quilt", 100, 7, 2500);
+    assert_eq!(tx.kind, TxKind::Sale);
+    assert_eq!(tx.memo, "sale: Quilt");
+    assert_eq!((tx.item_id, tx.bid_id), (Some(10), Some(100)));
+    assert_eq!(entry_shape(&tx), vec![(Account::BidderReceivable, Some(7), 2500, 0),
+                                      (Account::ItemRevenue, None, 0, 2500)]);
+    assert!(tx.is_balanced());
+
+    let tx = ledger::pledge(1, 11, "Playground fund", 101, 7, 10000);
+    assert_eq!(tx.kind, TxKind::Pledge);
+    assert_eq!(tx.memo, "pledge: Playground fund");
+    assert_eq!((tx.item_id, tx.bid_id), (Some(11), Some(101)));
+    assert_eq!(entry_shape(&tx), vec![(Account::BidderReceivable, Some(7), 10000, 0),
+                                      (Account::Donations, None, 0, 10000)]);
+    assert!(tx.is_balanced());
+}
+
+#[test]
+fn payments_and_refunds_move_money_through_the_gateway() {
+    let tx = ledger::payment(1, 7, 2500, "card payment");
+    assert_eq!(tx.kind, TxKind::Payment);
+    assert_eq!((tx.item_id, tx.bid_id), (None, None));
+    assert_eq!(entry_shape(&tx), vec![(Account::GatewayClearing, None, 2500, 0),
+                                      (Account::BidderReceivable, Some(7), 0, 2500)]);
+    assert!(tx.is_balanced());
+
+    let tx = ledger::refund(1, 7, 500, "returned item");
+    assert_eq!(tx.kind, TxKind::Refund);
+    assert_eq!(entry_shape(&tx), vec![(Account::Refunds, Some(7), 500, 0),
+                                      (Account::GatewayClearing, None, 0, 500)]);
+    assert!(tx.is_balanced());
+
+    assert!(!ledger::payment(1, 7, 0, "empty").is_balanced());
+    assert!(!ledger::refund(1, 7, -500, "backwards").is_balanced());
+}
+
+
+fn is_overflow(result: Result<Money, Error>) -> bool {
+    match result {
+        Err(err) => match *err.kind() {
+            ErrorKind::MoneyOverflow => true,
+            _ => false,
+        },
+        Ok(_) => false,
+    }
+}
+
+
+#[test]
+fn currency_codes_are_looked_up() {
+    assert_eq!(Currency::from_code(" usd ").unwrap(), money::USD);
+    assert_eq!(Currency::from_code("JPY").unwrap(), money::JPY);
+    match *Currency::from_code("XYZ").unwrap_err().kind() {
+        ErrorKind::UnknownCurrency(ref code) => assert_eq!(code, "XYZ"),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+
+#[test]
+fn money_parses_user_input() {
+    let parse = |s: &str, c: Currency| Money::parse(s, c).map(|m| m.amount).ok();
+    assert_eq!(parse("$1,250.00", money::USD), Some(125000));
+    assert_eq!(parse("1250", money::USD), Some(125000));
+    assert_eq!(parse("-3.5", money::USD), Some(-350));
+    assert_eq!(parse("-$3.50", money::USD), Some(-350));
+    assert_eq!(parse(".05", money::USD), Some(5));
+    assert_eq!(parse("10 USD", money::USD), Some(1000));
+    assert_eq!(parse("¥1,000", money::JPY), Some(1000));
+    assert_eq!(parse("1,234,567.89", money::USD), Some(123456789));
+    assert_eq!(parse("1,2,3", money::USD), None);
+    assert_eq!(parse("12,34", money::USD), None);
+    assert_eq!(parse("1234,567", money::USD), None);
+    assert_eq!(parse(",123", money::USD), None);
+    assert_eq!(parse("1,234,", money::USD), None);
+    assert_eq!(parse("1.2,3", money::USD), None);
+    assert_eq!(parse("1.234", money::USD), None);
+    assert_eq!(parse("1.5", money::JPY), None);
+    assert_eq!(parse("", money::USD), None);
+    assert_eq!(parse(".", money::USD), None);
+    assert_eq!(parse("12abc", money::USD), None);
+    assert_eq!(parse("€5", money::USD), None);
+    assert_eq!(parse("92233720368547758.07", money::USD), Some(i64::max_value()));
+    assert!(is_overflow(Money::parse("92233720368547758.08", money::USD)));
+}
+
+
+#[test]
+fn money_formats_with_symbol_grouping_and_precision() {
+    assert_eq!(Money::new(125000, money::USD).to_string(), "$1,250.00");
+    assert_eq!(Money::new(-350, money::USD).to_string(), "-$3.50");
+    assert_eq!(Money::new(5, money::USD).to_string(), "$0.05");
+    assert_eq!(Money::new(0, money::EUR).to_string(), "€0.00");
+    assert_eq!(Money::new(1000, money::JPY).to_string(), "¥1,000");
+    assert_eq!(Money::new(123456789, money::GBP).to_string(), "£1,234,567.89");
+    assert_eq!(Money::new(i64::min_value(), money::USD).to_string(), "-$92,233,720,368,547,758.08");
+}
+
+
+#[test]
+fn money_arithmetic_is_checked() {
+    let max = Money::new(i64::max_value(), money::USD);
+    let min = Money::new(i64::min_value(), money::USD);
+    let one = Money::new(1, money::USD);
+    assert!(is_overflow(max.checked_add(&one)));
+    assert!(is_overflow(min.checked_sub(&one)));
+    assert!(is_overflow(max.checked_mul(2)));
+    assert!(is_overflow(Money::sum(money::USD, vec![max, one].iter())));
+    assert_eq!(max.checked_sub(&one).unwrap().amount, i64::max_value() - 1);
+    assert_eq!(Money::sum(money::USD, vec![one, one, one].iter()).unwrap().amount, 3);
+
+    match *one.checked_add(&Money::new(1, money::CAD)).unwrap_err().kind() {
+        ErrorKind::CurrencyMismatch(ref a, ref b) => assert_eq!((a.as_str(), b.as_str()), ("USD", "CAD")),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+}
+
+
+#[test]
+fn payments_apply_to_amounts_oldest_first() {
+    // partial payment
+    assert_eq!(receipts::applied(2000, 0, 3000), 2000);
+    // overpayment stops at what's owed
+    assert_eq!(receipts::applied(5000, 0, 3000), 3000);
+    // underpayment that doesn't reach a later amount
+    assert_eq!(receipts::applied(2000, 3000, 1000), 0);
+    assert_eq!(receipts::applied(3500, 3000, 1000), 500);
+    // refunds can leave the net negative
+    assert_eq!(receipts::applied(-500, 0, 1000), 0);
+}
+
+
+#[test]
+fn receipts_count_what_was_paid_during_the_year() {
+    // a 5000 win worth 3000, paid in full this year
+    assert_eq!(receipts::paid_in_year(0, 5000, 0, 5000, 3000), Some((5000, 2000)));
+    // paid in part, only what's over the fair value is deductible
+    assert_eq!(receipts::paid_in_year(0, 4000, 0, 5000, 3000), Some((4000, 1000)));
+    assert_eq!(receipts::paid_in_year(0, 2000, 0, 5000, 3000), Some((2000, 0)));
+    // finished paying this year after 2000 last year
+    assert_eq!(receipts::paid_in_year(2000, 5000, 0, 5000, 3000), Some((3000, 2000)));
+    // paid in full last year
+    assert_eq!(receipts::paid_in_year(5000, 5000, 0, 5000, 3000), None);
+    // paid last year and partly refunded this year: the refund doesn't
+    // show up on this year's receipt
+    assert_eq!(receipts::paid_in_year(5000, 3000, 0, 5000, 3000), None);
+    // pledges are fully deductible
+    assert_eq!(receipts::paid_in_year(0, 1000, 5000, 2500, 0), None);
+    assert_eq!(receipts::paid_in_year(0, 6000, 5000, 2500, 0), Some((1000, 1000)));
+}
+
+
+fn sample_receipt() -> Receipt {
+    let usd = |amount| Money::new(amount, money::USD);
+    Receipt {
+        organization: OrgDetails {
+            name: "Smith & Sons <PTA>".into(),
+            address: Some("1 Main St".into()),
+            tax_id: Some("12-3456789".into()),
+            email: None,
+            phone: None,
+            note: Some("No goods or services were provided for \"pledges\".".into()),
+        },
+        bidder_id: 7,
+        bidder_name: "Ann O'Neil".into(),
+        year: 2016,
+        lines: vec![ReceiptLine {
+            kind: "sale".into(),
+            description: "Quilt".into(),
+            date: UTC.ymd(2016, 5, 14).and_hms(19, 30, 0),
+            paid: usd(125000),
+            fair_market_value: usd(40000),
+            deductible: usd(85000),
+        }],
+        total_paid: usd(125000),
+        total_deductible: usd(85000),
+    }
+}
+
+
+#[test]
+fn receipts_render_as_text() {
+    let text = sample_receipt().to_text();
+    assert!(text.starts_with("Smith & Sons <PTA>\n1 Main St\nTax ID: 12-3456789\n"));
+    assert!(text.contains("2016 Donation Receipt"));
+    assert!(text.contains("Donor: Ann O'Neil (bidder #7)"));
+    assert!(text.contains("2016-05-14"));
+    assert!(text.contains("$1,250.00"));
+    assert!(text.contains("$850.00"));
+    assert!(text.trim_right().ends_with("No goods or services were provided for \"pledges\"."));
+}
+
+
+#[test]
+fn receipts_render_as_escaped_html() {
+    let html = sample_receipt().to_html();
+    assert!(html.contains("<h1>Smith &amp; Sons &lt;PTA&gt;</h1>"));
+    assert!(html.contains("Donor: Ann O&#39;Neil (bidder #7)"));
+    assert!(html.contains("<td class=\"amt\">$1,250.00</td>"));
+    assert!(html.contains("for &quot;pledges&quot;."));
+    assert!(!html.contains("<PTA>"));
+
+    assert_eq!(receipts::escape_html("a < b && \"c\" > 'd'"),
+               "a &lt; b &amp;&amp; &quot;c&quot; &gt; &#39;d&#39;");
+}
+
+
+fn paper_bid(row: usize, amount: i64, minute: u32) -> (PaperBid, ()) {
+    let written_at = UTC.ymd(2017, 4, 8).and_hms(19, minute, 0);
+    (PaperBid { row: row, paddle: 100 + row as i32, item_id: 5, amount: amount, written_at: written_at }, ())
+}
+
+#[test]
+fn paper_bids_are_sorted_by_written_time() {
+    let mut rows = vec![paper_bid(1, 3000, 20), paper_bid(2, 1000, 5), paper_bid(3, 2000, 10)];
+    assert!(bidding::check_order(5, &mut rows).is_empty());
+    assert_eq!(rows.iter().map(|&(ref row, _)| row.row).collect::<Vec<_>>(), vec![2, 3, 1]);
+}
+
+#[test]
+fn paper_bids_must_increase_over_time() {
+    let mut rows = vec![paper_bid(1, 1000, 5), paper_bid(2, 1000, 10), paper_bid(3, 900, 15), paper_bid(4, 1500, 20)];
+    assert_eq!(bidding::check_order(5, &mut rows), vec![
+        RowError::new(2, "amount must be greater than row 1 written earlier on item 5"),
+        RowError::new(3, "amount must be greater than row 2 written earlier on item 5"),
+    ]);
+}
+
+#[test]
+fn paper_bids_cant_be_written_at_the_same_time() {
+    let mut rows = vec![paper_bid(2, 1200, 5), paper_bid(1, 1000, 5)];
+    assert_eq!(bidding::check_order(5, &mut rows),
+               vec![RowError::new(2, "written at the same time as row 1 on item 5")]);
+
+    let mut rows: Vec<(PaperBid, ())> = vec![];
+    assert!(bidding::check_order(5, &mut rows).is_empty());
+}
+
+
+#[test]
+fn roles_carry_fixed_permissions() {
+    assert_eq!(Role::SuperAdmin.permissions(), Permission::all());
+    assert!(Role::OrgAdmin.permissions().contains(&Permission::ManageOrganization));
+    assert!(!Role::OrgAdmin.permissions().contains(&Permission::ManageOrganizations));
+    assert_eq!(Role::Volunteer.permissions(), vec![Permission::CheckIn, Permission::EnterBids]);
+    assert_eq!(Role::Bidder.permissions(), vec![Permission::PlaceBids]);
+
+    for role in Role::all() {
+        assert_eq!(Role::from_str(role.as_str()), Some(role));
+    }
+    assert_eq!(Role::from_str("admin"), None);
+}
+
+
+fn grant(role: &str, org_id: Option<i32>) -> UserRole {
+    let now = UTC::now();
+    UserRole { id: 1, user_id: 2, role: role.into(), organization_id: org_id, date_created: now, date_modified: now }
+}
+
+#[test]
+fn org_grants_apply_only_within_their_organization() {
+    let global = grant("volunteer", None);
+    assert!(global.applies_to(None));
+    assert!(global.applies_to(Some(3)));
+
+    let scoped = grant("volunteer", Some(3));
+    assert!(scoped.applies_to(Some(3)));
+    assert!(!scoped.applies_to(Some(4)));
+    assert!(!scoped.applies_to(None));
+}
+
+#[test]
+fn grants_give_their_roles_permissions() {
+    let volunteer = grant("volunteer", Some(3));
+    assert!(volunteer.grants(Permission::CheckIn));
+    assert!(!volunteer.grants(Permission::ManageOrganization));
+    assert!(grant("super_admin", None).grants(Permission::ManageOrganizations));
+    // grants for roles this version doesn't know give nothing
+    assert!(!grant("treasurer", Some(3)).grants(Permission::CheckIn));
+}
+
+
+/// Fields named by an `InvalidFields` error
+fn invalid_fields<T>(result: Result<T, Error>) -> Vec<String> {
+    match result {
+        Err(err) => match *err.kind() {
+            ErrorKind::InvalidFields(ref fields) => fields.iter().map(|f| f.field.clone()).collect(),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        },
+        Ok(_) => panic!("expected invalid fields"),
+    }
+}
+
+
+#[test]
+fn admin_input_reads_optional_fields() {
+    let input = OrgInput::from_json(r#"{"name": "PTA", "extra": {"currency": "CAD"}, "preauth_amount": null}"#).unwrap();
+    assert_eq!(input.name, Some("PTA".to_string()));
+    assert!(input.extra.unwrap().find("currency").is_some());
+    assert_eq!(input.preauth_amount, None);
+    assert_eq!(input.bidder_spending_limit, None);
+
+    let input = ItemInput::from_json(r#"{"organization_id": 3, "is_goal": true, "unknown": 1}"#).unwrap();
+    assert_eq!(input.organization_id, Some(3));
+    assert_eq!(input.is_goal, Some(true));
+    assert_eq!(input.title, None);
+}
+
+
+#[test]
+fn admin_input_reports_malformed_bodies_and_mistyped_fields() {
+    assert_eq!(invalid_fields(OrgInput::from_json("{")), vec!["body"]);
+    assert_eq!(invalid_fields(OrgInput::from_json("[]")), vec!["body"]);
+    assert_eq!(invalid_fields(OrgInput::from_json(r#"{"name": 5, "preauth_amount": 10}"#)),
+               vec!["name", "preauth_amount"]);
+    assert_eq!(invalid_fields(ItemInput::from_json(r#"{"organization_id": "3", "is_goal": 1}"#)),
+               vec!["organization_id", "is_goal"]);
+    assert_eq!(invalid_fields(ItemInput::from_json(r#"{"organization_id": 1.5}"#)), vec!["organization_id"]);
+    assert_eq!(invalid_fields(ItemInput::from_json(r#"{"organization_id": 4294967296}"#)), vec!["organization_id"]);
+}
+
+
+#[test]
+fn item_changes_to_bidding_rules_are_destructive() {
+    assert!(!ItemChanges::default().is_destructive());
+    assert!(!ItemChanges { title: Some("Quilt".to_string()), value: Some(5000), closes_at: Some(None),
+                           ..ItemChanges::default() }.is_destructive());
+    assert!(ItemChanges { organization_id: Some(2), ..ItemChanges::default() }.is_destructive());
+    assert!(ItemChanges { is_goal: Some(true), ..ItemChanges::default() }.is_destructive());
+    assert!(ItemChanges { starting: Some(100), ..ItemChanges::default() }.is_destructive());
+    assert!(ItemChanges { min_bid: Some(100), ..ItemChanges::default() }.is_destructive());
+}
+
+
+#[test]
+fn bid_changes_to_bidder_or_amount_are_destructive() {
+    assert!(!BidChanges::default().is_destructive());
+    assert!(!BidChanges { written_at: Some(Some(UTC.ymd(2017, 3, 1).and_hms(19, 0, 0))),
+                          ..BidChanges::default() }.is_destructive());
+    assert!(BidChanges { bidder_id: Some(4), ..BidChanges::default() }.is_destructive());
+    assert!(BidChanges { amount: Some(2500), ..BidChanges::default() }.is_destructive());
+}
+
+
+#[test]
+fn bidder_changes_moving_organization() {
+    let bidder = Bidder {
+        id: 7, organization_id: 1, id_name: "Smith".to_string(),
+        date_created: UTC::now(), date_modified: UTC::now(),
+        spending_limit: None, paddle: Some(12), terms_accepted_at: None,
+    };
+    assert!(!BidderChanges::default().moves_organization(&bidder));
+    assert!(!BidderChanges { organization_id: Some(1), ..BidderChanges::default() }.moves_organization(&bidder));
+    assert!(!BidderChanges { paddle: Some(None), ..BidderChanges::default() }.moves_organization(&bidder));
+    assert!(BidderChanges { organization_id: Some(2), ..BidderChanges::default() }.moves_organization(&bidder));
+}
+
+
+#[test]
+fn model_conflicts_have_stable_codes() {
+    assert_eq!(ErrorKind::ItemHasBids(3).code(), "item_has_bids");
+    assert_eq!(ErrorKind::BidderHasBids(7).code(), "bidder_has_bids");
+    assert_eq!(ErrorKind::BidPosted(9).code(), "bid_posted");
+    assert_eq!(ErrorKind::BidderHasBids(7).to_string(), "bidder 7 has already placed bids");
+}
+
+
+#[test]
+fn password_policy_checks_length_and_denylist() {
+    assert!(password_policy::check("short").is_some());
+    assert!(password_policy::check(&"a".repeat(password_policy::MAX_BYTES + 1)).is_some());
+    // length counts characters, not bytes
+    assert!(password_policy::check("ééééééé").is_some());
+
+    assert!(password_policy::check("password1").is_some());
+    assert!(password_policy::check("PassWord1").is_some());
+    assert!(password_policy::check("QWERTYUIOP").is_some());
+
+    assert_eq!(password_policy::check("correct horse battery staple"), None);
+    assert_eq!(password_policy::check(&"a".repeat(password_policy::MAX_BYTES)), None);
+}
+
+
+#[test]
+fn login_throttle_backs_off_then_locks_out() {
+    let now = UTC::now();
+    let email = |failures| Status::new(Key::Email("a@b.c".into()), failures, Some(now));
+
+    assert_eq!(email(2).retry_at, None);
+    assert_eq!(email(3).retry_at, Some(now + Duration::seconds(2)));
+    assert_eq!(email(4).retry_at, Some(now + Duration::seconds(4)));
+    assert_eq!(email(9).retry_at, Some(now + Duration::seconds(128)));
+    assert!(!email(9).locked);
+    assert!(email(9).retry_after().unwrap() > 0);
+
+    let locked = email(10);
+    assert!(locked.locked);
+    assert_eq!(locked.retry_at, Some(now + Duration::minutes(login_throttle::LOCKOUT_MINUTES)));
+
+    // ip addresses get more room, and delays are capped short of the lockout
+    let ip = |failures| Status::new(Key::Ip("10.0.0.1".into()), failures, Some(now));
+    assert_eq!(ip(9).retry_at, None);
+    assert_eq!(ip(10).retry_at, Some(now + Duration::seconds(2)));
+    assert_eq!(ip(49).retry_at, Some(now + Duration::minutes(15)));
+    assert!(!ip(49).locked);
+    assert!(ip(50).locked);
+
+    // waits run from the last failure
+    assert_eq!(Status::new(Key::Email("a@b.c".into()), 0, None).retry_at, None);
+    let old = Status::new(Key::Email("a@b.c".into()), 3, Some(now - Duration::minutes(1)));
+    assert!(old.retry_at.is_some());
+    assert_eq!(old.retry_after(), None);
+}
+
+
+#[test]
+fn const_eq_compares_whole_slices() {
+    assert!(auth::const_eq(b"", b""));
+    assert!(auth::const_eq(b"abc", b"abc"));
+    assert!(!auth::const_eq(b"abc", b"abd"));
+    assert!(!auth::const_eq(b"abc", b"abcd"));
+    assert!(!auth::const_eq(b"abc\0", b"abc"));
+    assert!(!auth::const_eq(b"", b"a"));
+}
+
+
+fn seconds(d: ::std::time::Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+/// Median wall time of a few runs of `f`
+fn median_time<F: FnMut()>(mut f: F) -> f64 {
+    let mut times = (0..3).map(|_| {
+        let start = Instant::now();
+        f();
+        seconds(start.elapsed())
+    }).collect::<Vec<_>>();
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    times[times.len() / 2]
+}
+
+#[test]
+fn dummy_hash_uses_current_params() {
+    assert_eq!(auth::dummy_hash().params, auth::CURRENT_PARAMS);
+}
+
+
+/// A legacy bcrypt row, as converted by the phc-hashes migration
+fn legacy_bcrypt_hash(password: &str) -> String {
+    let params = auth::Params::Bcrypt { cost: 4 };
+    let salt = vec![7u8; 16];
+    let hash = params.derive(password, &salt).unwrap();
+    auth::PasswordHash { params: params, salt: salt, hash: hash }.encode()
+}
+
+#[test]
+fn check_login_accepts_legacy_bcrypt_hashes() {
+    let stored = legacy_bcrypt_hash("bidrs-enter");
+    let checked = auth::check_login(Some(stored.as_str()), "bidrs-enter").expect("legacy hash should verify");
+    assert!(checked.needs_rehash());
+    assert!(auth::check_login(Some(stored.as_str()), "bidrs-exit").is_none());
+}
+
+#[test]
+fn check_login_refuses_missing_and_unparseable_hashes() {
+    assert!(auth::check_login(None, "bidrs-enter").is_none());
+    assert!(auth::check_login(Some("not-a-hash"), "bidrs-enter").is_none());
+    assert!(auth::check_login(Some("$md5$x=1$AAAA$AAAA"), "bidrs-enter").is_none());
+}
+
+
+/// Wall-clock comparison through `check_login`, with a loose bound since
+/// test machines are noisy; it catches a skipped hash, not small leaks.
+#[test]
+fn unknown_email_login_takes_as_long_as_wrong_password() {
+    let stored = auth::hash_password("correct horse battery staple").unwrap();
+    assert!(auth::check_login(Some(stored.as_str()), "correct horse battery staple").is_some());
+
+    let known = median_time(|| assert!(auth::check_login(Some(stored.as_str()), "wrong password").is_none()));
+    let unknown = median_time(|| assert!(auth::check_login(None, "wrong password").is_none()));
+    let ratio = unknown / known;
+    assert!(ratio > 0.33 && ratio < 3.0,
+            "unknown email took {:.3}s against {:.3}s for a wrong password", unknown, known);
+}
+
+
+#[test]
+fn totp_matches_rfc6238_vectors() {
+    // RFC 6238 appendix B, SHA1, keeping the last six of its eight digits
+    let secret = b"12345678901234567890";
+    let vectors = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+    for &(time, code) in vectors.iter() {
+        assert_eq!(two_factor::totp(secret, time / two_factor::STEP_SECS), code, "at time {}", time);
+    }
+}
+
+
+#[test]
+fn base32_matches_rfc4648_vectors() {
+    // RFC 4648 section 10, without padding
+    assert_eq!(two_factor::base32(b""), "");
+    assert_eq!(two_factor::base32(b"f"), "MY");
+    assert_eq!(two_factor::base32(b"fo"), "MZXQ");
+    assert_eq!(two_factor::base32(b"foo"), "MZXW6");
+    assert_eq!(two_factor::base32(b"foob"), "MZXW6YQ");
+    assert_eq!(two_factor::base32(b"fooba"), "MZXW6YTB");
+    assert_eq!(two_factor::base32(b"foobar"), "MZXW6YTBOI");
+}
+
+
+fn session_for(user_id: i32, idle: Duration) -> Session {
+    let mut sess = Session::new().with_user(user_id);
+    sess.stamp = sess.stamp - idle;
+    sess
+}
+
+#[test]
+fn memory_sessions_expire_once_unused_for_their_life() {
+    let life = Duration::minutes(20);
+    let backend = MemoryBackend::new();
+    let fresh = session_for(1, Duration::minutes(5));
+    let stale = session_for(1, Duration::minutes(25));
+    backend.insert(fresh.clone(), life).unwrap();
+    backend.insert(stale.clone(), life).unwrap();
+
+    assert_eq!(backend.get(&fresh.token, life).unwrap().unwrap().user_id, Some(1));
+    assert!(backend.get(&stale.token, life).unwrap().is_none());
+    assert!(backend.get("unknown", life).unwrap().is_none());
+    assert_eq!(backend.len().unwrap(), 2);
+
+    assert_eq!(backend.remove_expired(life).unwrap(), 1);
+    assert_eq!(backend.len().unwrap(), 1);
+}
+
+#[test]
+fn memory_sessions_touch_renews_or_drops_them() {
+    let life = Duration::minutes(20);
+    let backend = MemoryBackend::new();
+    let sess = session_for(1, Duration::minutes(15));
+    let stale = session_for(1, Duration::minutes(25));
+    backend.insert(sess.clone(), life).unwrap();
+    backend.insert(stale.clone(), life).unwrap();
+
+    let touched = backend.touch(&sess.token, life).unwrap().unwrap();
+    assert!(touched.stamp > sess.stamp);
+    assert!(backend.get(&sess.token, Duration::minutes(10)).unwrap().is_some());
+
+    // touching an expired session removes it rather than reviving it
+    assert!(backend.touch(&stale.token, life).unwrap().is_none());
+    assert_eq!(backend.len().unwrap(), 1);
+    assert!(backend.touch("unknown", life).unwrap().is_none());
+}
+
+#[test]
+fn memory_sessions_are_removed_by_user_keeping_one() {
+    let life = Duration::minutes(20);
+    let backend = MemoryBackend::new();
+    let (a, b, c) = (session_for(1, Duration::zero()), session_for(1, Duration::zero()), session_for(1, Duration::zero()));
+    let other = session_for(2, Duration::zero());
+    for sess in [&a, &b, &c, &other].iter() {
+        backend.insert((*sess).clone(), life).unwrap();
+    }
+
+    assert_eq!(backend.remove_by_user(1, Some(&a.token)).unwrap(), 2);
+    assert!(backend.get(&a.token, life).unwrap().is_some());
+    assert!(backend.get(&b.token, life).unwrap().is_none());
+    assert!(backend.get(&other.token, life).unwrap().is_some());
+
+    assert_eq!(backend.remove_by_user(1, None).unwrap(), 1);
+    assert!(backend.remove(&other.token).unwrap());
+    assert!(!backend.remove(&other.token).unwrap());
+    assert_eq!(backend.len().unwrap(), 0);
+}
+
+
+fn claims(user_id: i32, expires_in: Duration) -> Claims {
+    let now = UTC::now();
+    Claims {
+        sub: user_id,
+        sid: 40,
+        roles: vec!["bidder".into()],
+        iat: now.timestamp(),
+        exp: (now + expires_in).timestamp(),
+    }
+}
+
+#[test]
+fn signer_needs_a_long_enough_secret() {
+    assert!(Signer::new(b"too short").is_err());
+    assert!(Signer::new(&[7u8; 32]).is_ok());
+}
+
+#[test]
+fn signed_tokens_verify_with_the_same_secret() {
+    let signer = Signer::new(&[7u8; 32]).unwrap();
+    let token = signer.sign(&claims(12, Duration::minutes(15))).unwrap();
+
+    let verified = signer.verify(&token).unwrap();
+    assert_eq!((verified.sub, verified.sid), (12, 40));
+    assert_eq!(verified.roles, vec!["bidder".to_string()]);
+
+    let other = Signer::new(&[8u8; 32]).unwrap();
+    assert!(other.verify(&token).is_err());
+    assert!(other.decode(&token).is_err());
+
+    let mut tampered = token.clone();
+    tampered.push('x');
+    assert!(signer.verify(&tampered).is_err());
+}
+
+#[test]
+fn expired_tokens_decode_but_dont_verify() {
+    let signer = Signer::new(&[7u8; 32]).unwrap();
+    let token = signer.sign(&claims(12, Duration::minutes(-1))).unwrap();
+    assert!(signer.verify(&token).is_err());
+    assert_eq!(signer.decode(&token).unwrap().sid, 40);
+}